@@ -0,0 +1 @@
+ALTER TABLE collections_mods ADD COLUMN ordinal integer NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+CREATE TABLE collections_history (
+    id bigserial PRIMARY KEY,
+    collection_id bigint REFERENCES collections NOT NULL,
+    actor_id bigint REFERENCES users NOT NULL,
+    old_status varchar(64) NOT NULL,
+    new_status varchar(64) NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX collections_history_collection_id ON collections_history (collection_id);
@@ -0,0 +1,8 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Substring title searches
+CREATE INDEX collections_title_trgm ON collections USING gin (title gin_trgm_ops);
+
+-- Browsing by status, newest first
+CREATE INDEX collections_status_created ON collections (status, created DESC);
+CREATE INDEX collections_user_id ON collections (user_id);
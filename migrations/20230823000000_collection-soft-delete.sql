@@ -0,0 +1,3 @@
+ALTER TABLE collections ADD COLUMN deleted_at timestamptz NULL;
+
+CREATE INDEX collections_deleted_at ON collections (deleted_at) WHERE deleted_at IS NOT NULL;
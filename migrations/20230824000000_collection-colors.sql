@@ -0,0 +1 @@
+ALTER TABLE collections ADD COLUMN colors integer[] NOT NULL DEFAULT '{}';
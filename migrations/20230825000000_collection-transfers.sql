@@ -0,0 +1,6 @@
+-- Allow the collection history to record changes other than status changes
+ALTER TABLE collections_history ADD COLUMN action varchar(64) NOT NULL DEFAULT 'status_change';
+ALTER TABLE collections_history ALTER COLUMN old_status DROP NOT NULL;
+ALTER TABLE collections_history ALTER COLUMN new_status DROP NOT NULL;
+ALTER TABLE collections_history ADD COLUMN old_owner_id bigint NULL REFERENCES users;
+ALTER TABLE collections_history ADD COLUMN new_owner_id bigint NULL REFERENCES users;
@@ -0,0 +1,3 @@
+ALTER TABLE collections ADD COLUMN body text NOT NULL DEFAULT '';
+-- The body is rendered and sanitized when it is written, so reads can serve it directly
+ALTER TABLE collections ADD COLUMN body_html text NOT NULL DEFAULT '';
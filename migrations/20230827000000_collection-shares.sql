@@ -0,0 +1,8 @@
+CREATE TABLE collection_shares (
+    collection_id bigint REFERENCES collections NOT NULL,
+    user_id bigint REFERENCES users NOT NULL,
+    created timestamp with time zone NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (collection_id, user_id)
+);
+
+CREATE INDEX collection_shares_user_id ON collection_shares (user_id);
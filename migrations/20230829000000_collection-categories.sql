@@ -0,0 +1,22 @@
+CREATE TABLE collection_tags (
+    id serial PRIMARY KEY,
+    name varchar(64) UNIQUE NOT NULL
+);
+
+INSERT INTO collection_tags (name) VALUES
+    ('adventure'),
+    ('building'),
+    ('magic'),
+    ('modpack-base'),
+    ('performance'),
+    ('quality-of-life'),
+    ('technology'),
+    ('utility');
+
+CREATE TABLE collection_categories (
+    collection_id bigint REFERENCES collections NOT NULL,
+    category_id int REFERENCES collection_tags NOT NULL,
+    PRIMARY KEY (collection_id, category_id)
+);
+
+CREATE INDEX collection_categories_category_id ON collection_categories (category_id);
@@ -0,0 +1 @@
+ALTER TABLE collections ADD COLUMN featured_project bigint NULL REFERENCES mods ON DELETE SET NULL;
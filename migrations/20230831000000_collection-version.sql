@@ -0,0 +1 @@
+ALTER TABLE collections ADD COLUMN version integer NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+CREATE TABLE collection_views (
+    collection_id bigint PRIMARY KEY REFERENCES collections,
+    views bigint NOT NULL DEFAULT 0
+);
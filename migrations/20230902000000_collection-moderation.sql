@@ -0,0 +1 @@
+ALTER TABLE collections_history ADD COLUMN reason varchar(2048) NULL;
@@ -0,0 +1,8 @@
+CREATE TABLE collections_children (
+    parent_id bigint REFERENCES collections NOT NULL,
+    child_id bigint REFERENCES collections NOT NULL,
+    ordinal integer NOT NULL DEFAULT 0,
+    PRIMARY KEY (parent_id, child_id)
+);
+
+CREATE INDEX collections_children_child_id ON collections_children (child_id);
@@ -0,0 +1,3 @@
+CREATE INDEX collections_created ON collections (created DESC, id DESC);
+CREATE INDEX collections_updated ON collections (updated DESC, id DESC);
+CREATE INDEX collections_follows ON collections (follows DESC, id DESC);
@@ -0,0 +1 @@
+ALTER TABLE collections ADD COLUMN icon_filename varchar(255) NULL;
@@ -0,0 +1,9 @@
+CREATE TABLE collection_pins (
+    user_id bigint REFERENCES users NOT NULL,
+    collection_id bigint REFERENCES collections NOT NULL,
+    ordinal integer NOT NULL DEFAULT 0,
+    created timestamp with time zone NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, collection_id)
+);
+
+CREATE INDEX collection_pins_collection_id ON collection_pins (collection_id);
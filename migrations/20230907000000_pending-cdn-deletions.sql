@@ -0,0 +1,7 @@
+CREATE TABLE pending_cdn_deletions (
+    id bigserial PRIMARY KEY,
+    file_id varchar(1024) NOT NULL,
+    file_name varchar(1024) NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    created timestamp with time zone NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
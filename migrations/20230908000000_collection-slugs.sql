@@ -0,0 +1,3 @@
+ALTER TABLE collections ADD COLUMN slug varchar(64) NULL;
+
+CREATE UNIQUE INDEX collections_slug ON collections (LOWER(slug));
@@ -0,0 +1,2 @@
+-- Existing shares only let users view the collection
+ALTER TABLE collection_shares ADD COLUMN permissions bigint NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+CREATE TABLE collection_outbox (
+    id bigserial PRIMARY KEY,
+    -- Events are only recorded once per key, so retried writes don't deliver them twice
+    dedup_key varchar(255) NOT NULL UNIQUE,
+    payload jsonb NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt timestamp with time zone NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created timestamp with time zone NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Set once the event is delivered or given up on
+    processed timestamp with time zone NULL
+);
+
+CREATE INDEX collection_outbox_pending ON collection_outbox (next_attempt) WHERE processed IS NULL;
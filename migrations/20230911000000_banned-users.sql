@@ -0,0 +1,4 @@
+-- Banned accounts can't be given collections, so bans are tracked again by GitHub id
+CREATE TABLE banned_users (
+    github_id bigint NOT NULL PRIMARY KEY
+);
@@ -3,6 +3,7 @@ use crate::database::models::project_item::QueryProject;
 use crate::database::models::version_item::QueryVersion;
 use crate::database::models::Collection;
 use crate::database::{models, Project, Version};
+use crate::models::collections::CollectionPermissions;
 use crate::models::users::User;
 use crate::routes::ApiError;
 use actix_web::web;
@@ -201,7 +202,11 @@ pub async fn is_authorized_collection(
     let mut authorized = !collection_data.status.is_hidden();
 
     if let Some(user) = &user_option {
-        if !authorized && (user.role.is_mod() || user.id == collection_data.user_id.into()) {
+        if !authorized
+            && (user.role.is_mod()
+                || user.id == collection_data.user_id.into()
+                || collection_data.shared_with.contains(&user.id.into()))
+        {
             authorized = true;
         }
     }
@@ -209,60 +214,380 @@ pub async fn is_authorized_collection(
     Ok(authorized)
 }
 
+/// Whether the user may change the contents of a collection, which is limited to its owner
+/// and moderators regardless of the collection's visibility.
+pub fn is_authorized_collection_edit(collection_data: &Collection, user: &User) -> bool {
+    user.role.is_mod() || user.id == collection_data.user_id.into()
+}
+
+/// The permissions the user has to change a collection. Its owner and moderators have all of
+/// them, and users it is shared with have the ones they were granted.
+pub async fn collection_edit_permissions(
+    collection_data: &Collection,
+    user: &User,
+    pool: &PgPool,
+) -> Result<CollectionPermissions, ApiError> {
+    if is_authorized_collection_edit(collection_data, user) {
+        return Ok(CollectionPermissions::ALL);
+    }
+
+    if !collection_data.shared_with.contains(&user.id.into()) {
+        return Ok(CollectionPermissions::empty());
+    }
+
+    Ok(
+        Collection::get_share_permissions(collection_data.id, user.id.into(), pool)
+            .await?
+            .unwrap_or_else(CollectionPermissions::empty),
+    )
+}
+
+/// Filters collections to those the user can see, keeping them in the order they were given
 pub async fn filter_authorized_collections(
     collections: Vec<Collection>,
     user_option: &Option<User>,
     pool: &web::Data<PgPool>,
 ) -> Result<Vec<crate::models::collections::Collection>, ApiError> {
-    let mut return_collections = Vec::new();
-    let mut check_collections = Vec::new();
-
-    for collection in collections {
-        if !collection.status.is_hidden()
+    let is_visible = |collection: &Collection| {
+        !collection.status.is_hidden()
             || user_option
                 .as_ref()
-                .map(|x| x.role.is_mod())
+                .map(|x| x.role.is_mod() || collection.shared_with.contains(&x.id.into()))
                 .unwrap_or(false)
-        {
-            return_collections.push(collection.into());
-        } else if user_option.is_some() {
-            check_collections.push(collection);
-        }
-    }
+    };
+
+    // Hidden collections are only visible to their owner, which is checked in one query
+    let check_ids = collections
+        .iter()
+        .filter(|x| !is_visible(x))
+        .map(|x| x.id.0)
+        .collect::<Vec<_>>();
 
-    if !check_collections.is_empty() {
+    let mut owned_ids = Vec::new();
+    if !check_ids.is_empty() {
         if let Some(user) = user_option {
             let user_id: models::ids::UserId = user.id.into();
 
-            use futures::TryStreamExt;
-
-            sqlx::query!(
+            owned_ids = sqlx::query!(
                 "
-                SELECT c.id id, c.user_id user_id FROM collections c
+                SELECT c.id id FROM collections c
                 WHERE c.user_id = $2 AND c.id = ANY($1)
                 ",
-                &check_collections.iter().map(|x| x.id.0).collect::<Vec<_>>(),
+                &check_ids,
                 user_id as database::models::ids::UserId,
             )
-            .fetch_many(&***pool)
-            .try_for_each(|e| {
-                if let Some(row) = e.right() {
-                    check_collections.retain(|x| {
-                        let bool = x.id.0 == row.id && x.user_id.0 == row.user_id;
+            .fetch_all(&***pool)
+            .await?
+            .into_iter()
+            .map(|x| x.id)
+            .collect();
+        }
+    }
 
-                        if bool {
-                            return_collections.push(x.clone().into());
-                        }
+    let mut return_collections = collections
+        .into_iter()
+        .filter(|x| is_visible(x) || owned_ids.contains(&x.id.0))
+        .map(crate::models::collections::Collection::from)
+        .collect::<Vec<_>>();
 
-                        !bool
-                    });
+    filter_authorized_child_collections(&mut return_collections, user_option, pool).await?;
+
+    Ok(return_collections)
+}
+
+/// Removes the child collections the user can't see from the given collections, with the same
+/// rules as `is_authorized_collection`. Children of children are filtered when they are read.
+pub async fn filter_authorized_child_collections(
+    collections: &mut [crate::models::collections::Collection],
+    user_option: &Option<User>,
+    pool: &web::Data<PgPool>,
+) -> Result<(), ApiError> {
+    let mut child_ids = collections
+        .iter()
+        .flat_map(|x| x.child_collections.iter().map(|y| y.0 as i64))
+        .collect::<Vec<_>>();
+    child_ids.sort_unstable();
+    child_ids.dedup();
+
+    if child_ids.is_empty() {
+        return Ok(());
+    }
+
+    let is_mod = user_option
+        .as_ref()
+        .map(|x| x.role.is_mod())
+        .unwrap_or(false);
+    let user_id = user_option
+        .as_ref()
+        .map(|x| database::models::ids::UserId::from(x.id).0);
+    let hidden_statuses = crate::models::collections::CollectionStatus::iterator()
+        .filter(|x| x.is_hidden())
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+
+    let visible = sqlx::query!(
+        "
+        SELECT c.id FROM collections c
+        WHERE c.id = ANY($1) AND c.deleted_at IS NULL AND (
+            $2 OR NOT (c.status = ANY($3)) OR c.user_id = $4 OR EXISTS (
+                SELECT 1 FROM collection_shares cs
+                WHERE cs.collection_id = c.id AND cs.user_id = $4
+            )
+        )
+        ",
+        &child_ids,
+        is_mod,
+        &hidden_statuses,
+        user_id,
+    )
+    .fetch_all(&***pool)
+    .await?
+    .into_iter()
+    .map(|x| models::ids::CollectionId(x.id))
+    .collect::<Vec<_>>();
+
+    for collection in collections {
+        collection
+            .child_collections
+            .retain(|x| visible.contains(&(*x).into()));
+    }
+
+    Ok(())
+}
+
+/// A reference to a resource of any type, such as an entry in a mixed feed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ResourceId {
+    Project(models::ids::ProjectId),
+    Collection(models::ids::CollectionId),
+}
+
+pub enum AuthorizedResource {
+    Project(Box<crate::models::projects::Project>),
+    Collection(Box<crate::models::collections::Collection>),
+}
+
+/// Loads resources of mixed types and keeps the ones visible to the user, in the order they were
+/// given. Each type is loaded and filtered in a single batch with the same rules as
+/// `filter_authorized_projects` and `filter_authorized_collections`. Missing resources are skipped.
+pub async fn filter_authorized_resources(
+    resource_ids: &[ResourceId],
+    user_option: &Option<User>,
+    pool: &web::Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+) -> Result<Vec<AuthorizedResource>, ApiError> {
+    let mut project_ids = Vec::new();
+    let mut collection_ids = Vec::new();
+
+    for resource_id in resource_ids {
+        match resource_id {
+            ResourceId::Project(id) => project_ids.push(*id),
+            ResourceId::Collection(id) => collection_ids.push(*id),
+        }
+    }
+
+    let (projects, collections) = futures::future::try_join(
+        Project::get_many_ids(&project_ids, &***pool, redis),
+        Collection::get_many(&collection_ids, &***pool, redis),
+    )
+    .await?;
+
+    authorize_resources(resource_ids, projects, collections, user_option, pool).await
+}
+
+async fn authorize_resources(
+    resource_ids: &[ResourceId],
+    projects: Vec<QueryProject>,
+    collections: Vec<Collection>,
+    user_option: &Option<User>,
+    pool: &web::Data<PgPool>,
+) -> Result<Vec<AuthorizedResource>, ApiError> {
+    let (mut projects, mut collections) = futures::future::try_join(
+        filter_authorized_projects(projects, user_option, pool),
+        filter_authorized_collections(collections, user_option, pool),
+    )
+    .await?;
+
+    let mut resources = Vec::new();
+    for resource_id in resource_ids {
+        match resource_id {
+            ResourceId::Project(id) => {
+                let id: crate::models::ids::ProjectId = (*id).into();
+
+                if let Some(index) = projects.iter().position(|x| x.id == id) {
+                    resources.push(AuthorizedResource::Project(Box::new(
+                        projects.swap_remove(index),
+                    )));
                 }
+            }
+            ResourceId::Collection(id) => {
+                let id: crate::models::ids::CollectionId = (*id).into();
 
-                futures::future::ready(Ok(()))
-            })
-            .await?;
+                if let Some(index) = collections.iter().position(|x| x.id == id) {
+                    resources.push(AuthorizedResource::Collection(Box::new(
+                        collections.swap_remove(index),
+                    )));
+                }
+            }
         }
     }
 
-    Ok(return_collections)
+    Ok(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::collections::CollectionStatus;
+    use crate::models::projects::{MonetizationStatus, ProjectStatus, SideType};
+
+    fn collection(id: i64, status: CollectionStatus) -> Collection {
+        Collection {
+            id: models::ids::CollectionId(id),
+            user_id: models::ids::UserId(1),
+            title: String::new(),
+            description: String::new(),
+            slug: None,
+            body: String::new(),
+            body_html: String::new(),
+            created: chrono::Utc::now(),
+            updated: chrono::Utc::now(),
+            icon_url: None,
+            icon_filename: None,
+            color: None,
+            status,
+            follows: 0,
+            colors: Vec::new(),
+            shared_with: Vec::new(),
+            categories: Vec::new(),
+            featured_project: None,
+            version: 1,
+            projects: Vec::new(),
+            child_collections: Vec::new(),
+        }
+    }
+
+    fn project(id: i64, status: ProjectStatus) -> QueryProject {
+        QueryProject {
+            inner: database::models::project_item::Project {
+                id: models::ids::ProjectId(id),
+                project_type: models::ids::ProjectTypeId(1),
+                team_id: models::ids::TeamId(id),
+                title: String::new(),
+                description: String::new(),
+                body: String::new(),
+                body_url: None,
+                published: chrono::Utc::now(),
+                updated: chrono::Utc::now(),
+                approved: None,
+                queued: None,
+                status,
+                requested_status: None,
+                downloads: 0,
+                follows: 0,
+                icon_url: None,
+                issues_url: None,
+                source_url: None,
+                wiki_url: None,
+                license_url: None,
+                discord_url: None,
+                client_side: models::ids::SideTypeId(1),
+                server_side: models::ids::SideTypeId(1),
+                license: String::new(),
+                slug: None,
+                moderation_message: None,
+                moderation_message_body: None,
+                webhook_sent: false,
+                color: None,
+                monetization_status: MonetizationStatus::Monetized,
+                loaders: Vec::new(),
+                game_versions: Vec::new(),
+            },
+            project_type: String::new(),
+            categories: Vec::new(),
+            additional_categories: Vec::new(),
+            versions: Vec::new(),
+            donation_urls: Vec::new(),
+            gallery_items: Vec::new(),
+            client_side: SideType::Unknown,
+            server_side: SideType::Unknown,
+            thread_id: models::ids::ThreadId(id),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn filtered_collections_keep_their_order() {
+        // Nothing here needs the database, so the pool never connects
+        let pool = web::Data::new(
+            sqlx::postgres::PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/labrinth")
+                .unwrap(),
+        );
+
+        let collections = vec![
+            collection(3, CollectionStatus::Listed),
+            collection(1, CollectionStatus::Private),
+            collection(4, CollectionStatus::Unlisted),
+            collection(2, CollectionStatus::Listed),
+        ];
+
+        let filtered = filter_authorized_collections(collections, &None, &pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            filtered.iter().map(|x| x.id.0).collect::<Vec<_>>(),
+            vec![3, 4, 2]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn authorized_resources_keep_their_order() {
+        // Nothing here needs the database, so the pool never connects
+        let pool = web::Data::new(
+            sqlx::postgres::PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/labrinth")
+                .unwrap(),
+        );
+
+        let resource_ids = vec![
+            ResourceId::Collection(models::ids::CollectionId(2)),
+            ResourceId::Project(models::ids::ProjectId(3)),
+            ResourceId::Project(models::ids::ProjectId(5)),
+            ResourceId::Collection(models::ids::CollectionId(1)),
+            ResourceId::Project(models::ids::ProjectId(4)),
+            ResourceId::Collection(models::ids::CollectionId(6)),
+        ];
+        let projects = vec![
+            project(4, ProjectStatus::Approved),
+            project(5, ProjectStatus::Draft),
+            project(3, ProjectStatus::Unlisted),
+        ];
+        let collections = vec![
+            collection(1, CollectionStatus::Listed),
+            collection(2, CollectionStatus::Unlisted),
+        ];
+
+        let resources = authorize_resources(&resource_ids, projects, collections, &None, &pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            resources
+                .iter()
+                .map(|x| match x {
+                    AuthorizedResource::Project(project) => ResourceId::Project(project.id.into()),
+                    AuthorizedResource::Collection(collection) => {
+                        ResourceId::Collection(collection.id.into())
+                    }
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                ResourceId::Collection(models::ids::CollectionId(2)),
+                ResourceId::Project(models::ids::ProjectId(3)),
+                ResourceId::Collection(models::ids::CollectionId(1)),
+                ResourceId::Project(models::ids::ProjectId(4)),
+            ]
+        );
+    }
 }
@@ -1,13 +1,65 @@
 use super::ids::*;
 use crate::database::models;
+use crate::database::models::notification_item::NotificationBuilder;
 use crate::database::models::DatabaseError;
-use crate::models::collections::CollectionStatus;
+use crate::models::collections::{CollectionPermissions, CollectionSort, CollectionStatus};
+use crate::models::notifications::NotificationBody;
+use crate::util::metrics::COLLECTION_BACKEND_DURATION;
 use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use log::warn;
 use redis::cmd;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use tracing::Instrument;
 
 const COLLECTIONS_NAMESPACE: &str = "collections";
+const COLLECTIONS_ICONS_NAMESPACE: &str = "collections_icons";
+const COLLECTIONS_RECENT_NAMESPACE: &str = "collections_recent";
+const COLLECTIONS_TRENDING_NAMESPACE: &str = "collections_trending";
+const COLLECTIONS_IDEMPOTENCY_NAMESPACE: &str = "collections_idempotency";
 const DEFAULT_EXPIRY: i64 = 1800; // 30 minutes
+const ICON_EXPIRY: i64 = 60 * 60 * 24; // 1 day
+const RECENT_EXPIRY: i64 = 60; // 1 minute
+const TRENDING_EXPIRY: i64 = 60 * 5; // 5 minutes
+const IDEMPOTENCY_EXPIRY: i64 = 60 * 60 * 24; // 1 day
+/// Stored for an idempotency key while the collection it creates is not committed yet
+const IDEMPOTENCY_PENDING: &str = "pending";
+
+lazy_static! {
+    // Collections whose cache entries could not be cleared, retried by `clear_stale_caches`
+    static ref STALE_CACHE_IDS: Mutex<HashSet<i64>> = Mutex::new(HashSet::new());
+}
+/// How long a soft-deleted collection can be restored before it is purged
+pub const DELETED_RETENTION_DAYS: i64 = 30;
+/// The number of member project colors kept in a collection's palette
+pub const PALETTE_SIZE: i64 = 4;
+const DEFAULT_LISTED_MIN_PROJECTS: i64 = 2;
+const DEFAULT_COLLECTION_PROJECTS_LIMIT: i64 = 10000;
+
+/// The number of projects a collection needs to be listed, which keeps empty lists out of
+/// public browsing
+pub fn listed_min_projects() -> i64 {
+    crate::util::env::parse_var("COLLECTION_LISTED_MIN_PROJECTS")
+        .unwrap_or(DEFAULT_LISTED_MIN_PROJECTS)
+}
+
+/// The most project memberships a user may have across all of their collections
+pub fn collection_projects_limit() -> i64 {
+    crate::util::env::parse_var("COLLECTION_PROJECTS_LIMIT")
+        .unwrap_or(DEFAULT_COLLECTION_PROJECTS_LIMIT)
+}
+
+/// The state of an idempotency key for creating a collection
+pub enum CreateKeyClaim {
+    /// The key was unused and is now held by the caller
+    Claimed,
+    /// Another request with the key is still creating its collection
+    Pending,
+    /// A collection was already created with the key
+    Created(CollectionId),
+}
 
 #[derive(Clone)]
 pub struct CollectionBuilder {
@@ -15,7 +67,9 @@ pub struct CollectionBuilder {
     pub user_id: UserId,
     pub title: String,
     pub description: String,
+    pub slug: Option<String>,
     pub status: CollectionStatus,
+    pub categories: Vec<String>,
     pub projects: Vec<ProjectId>,
 }
 
@@ -29,12 +83,23 @@ impl CollectionBuilder {
             title: self.title,
             user_id: self.user_id,
             description: self.description,
+            slug: self.slug,
             created: Utc::now(),
             updated: Utc::now(),
             icon_url: None,
+            icon_filename: None,
             color: None,
             status: self.status,
+            body: String::new(),
+            body_html: String::new(),
+            follows: 0,
+            colors: Vec::new(),
+            shared_with: Vec::new(),
+            categories: self.categories,
+            featured_project: None,
+            version: 1,
             projects: self.projects,
+            child_collections: Vec::new(),
         };
         collection_struct.insert(&mut *transaction).await?;
 
@@ -47,15 +112,44 @@ pub struct Collection {
     pub user_id: UserId,
     pub title: String,
     pub description: String,
+    /// A unique, lowercase url-safe name the collection can be accessed by instead of its id
+    pub slug: Option<String>,
+    /// Long form markdown description
+    pub body: String,
+    /// `body` rendered to sanitized HTML when it was last edited
+    pub body_html: String,
     pub created: DateTime<Utc>,
     pub updated: DateTime<Utc>,
     pub icon_url: Option<String>,
+    /// The sanitized name the icon was uploaded with, served when it is downloaded
+    pub icon_filename: Option<String>,
     pub color: Option<u32>,
     pub status: CollectionStatus,
+    pub follows: i32,
+    /// Icon colors of up to `PALETTE_SIZE` member projects, in collection order
+    pub colors: Vec<u32>,
+    /// Users which can view this collection even when it is hidden
+    pub shared_with: Vec<UserId>,
+    /// Names of the collection tags this collection is categorized under
+    pub categories: Vec<String>,
+    /// A member project highlighted at the top of the collection
+    pub featured_project: Option<ProjectId>,
+    /// Incremented whenever the collection's contents change
+    pub version: i32,
     pub projects: Vec<ProjectId>,
+    /// Collections contained in this collection, in display order
+    pub child_collections: Vec<CollectionId>,
 }
 
 impl Collection {
+    /// An entity tag for this collection. Any change which clears the collection from the cache
+    /// also changes the tag, since it is derived from the full cached representation.
+    pub fn etag(&self) -> Result<String, DatabaseError> {
+        let hash = sha1::Sha1::from(serde_json::to_vec(self)?).hexdigest();
+
+        Ok(format!("\"{}-{}\"", self.updated.timestamp_millis(), hash))
+    }
+
     pub async fn insert(
         &self,
         transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
@@ -64,11 +158,11 @@ impl Collection {
             "
             INSERT INTO collections (
                 id, user_id, title, description, 
-                created, icon_url, status
+                created, icon_url, status, slug
             )
             VALUES (
                 $1, $2, $3, $4, 
-                $5, $6, $7
+                $5, $6, $7, $8
             )
             ",
             self.id as CollectionId,
@@ -78,19 +172,23 @@ impl Collection {
             self.created,
             self.icon_url.as_ref(),
             self.status.to_string(),
+            self.slug.as_ref(),
         )
         .execute(&mut *transaction)
         .await?;
 
-        for project_id in self.projects.iter() {
+        Collection::set_categories(self.id, &self.categories, &mut *transaction).await?;
+
+        for (ordinal, project_id) in self.projects.iter().enumerate() {
             sqlx::query!(
                 "
-                    INSERT INTO collections_mods (collection_id, mod_id)
-                    VALUES ($1, $2)
+                    INSERT INTO collections_mods (collection_id, mod_id, ordinal)
+                    VALUES ($1, $2, $3)
                     ON CONFLICT DO NOTHING
                 ",
                 self.id as CollectionId,
                 *project_id as ProjectId,
+                ordinal as i32,
             )
             .execute(&mut *transaction)
             .await?;
@@ -99,6 +197,582 @@ impl Collection {
         Ok(())
     }
 
+    /// Replaces the categories of a collection. Unknown category names are ignored, so they
+    /// should be validated against `list_categories` beforehand.
+    pub async fn set_categories(
+        id: CollectionId,
+        categories: &[String],
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            DELETE FROM collection_categories
+            WHERE collection_id = $1
+            ",
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO collection_categories (collection_id, category_id)
+            SELECT $1, ct.id FROM collection_tags ct
+            WHERE ct.name = ANY($2)
+            ",
+            id as CollectionId,
+            categories,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The names of all categories collections can be tagged with
+    pub async fn list_categories<'a, E>(exec: E) -> Result<Vec<String>, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let categories = sqlx::query!(
+            "
+            SELECT name FROM collection_tags
+            ORDER BY name
+            "
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|x| x.name)
+        .collect();
+
+        Ok(categories)
+    }
+
+    pub async fn set_title(
+        id: CollectionId,
+        title: &str,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET title = $1
+            WHERE (id = $2)
+            ",
+            title,
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_description(
+        id: CollectionId,
+        description: &str,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET description = $1
+            WHERE (id = $2)
+            ",
+            description,
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_status(
+        id: CollectionId,
+        status: CollectionStatus,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET status = $1
+            WHERE (id = $2)
+            ",
+            status.as_str(),
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Points a collection at a newly uploaded icon
+    pub async fn set_icon(
+        id: CollectionId,
+        icon_url: &str,
+        color: Option<u32>,
+        icon_size: i64,
+        icon_filename: Option<&str>,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET icon_url = $1, color = $2, icon_size = $4, icon_filename = $5
+            WHERE (id = $3)
+            ",
+            icon_url,
+            color.map(|x| x as i32),
+            id as CollectionId,
+            icon_size,
+            icon_filename,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn clear_icon(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET icon_url = NULL, color = NULL, icon_size = NULL, icon_filename = NULL
+            WHERE (id = $1)
+            ",
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Adds projects after the current members of a collection, in the order given. Projects
+    /// which are already members are left where they are. Returns the projects which were added.
+    pub async fn add_projects(
+        id: CollectionId,
+        projects: &[ProjectId],
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<Vec<ProjectId>, DatabaseError> {
+        let added = sqlx::query!(
+            "
+            INSERT INTO collections_mods (collection_id, mod_id, ordinal)
+            SELECT $1, m.mod_id, (
+                SELECT COALESCE(MAX(ordinal) + 1, 0) FROM collections_mods
+                WHERE collection_id = $1
+            ) + m.idx::int - 1
+            FROM UNNEST($2::bigint[]) WITH ORDINALITY AS m(mod_id, idx)
+            ON CONFLICT DO NOTHING
+            RETURNING mod_id
+            ",
+            id as CollectionId,
+            &projects.iter().map(|x| x.0).collect::<Vec<_>>(),
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|x| ProjectId(x.mod_id))
+        .collect();
+
+        Ok(added)
+    }
+
+    /// Lets everyone following a collection, except the editor, know that projects were added
+    /// to it
+    pub async fn notify_followers(
+        id: CollectionId,
+        added_projects: &[ProjectId],
+        editor_id: Option<UserId>,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        if added_projects.is_empty() {
+            return Ok(());
+        }
+
+        let followers = sqlx::query!(
+            "
+            SELECT follower_id FROM collection_follows
+            WHERE collection_id = $1
+            ",
+            id as CollectionId,
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|x| UserId(x.follower_id))
+        .filter(|x| Some(*x) != editor_id)
+        .collect::<Vec<_>>();
+
+        NotificationBuilder {
+            body: NotificationBody::CollectionUpdate {
+                collection_id: id.into(),
+                added_projects: added_projects.iter().map(|x| (*x).into()).collect(),
+            },
+        }
+        .insert_many(followers, transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Recomputes the color palette of a collection from the icons of its first member projects.
+    /// This should be called whenever the membership or order of a collection changes.
+    pub async fn update_colors(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<Vec<u32>, DatabaseError> {
+        let colors = sqlx::query!(
+            "
+            UPDATE collections
+            SET colors = ARRAY(
+                SELECT m.color FROM collections_mods cm
+                INNER JOIN mods m ON m.id = cm.mod_id
+                WHERE cm.collection_id = $1 AND m.color IS NOT NULL
+                ORDER BY cm.ordinal, cm.mod_id
+                LIMIT $2
+            )
+            WHERE id = $1
+            RETURNING colors
+            ",
+            id as CollectionId,
+            PALETTE_SIZE,
+        )
+        .fetch_one(&mut *transaction)
+        .await?
+        .colors;
+
+        Ok(colors.into_iter().map(|x| x as u32).collect())
+    }
+
+    /// Marks a collection as updated now and bumps its version. This should be called whenever
+    /// its contents change.
+    pub async fn touch(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET updated = NOW(), version = version + 1
+            WHERE id = $1
+            ",
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes every project from a collection, returning the removed projects. The color
+    /// palette is cleared along with them.
+    pub async fn clear_projects(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<Vec<ProjectId>, DatabaseError> {
+        let removed = sqlx::query!(
+            "
+            DELETE FROM collections_mods
+            WHERE collection_id = $1
+            RETURNING mod_id
+            ",
+            id as CollectionId,
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|x| ProjectId(x.mod_id))
+        .collect();
+
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET colors = '{}'
+            WHERE id = $1
+            ",
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(removed)
+    }
+
+    /// Replaces the child collections of a collection, keeping them in the given order
+    pub async fn set_children(
+        id: CollectionId,
+        children: &[CollectionId],
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            DELETE FROM collections_children
+            WHERE parent_id = $1
+            ",
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT INTO collections_children (parent_id, child_id, ordinal)
+            SELECT $1, child_id, (ordinal - 1)::integer
+            FROM UNNEST($2::bigint[]) WITH ORDINALITY AS children(child_id, ordinal)
+            ON CONFLICT DO NOTHING
+            ",
+            id as CollectionId,
+            &children.iter().map(|x| x.0).collect::<Vec<i64>>(),
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether a collection is contained in any of the given collections, directly or through
+    /// their own children. Adding such collections as children would create a cycle.
+    pub async fn is_descendant_of<'a, E>(
+        id: CollectionId,
+        ancestors: &[CollectionId],
+        exec: E,
+    ) -> Result<bool, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let descendant = sqlx::query!(
+            "
+            WITH RECURSIVE descendants (id) AS (
+                SELECT UNNEST($1::bigint[])
+                UNION
+                SELECT ch.child_id FROM collections_children ch
+                INNER JOIN descendants d ON d.id = ch.parent_id
+            )
+            SELECT EXISTS(SELECT 1 FROM descendants WHERE id = $2)
+            ",
+            &ancestors.iter().map(|x| x.0).collect::<Vec<i64>>(),
+            id as CollectionId,
+        )
+        .fetch_one(exec)
+        .await?
+        .exists
+        .unwrap_or(false);
+
+        Ok(descendant)
+    }
+
+    /// The number of views of a collection, as of the last flush of counted views
+    pub async fn get_views<'a, E>(id: CollectionId, exec: E) -> Result<i64, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let views = sqlx::query!(
+            "
+            SELECT views FROM collection_views
+            WHERE collection_id = $1
+            ",
+            id as CollectionId,
+        )
+        .fetch_optional(exec)
+        .await?
+        .map(|x| x.views)
+        .unwrap_or(0);
+
+        Ok(views)
+    }
+
+    /// The current version of a collection, locking its row until the transaction ends so that
+    /// concurrent edits are applied one after another
+    pub async fn lock_version(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<Option<i32>, DatabaseError> {
+        let version = sqlx::query!(
+            "
+            SELECT version FROM collections
+            WHERE id = $1
+            FOR UPDATE
+            ",
+            id as CollectionId,
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .map(|x| x.version);
+
+        Ok(version)
+    }
+
+    /// Clears the featured project of a collection if it is no longer a member. This should be
+    /// called in the same transaction as any change which removes projects from the collection.
+    pub async fn clear_removed_featured_project(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            UPDATE collections c
+            SET featured_project = NULL
+            WHERE c.id = $1 AND c.featured_project IS NOT NULL AND NOT EXISTS (
+                SELECT 1 FROM collections_mods cm
+                WHERE cm.collection_id = c.id AND cm.mod_id = c.featured_project
+            )
+            ",
+            id as CollectionId,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The subset of the given projects which are of a project type
+    pub async fn filter_projects_by_type<'a, E>(
+        project_ids: &[ProjectId],
+        project_type: &str,
+        exec: E,
+    ) -> Result<Vec<ProjectId>, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let project_ids = sqlx::query!(
+            "
+            SELECT m.id FROM mods m
+            INNER JOIN project_types pt ON pt.id = m.project_type
+            WHERE m.id = ANY($1) AND pt.name = $2
+            ",
+            &project_ids.iter().map(|x| x.0).collect::<Vec<_>>(),
+            project_type,
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|x| ProjectId(x.id))
+        .collect();
+
+        Ok(project_ids)
+    }
+
+    /// The generated icon of a collection without a custom one, if it is cached.
+    /// It is invalidated along with the collection by `clear_cache`.
+    pub async fn get_cached_icon(
+        id: CollectionId,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let mut redis = redis.get().await?;
+
+        let icon = cmd("GET")
+            .arg(format!("{}:{}", COLLECTIONS_ICONS_NAMESPACE, id.0))
+            .query_async::<_, Option<Vec<u8>>>(&mut redis)
+            .await?;
+
+        Ok(icon)
+    }
+
+    pub async fn set_cached_icon(
+        id: CollectionId,
+        icon: &[u8],
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        let mut redis = redis.get().await?;
+
+        cmd("SET")
+            .arg(format!("{}:{}", COLLECTIONS_ICONS_NAMESPACE, id.0))
+            .arg(icon)
+            .arg("EX")
+            .arg(ICON_EXPIRY)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+
+        Ok(())
+    }
+
+    // Keys are scoped to the user and hashed, since clients may choose arbitrarily long keys
+    fn create_key(user_id: UserId, key: &str) -> String {
+        format!(
+            "{}:{}:{}",
+            COLLECTIONS_IDEMPOTENCY_NAMESPACE,
+            user_id.0,
+            sha1::Sha1::from(key).hexdigest()
+        )
+    }
+
+    /// Claims an idempotency key of a user for creating a collection. A claimed key has to be
+    /// completed with `complete_create_key` or released with `release_create_key`.
+    pub async fn claim_create_key(
+        user_id: UserId,
+        key: &str,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<CreateKeyClaim, DatabaseError> {
+        let mut redis = redis.get().await?;
+        let redis_key = Collection::create_key(user_id, key);
+
+        let claimed = cmd("SET")
+            .arg(&redis_key)
+            .arg(IDEMPOTENCY_PENDING)
+            .arg("NX")
+            .arg("EX")
+            .arg(IDEMPOTENCY_EXPIRY)
+            .query_async::<_, Option<String>>(&mut redis)
+            .await?
+            .is_some();
+        if claimed {
+            return Ok(CreateKeyClaim::Claimed);
+        }
+
+        let value = cmd("GET")
+            .arg(&redis_key)
+            .query_async::<_, Option<String>>(&mut redis)
+            .await?;
+
+        Ok(match value.as_deref().and_then(|x| x.parse::<i64>().ok()) {
+            Some(id) => CreateKeyClaim::Created(CollectionId(id)),
+            None => CreateKeyClaim::Pending,
+        })
+    }
+
+    pub async fn complete_create_key(
+        user_id: UserId,
+        key: &str,
+        id: CollectionId,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        let mut redis = redis.get().await?;
+
+        cmd("SET")
+            .arg(Collection::create_key(user_id, key))
+            .arg(id.0)
+            .arg("EX")
+            .arg(IDEMPOTENCY_EXPIRY)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn release_create_key(
+        user_id: UserId,
+        key: &str,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        let mut redis = redis.get().await?;
+
+        cmd("DEL")
+            .arg(Collection::create_key(user_id, key))
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Soft-deletes a collection. It is hidden from all reads and can be restored
+    /// by its owner until it is purged after `DELETED_RETENTION_DAYS`.
     pub async fn remove(
         id: CollectionId,
         transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
@@ -109,32 +783,256 @@ impl Collection {
         if let Some(collection) = collection {
             sqlx::query!(
                 "
-                DELETE FROM collections_mods
-                WHERE collection_id = $1
+                UPDATE collections
+                SET deleted_at = NOW(), version = version + 1
+                WHERE id = $1 AND deleted_at IS NULL
                 ",
                 id as CollectionId,
             )
             .execute(&mut *transaction)
             .await?;
 
-            sqlx::query!(
-                "
-                DELETE FROM collections
-                WHERE id = $1
-                ",
-                id as CollectionId,
-            )
-            .execute(&mut *transaction)
-            .await?;
+            models::Collection::clear_cache(collection.id, redis).await?;
+
+            Ok(Some(()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn remove_many(
+        ids: &[CollectionId],
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let ids_parsed: Vec<i64> = ids.iter().map(|x| x.0).collect();
+
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET deleted_at = NOW(), version = version + 1
+            WHERE id = ANY($1) AND deleted_at IS NULL
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        models::Collection::clear_cache_many(ids, redis).await?;
+
+        Ok(())
+    }
+
+    /// Restores a soft-deleted collection, returning its status, or `None` if it is not deleted
+    pub async fn restore(
+        id: CollectionId,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<Option<CollectionStatus>, DatabaseError> {
+        let result = sqlx::query!(
+            "
+            UPDATE collections
+            SET deleted_at = NULL, version = version + 1
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING status
+            ",
+            id as CollectionId,
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        models::Collection::clear_cache(id, redis).await?;
+
+        Ok(result.map(|x| CollectionStatus::from(x.status.as_str())))
+    }
+
+    /// Gets the owner and deletion time of a soft-deleted collection
+    pub async fn get_deleted<'a, E>(
+        id: CollectionId,
+        exec: E,
+    ) -> Result<Option<(UserId, DateTime<Utc>)>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let result = sqlx::query!(
+            "
+            SELECT user_id, deleted_at FROM collections
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            ",
+            id as CollectionId,
+        )
+        .fetch_optional(exec)
+        .await?;
+
+        Ok(result.and_then(|x| x.deleted_at.map(|y| (UserId(x.user_id), y))))
+    }
+
+    /// Gets the ids and icons of collections soft-deleted before the given time
+    pub async fn get_deleted_before<'a, E>(
+        before: DateTime<Utc>,
+        exec: E,
+    ) -> Result<Vec<(CollectionId, Option<String>)>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        use futures::TryStreamExt;
+
+        sqlx::query!(
+            "
+            SELECT id, icon_url FROM collections
+            WHERE deleted_at < $1
+            ",
+            before,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async { Ok(e.right().map(|m| (CollectionId(m.id), m.icon_url))) })
+        .try_collect::<Vec<_>>()
+        .await
+    }
+
+    /// Makes listed collections with fewer than `min_projects` projects unlisted, returning
+    /// their ids and owners. Their caches are not cleared.
+    pub async fn unlist_underfilled<'a, E>(
+        min_projects: i64,
+        exec: E,
+    ) -> Result<Vec<(CollectionId, UserId)>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        use futures::TryStreamExt;
+
+        sqlx::query!(
+            "
+            UPDATE collections c
+            SET status = $2, version = version + 1
+            WHERE c.status = $1 AND c.deleted_at IS NULL
+            AND (SELECT COUNT(*) FROM collections_mods cm WHERE cm.collection_id = c.id) < $3
+            RETURNING c.id, c.user_id
+            ",
+            CollectionStatus::Listed.as_str(),
+            CollectionStatus::Unlisted.as_str(),
+            min_projects,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async {
+            Ok(e.right().map(|m| (CollectionId(m.id), UserId(m.user_id))))
+        })
+        .try_collect::<Vec<_>>()
+        .await
+    }
+
+    /// Permanently deletes collections and everything referencing them
+    pub async fn purge_many(
+        ids: &[CollectionId],
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let ids_parsed: Vec<i64> = ids.iter().map(|x| x.0).collect();
+
+        sqlx::query!(
+            "
+            DELETE FROM collections_mods
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collection_follows
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collection_pins
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collections_history
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collection_shares
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collection_categories
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collection_views
+            WHERE collection_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM collections_children
+            WHERE parent_id = ANY($1) OR child_id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
 
-            models::Collection::clear_cache(collection.id, redis).await?;
+        sqlx::query!(
+            "
+            DELETE FROM collections
+            WHERE id = ANY($1)
+            ",
+            &ids_parsed,
+        )
+        .execute(&mut *transaction)
+        .await?;
 
-            Ok(Some(()))
-        } else {
-            Ok(None)
-        }
+        models::Collection::clear_cache_many(ids, redis).await?;
+
+        Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(collection_id = id.0))]
     pub async fn get<'a, 'b, E>(
         id: CollectionId,
         executor: E,
@@ -148,6 +1046,71 @@ impl Collection {
             .map(|x| x.into_iter().next())
     }
 
+    /// Loads a collection without its member projects, along with how many it has.
+    /// The returned collection's `projects` is always empty, and it bypasses the cache.
+    pub async fn get_without_projects<'a, E>(
+        id: CollectionId,
+        exec: E,
+    ) -> Result<Option<(Collection, i64)>, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let result = sqlx::query!(
+            "
+            SELECT c.id id, c.title title, c.description description, c.slug slug, c.body body, c.body_html body_html,
+            c.icon_url icon_url, c.icon_filename icon_filename, c.color color, c.created created, c.user_id user_id,
+            c.updated updated, c.status status, c.follows follows, c.colors colors, c.featured_project featured_project, c.version version,
+            (SELECT COUNT(*) FROM collections_mods cm WHERE cm.collection_id = c.id) project_count,
+            ARRAY(SELECT cs.user_id FROM collection_shares cs WHERE cs.collection_id = c.id) shared_with,
+            ARRAY(
+                SELECT ct.name FROM collection_categories cc
+                INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                WHERE cc.collection_id = c.id ORDER BY ct.name
+            ) categories
+            FROM collections c
+            WHERE c.id = $1 AND c.deleted_at IS NULL
+            ",
+            id as CollectionId,
+        )
+        .fetch_optional(exec)
+        .await?;
+
+        Ok(result.map(|m| {
+            (
+                Collection {
+                    id: CollectionId(m.id),
+                    user_id: UserId(m.user_id),
+                    title: m.title,
+                    description: m.description,
+                    slug: m.slug,
+                    body: m.body,
+                    body_html: m.body_html,
+                    icon_url: m.icon_url,
+                    icon_filename: m.icon_filename,
+                    color: m.color.map(|x| x as u32),
+                    created: m.created,
+                    updated: m.updated,
+                    status: CollectionStatus::from(m.status.as_str()),
+                    follows: m.follows,
+                    colors: m.colors.into_iter().map(|x| x as u32).collect(),
+                    shared_with: m
+                        .shared_with
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(UserId)
+                        .collect(),
+                    categories: m.categories.unwrap_or_default(),
+                    featured_project: m.featured_project.map(ProjectId),
+                    version: m.version,
+                    projects: Vec::new(),
+                    child_collections: Vec::new(),
+                },
+                m.project_count.unwrap_or(0),
+            )
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(count = collection_ids.len()))]
     pub async fn get_many<'a, E>(
         collection_ids: &[CollectionId],
         exec: E,
@@ -168,6 +1131,9 @@ impl Collection {
         let mut remaining_collections: Vec<CollectionId> = collection_ids.to_vec();
 
         if !collection_ids.is_empty() {
+            let timer = COLLECTION_BACKEND_DURATION
+                .with_label_values(&["redis"])
+                .start_timer();
             let collections = cmd("MGET")
                 .arg(
                     collection_ids
@@ -176,7 +1142,9 @@ impl Collection {
                         .collect::<Vec<_>>(),
                 )
                 .query_async::<_, Vec<Option<String>>>(&mut redis)
+                .instrument(tracing::debug_span!("redis_get"))
                 .await?;
+            timer.observe_duration();
 
             for collection in collections {
                 if let Some(collection) =
@@ -192,15 +1160,28 @@ impl Collection {
         if !remaining_collections.is_empty() {
             let collection_ids_parsed: Vec<i64> =
                 remaining_collections.iter().map(|x| x.0).collect();
+            let timer = COLLECTION_BACKEND_DURATION
+                .with_label_values(&["postgres"])
+                .start_timer();
             let db_collections: Vec<Collection> = sqlx::query!(
                 "
-                SELECT c.id id, c.title title, c.description description,
-                c.icon_url icon_url, c.color color, c.created created, c.user_id user_id,
-                c.updated updated, c.status status,
-                ARRAY_AGG(DISTINCT cm.mod_id) filter (where cm.mod_id is not null) mods
+                SELECT c.id id, c.title title, c.description description, c.slug slug, c.body body, c.body_html body_html,
+                c.icon_url icon_url, c.icon_filename icon_filename, c.color color, c.created created, c.user_id user_id,
+                c.updated updated, c.status status, c.follows follows, c.colors colors, c.featured_project featured_project, c.version version,
+                ARRAY_AGG(cm.mod_id ORDER BY cm.ordinal, cm.mod_id) filter (where cm.mod_id is not null) mods,
+                ARRAY(SELECT cs.user_id FROM collection_shares cs WHERE cs.collection_id = c.id) shared_with,
+                ARRAY(
+                    SELECT ct.name FROM collection_categories cc
+                    INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                    WHERE cc.collection_id = c.id ORDER BY ct.name
+                ) categories,
+                ARRAY(
+                    SELECT ch.child_id FROM collections_children ch
+                    WHERE ch.parent_id = c.id ORDER BY ch.ordinal, ch.child_id
+                ) child_collections
                 FROM collections c
                 LEFT JOIN collections_mods cm ON cm.collection_id = c.id
-                WHERE c.id = ANY($1)
+                WHERE c.id = ANY($1) AND c.deleted_at IS NULL
                 GROUP BY c.id;
                 ",
                 &collection_ids_parsed,
@@ -215,22 +1196,45 @@ impl Collection {
                         user_id: UserId(m.user_id),
                         title: m.title.clone(),
                         description: m.description.clone(),
+                        slug: m.slug.clone(),
+                        body: m.body.clone(),
+                        body_html: m.body_html.clone(),
                         icon_url: m.icon_url.clone(),
+                        icon_filename: m.icon_filename.clone(),
                         color: m.color.map(|x| x as u32),
                         created: m.created,
                         updated: m.updated,
-                        status: CollectionStatus::from_str(&m.status),
+                        status: CollectionStatus::from(m.status.as_str()),
+                        follows: m.follows,
+                        colors: m.colors.into_iter().map(|x| x as u32).collect(),
+                        shared_with: m
+                            .shared_with
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(UserId)
+                            .collect(),
+                        categories: m.categories.clone().unwrap_or_default(),
+                        featured_project: m.featured_project.map(ProjectId),
+                        version: m.version,
                         projects: m
                             .mods
                             .unwrap_or_default()
                             .into_iter()
                             .map(ProjectId)
                             .collect(),
+                        child_collections: m
+                            .child_collections
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(CollectionId)
+                            .collect(),
                     }
                 }))
             })
             .try_collect::<Vec<Collection>>()
+            .instrument(tracing::debug_span!("postgres_get"))
             .await?;
+            timer.observe_duration();
 
             for collection in db_collections {
                 cmd("SET")
@@ -248,16 +1252,766 @@ impl Collection {
         Ok(found_collections)
     }
 
+    /// The most recently updated listed collections, newest first. The ids are cached briefly,
+    /// so collections updated within the last minute may not be included yet.
+    pub async fn get_recent<'a, E>(
+        limit: i64,
+        exec: E,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<Vec<CollectionId>, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let mut redis = redis.get().await?;
+        let key = format!("{}:{}", COLLECTIONS_RECENT_NAMESPACE, limit);
+
+        let cached = cmd("GET")
+            .arg(&key)
+            .query_async::<_, Option<String>>(&mut redis)
+            .await?
+            .and_then(|x| serde_json::from_str::<Vec<CollectionId>>(&x).ok());
+
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let collection_ids = sqlx::query!(
+            "
+            SELECT c.id FROM collections c
+            WHERE c.status = $1 AND c.deleted_at IS NULL
+            ORDER BY c.updated DESC, c.id DESC
+            LIMIT $2
+            ",
+            CollectionStatus::Listed.as_str(),
+            limit,
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|x| CollectionId(x.id))
+        .collect::<Vec<_>>();
+
+        cmd("SET")
+            .arg(&key)
+            .arg(serde_json::to_string(&collection_ids)?)
+            .arg("EX")
+            .arg(RECENT_EXPIRY)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+
+        Ok(collection_ids)
+    }
+
+    /// The listed collections followed the most in the last `days` days, with the number of
+    /// those follows. Collections without recent follows are left out, and ties are broken by
+    /// all-time views. The ranking is cached for a few minutes.
+    pub async fn get_trending<'a, E>(
+        days: i32,
+        limit: i64,
+        exec: E,
+        redis: &deadpool_redis::Pool,
+    ) -> Result<Vec<(CollectionId, i64)>, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let mut redis = redis.get().await?;
+        let key = format!("{}:{}:{}", COLLECTIONS_TRENDING_NAMESPACE, days, limit);
+
+        let cached = cmd("GET")
+            .arg(&key)
+            .query_async::<_, Option<String>>(&mut redis)
+            .await?
+            .and_then(|x| serde_json::from_str::<Vec<(CollectionId, i64)>>(&x).ok());
+
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let trending = sqlx::query!(
+            "
+            SELECT c.id, COUNT(cf.follower_id) \"recent_follows!\"
+            FROM collections c
+            INNER JOIN collection_follows cf ON cf.collection_id = c.id
+                AND cf.created > NOW() - make_interval(days => $2)
+            LEFT JOIN collection_views cv ON cv.collection_id = c.id
+            WHERE c.status = $1 AND c.deleted_at IS NULL
+            GROUP BY c.id, cv.views
+            ORDER BY 2 DESC, COALESCE(cv.views, 0) DESC, c.id DESC
+            LIMIT $3
+            ",
+            CollectionStatus::Listed.as_str(),
+            days,
+            limit,
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|x| (CollectionId(x.id), x.recent_follows))
+        .collect::<Vec<_>>();
+
+        cmd("SET")
+            .arg(&key)
+            .arg(serde_json::to_string(&trending)?)
+            .arg("EX")
+            .arg(TRENDING_EXPIRY)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+
+        Ok(trending)
+    }
+
+    /// Finds the collection with a slug, compared case-insensitively. Slugs of soft-deleted
+    /// collections stay reserved so they can be restored, so these are found as well.
+    pub async fn get_id_from_slug<'a, E>(
+        slug: &str,
+        exec: E,
+    ) -> Result<Option<CollectionId>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let result = sqlx::query!(
+            "
+            SELECT id FROM collections
+            WHERE LOWER(slug) = LOWER($1)
+            ",
+            slug,
+        )
+        .fetch_optional(exec)
+        .await?;
+
+        Ok(result.map(|x| CollectionId(x.id)))
+    }
+
+    /// Finds the collection with an id or a slug in one query. A collection with the id is preferred
+    /// over one with the slug. Like `get_id_from_slug`, soft-deleted collections are found too.
+    pub async fn resolve_id<'a, E>(
+        id: Option<CollectionId>,
+        slug: Option<&str>,
+        exec: E,
+    ) -> Result<Option<CollectionId>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let result = sqlx::query!(
+            "
+            SELECT id FROM collections
+            WHERE id = $1 OR LOWER(slug) = LOWER($2)
+            ORDER BY (id = $1) IS TRUE DESC
+            LIMIT 1
+            ",
+            id.map(|x| x.0),
+            slug,
+        )
+        .fetch_optional(exec)
+        .await?;
+
+        Ok(result.map(|x| CollectionId(x.id)))
+    }
+
+    /// The permissions a user was granted when the collection was shared with them, if it was
+    pub async fn get_share_permissions<'a, E>(
+        id: CollectionId,
+        user_id: UserId,
+        exec: E,
+    ) -> Result<Option<CollectionPermissions>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let result = sqlx::query!(
+            "
+            SELECT permissions FROM collection_shares
+            WHERE collection_id = $1 AND user_id = $2
+            ",
+            id as CollectionId,
+            user_id as UserId,
+        )
+        .fetch_optional(exec)
+        .await?;
+
+        Ok(result.map(|x| CollectionPermissions::from_bits_truncate(x.permissions as u64)))
+    }
+
+    /// Lists a user's collections, newest first, along with whether each contains the project
+    pub async fn get_user_memberships<'a, E>(
+        user_id: UserId,
+        project_id: ProjectId,
+        exec: E,
+    ) -> Result<Vec<(CollectionId, String, CollectionStatus, bool)>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        use futures::stream::TryStreamExt;
+
+        sqlx::query!(
+            "
+            SELECT c.id, c.title, c.status, cm.mod_id IS NOT NULL contains_project
+            FROM collections c
+            LEFT JOIN collections_mods cm ON cm.collection_id = c.id AND cm.mod_id = $2
+            WHERE c.user_id = $1 AND c.deleted_at IS NULL
+            ORDER BY c.created DESC, c.id DESC
+            ",
+            user_id as UserId,
+            project_id as ProjectId,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async {
+            Ok(e.right().map(|m| {
+                (
+                    CollectionId(m.id),
+                    m.title,
+                    CollectionStatus::from(m.status.as_str()),
+                    m.contains_project.unwrap_or(false),
+                )
+            }))
+        })
+        .try_collect()
+        .await
+    }
+
+    /// Lists the listed collections containing a project, most followed first, along with how
+    /// many there are in total
+    pub async fn get_listed_containing_project<'a, E>(
+        project_id: ProjectId,
+        offset: i64,
+        limit: i64,
+        exec: E,
+    ) -> Result<(Vec<CollectionId>, i64), sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres> + Copy,
+    {
+        use futures::stream::TryStreamExt;
+
+        let total_count = sqlx::query!(
+            "
+            SELECT COUNT(c.id) count FROM collections c
+            INNER JOIN collections_mods cm ON cm.collection_id = c.id AND cm.mod_id = $1
+            WHERE c.status = $2 AND c.deleted_at IS NULL
+            ",
+            project_id as ProjectId,
+            CollectionStatus::Listed.as_str(),
+        )
+        .fetch_one(exec)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        let collections = sqlx::query!(
+            "
+            SELECT c.id FROM collections c
+            INNER JOIN collections_mods cm ON cm.collection_id = c.id AND cm.mod_id = $1
+            WHERE c.status = $2 AND c.deleted_at IS NULL
+            ORDER BY c.follows DESC, c.id DESC
+            OFFSET $3 LIMIT $4
+            ",
+            project_id as ProjectId,
+            CollectionStatus::Listed.as_str(),
+            offset,
+            limit,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async { Ok(e.right().map(|m| CollectionId(m.id))) })
+        .try_collect::<Vec<CollectionId>>()
+        .await?;
+
+        Ok((collections, total_count))
+    }
+
+    /// Counts the collections containing a project which aren't listed
+    pub async fn count_hidden_containing_project<'a, E>(
+        project_id: ProjectId,
+        exec: E,
+    ) -> Result<i64, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let result = sqlx::query!(
+            "
+            SELECT COUNT(c.id) count FROM collections c
+            INNER JOIN collections_mods cm ON cm.collection_id = c.id AND cm.mod_id = $1
+            WHERE c.status != $2 AND c.deleted_at IS NULL
+            ",
+            project_id as ProjectId,
+            CollectionStatus::Listed.as_str(),
+        )
+        .fetch_one(exec)
+        .await?;
+
+        Ok(result.count.unwrap_or(0))
+    }
+
+    /// Matches member projects whose title or slug contains the query, in collection order.
+    /// Unless `visible_statuses` is `None`, only projects with one of them or with the viewer on
+    /// their team are matched, so pages and counts only include projects the viewer can see.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_projects<'a, E>(
+        id: CollectionId,
+        query: &str,
+        visible_statuses: Option<&[String]>,
+        viewer_id: Option<UserId>,
+        offset: i64,
+        limit: i64,
+        exec: E,
+    ) -> Result<(Vec<ProjectId>, i64), sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres> + Copy,
+    {
+        let pattern = substring_pattern(query);
+        let viewer_id = viewer_id.map(|x| x.0);
+
+        let total_count = sqlx::query!(
+            "
+            SELECT COUNT(m.id) count FROM collections_mods cm
+            INNER JOIN mods m ON m.id = cm.mod_id
+            WHERE cm.collection_id = $1 AND (m.title ILIKE $2 OR m.slug ILIKE $2)
+            AND ($3::text[] IS NULL OR m.status = ANY($3) OR EXISTS(
+                SELECT 1 FROM team_members tm
+                WHERE tm.team_id = m.team_id AND tm.user_id = $4
+            ))
+            ",
+            id as CollectionId,
+            pattern,
+            visible_statuses,
+            viewer_id,
+        )
+        .fetch_one(exec)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        let project_ids = sqlx::query!(
+            "
+            SELECT m.id FROM collections_mods cm
+            INNER JOIN mods m ON m.id = cm.mod_id
+            WHERE cm.collection_id = $1 AND (m.title ILIKE $2 OR m.slug ILIKE $2)
+            AND ($3::text[] IS NULL OR m.status = ANY($3) OR EXISTS(
+                SELECT 1 FROM team_members tm
+                WHERE tm.team_id = m.team_id AND tm.user_id = $4
+            ))
+            ORDER BY cm.ordinal, cm.mod_id
+            OFFSET $5 LIMIT $6
+            ",
+            id as CollectionId,
+            pattern,
+            visible_statuses,
+            viewer_id,
+            offset,
+            limit,
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|x| ProjectId(x.id))
+        .collect();
+
+        Ok((project_ids, total_count))
+    }
+
+    /// Searches collections by title, status and owner in the given order.
+    /// Returns one page of ids along with the total number of matches.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search<'a, E>(
+        title_query: Option<&str>,
+        statuses: &[String],
+        user_id: Option<UserId>,
+        category: Option<&str>,
+        sort: CollectionSort,
+        offset: i64,
+        limit: i64,
+        exec: E,
+    ) -> Result<(Vec<CollectionId>, i64), sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres> + Copy,
+    {
+        let title_pattern = title_query.map(substring_pattern);
+
+        let total_count = sqlx::query!(
+            "
+            SELECT COUNT(c.id) count FROM collections c
+            WHERE c.status = ANY($1) AND c.deleted_at IS NULL
+            AND ($2::text IS NULL OR c.title ILIKE $2)
+            AND ($3::bigint IS NULL OR c.user_id = $3)
+            AND ($4::text IS NULL OR EXISTS(
+                SELECT 1 FROM collection_categories cc
+                INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                WHERE cc.collection_id = c.id AND ct.name = $4
+            ))
+            ",
+            statuses,
+            title_pattern,
+            user_id.map(|x| x.0),
+            category,
+        )
+        .fetch_one(exec)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        // Each sort has its own query, so the ordering can use the matching index
+        let collections = match sort {
+            CollectionSort::Created => sqlx::query!(
+                "
+                SELECT c.id FROM collections c
+                WHERE c.status = ANY($1) AND c.deleted_at IS NULL
+                AND ($2::text IS NULL OR c.title ILIKE $2)
+                AND ($3::bigint IS NULL OR c.user_id = $3)
+                AND ($6::text IS NULL OR EXISTS(
+                    SELECT 1 FROM collection_categories cc
+                    INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                    WHERE cc.collection_id = c.id AND ct.name = $6
+                ))
+                ORDER BY c.created DESC, c.id DESC
+                OFFSET $4 LIMIT $5
+                ",
+                statuses,
+                title_pattern,
+                user_id.map(|x| x.0),
+                offset,
+                limit,
+                category,
+            )
+            .fetch_all(exec)
+            .await?
+            .into_iter()
+            .map(|x| CollectionId(x.id))
+            .collect(),
+            CollectionSort::Updated => sqlx::query!(
+                "
+                SELECT c.id FROM collections c
+                WHERE c.status = ANY($1) AND c.deleted_at IS NULL
+                AND ($2::text IS NULL OR c.title ILIKE $2)
+                AND ($3::bigint IS NULL OR c.user_id = $3)
+                AND ($6::text IS NULL OR EXISTS(
+                    SELECT 1 FROM collection_categories cc
+                    INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                    WHERE cc.collection_id = c.id AND ct.name = $6
+                ))
+                ORDER BY c.updated DESC, c.id DESC
+                OFFSET $4 LIMIT $5
+                ",
+                statuses,
+                title_pattern,
+                user_id.map(|x| x.0),
+                offset,
+                limit,
+                category,
+            )
+            .fetch_all(exec)
+            .await?
+            .into_iter()
+            .map(|x| CollectionId(x.id))
+            .collect(),
+            CollectionSort::Follows => sqlx::query!(
+                "
+                SELECT c.id FROM collections c
+                WHERE c.status = ANY($1) AND c.deleted_at IS NULL
+                AND ($2::text IS NULL OR c.title ILIKE $2)
+                AND ($3::bigint IS NULL OR c.user_id = $3)
+                AND ($6::text IS NULL OR EXISTS(
+                    SELECT 1 FROM collection_categories cc
+                    INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                    WHERE cc.collection_id = c.id AND ct.name = $6
+                ))
+                ORDER BY c.follows DESC, c.id DESC
+                OFFSET $4 LIMIT $5
+                ",
+                statuses,
+                title_pattern,
+                user_id.map(|x| x.0),
+                offset,
+                limit,
+                category,
+            )
+            .fetch_all(exec)
+            .await?
+            .into_iter()
+            .map(|x| CollectionId(x.id))
+            .collect(),
+            CollectionSort::Projects => sqlx::query!(
+                "
+                SELECT c.id FROM collections c
+                LEFT JOIN (
+                    SELECT cm.collection_id, COUNT(*) count FROM collections_mods cm
+                    GROUP BY cm.collection_id
+                ) cm ON cm.collection_id = c.id
+                WHERE c.status = ANY($1) AND c.deleted_at IS NULL
+                AND ($2::text IS NULL OR c.title ILIKE $2)
+                AND ($3::bigint IS NULL OR c.user_id = $3)
+                AND ($6::text IS NULL OR EXISTS(
+                    SELECT 1 FROM collection_categories cc
+                    INNER JOIN collection_tags ct ON ct.id = cc.category_id
+                    WHERE cc.collection_id = c.id AND ct.name = $6
+                ))
+                ORDER BY COALESCE(cm.count, 0) DESC, c.id DESC
+                OFFSET $4 LIMIT $5
+                ",
+                statuses,
+                title_pattern,
+                user_id.map(|x| x.0),
+                offset,
+                limit,
+                category,
+            )
+            .fetch_all(exec)
+            .await?
+            .into_iter()
+            .map(|x| CollectionId(x.id))
+            .collect(),
+        };
+
+        Ok((collections, total_count))
+    }
+
+    /// Clears the cached collection. A Redis failure is logged rather than returned, since the
+    /// database write it follows has usually succeeded. The collection is then cleared again by
+    /// `clear_stale_caches`.
+    #[tracing::instrument(level = "debug", skip_all, fields(collection_id = id.0))]
     pub async fn clear_cache(
         id: CollectionId,
         redis: &deadpool_redis::Pool,
     ) -> Result<(), DatabaseError> {
+        Collection::clear_cache_many(&[id], redis).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(count = ids.len()))]
+    pub async fn clear_cache_many(
+        ids: &[CollectionId],
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        if let Err(err) = Collection::delete_cache_keys(ids, redis).await {
+            warn!(
+                "Failed to clear the cache of {} collection(s): {err}",
+                ids.len()
+            );
+            STALE_CACHE_IDS
+                .lock()
+                .unwrap_or_else(|x| x.into_inner())
+                .extend(ids.iter().map(|x| x.0));
+        }
+
+        Ok(())
+    }
+
+    /// Retries clearing the caches of collections whose cache could not be cleared before
+    pub async fn clear_stale_caches(redis: &deadpool_redis::Pool) -> Result<(), DatabaseError> {
+        let ids = std::mem::take(&mut *STALE_CACHE_IDS.lock().unwrap_or_else(|x| x.into_inner()))
+            .into_iter()
+            .map(CollectionId)
+            .collect::<Vec<_>>();
+
+        if let Err(err) = Collection::delete_cache_keys(&ids, redis).await {
+            STALE_CACHE_IDS
+                .lock()
+                .unwrap_or_else(|x| x.into_inner())
+                .extend(ids.iter().map(|x| x.0));
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_cache_keys(
+        ids: &[CollectionId],
+        redis: &deadpool_redis::Pool,
+    ) -> Result<(), DatabaseError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
         let mut redis = redis.get().await?;
         let mut cmd = cmd("DEL");
 
-        cmd.arg(format!("{}:{}", COLLECTIONS_NAMESPACE, id.0));
+        for id in ids {
+            cmd.arg(format!("{}:{}", COLLECTIONS_NAMESPACE, id.0));
+            cmd.arg(format!("{}:{}", COLLECTIONS_ICONS_NAMESPACE, id.0));
+        }
         cmd.query_async::<_, ()>(&mut redis).await?;
 
         Ok(())
     }
 }
+
+/// Sorts loaded items into the order of the ids they were requested with, as `get_many` and the
+/// authorization filters return them in no particular order. Items whose id is not listed go last.
+pub fn sort_by_ids<T, K>(items: &mut [T], ids: &[K], key: impl Fn(&T) -> K)
+where
+    K: Eq + std::hash::Hash,
+{
+    let positions = ids
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, id)| (id, index))
+        .collect::<std::collections::HashMap<_, _>>();
+
+    items.sort_by_key(|x| positions.get(&key(x)).copied().unwrap_or(usize::MAX));
+}
+
+// Escapes LIKE wildcards so the query is matched as a plain substring
+fn substring_pattern(query: &str) -> String {
+    format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+#[derive(Clone, Debug)]
+pub enum CollectionChange {
+    StatusChange {
+        old_status: CollectionStatus,
+        new_status: CollectionStatus,
+    },
+    Transfer {
+        old_owner_id: UserId,
+        new_owner_id: UserId,
+    },
+    /// A status set by a moderator, bypassing the usual status permissions
+    Moderation {
+        old_status: CollectionStatus,
+        new_status: CollectionStatus,
+        reason: String,
+    },
+}
+
+impl CollectionChange {
+    pub fn action(&self) -> &'static str {
+        match self {
+            CollectionChange::StatusChange { .. } => "status_change",
+            CollectionChange::Transfer { .. } => "transfer",
+            CollectionChange::Moderation { .. } => "moderation",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CollectionHistoryEntry {
+    pub collection_id: CollectionId,
+    pub actor_id: UserId,
+    pub change: CollectionChange,
+    pub created: DateTime<Utc>,
+}
+
+impl CollectionHistoryEntry {
+    pub async fn insert(
+        &self,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        let (old_status, new_status, old_owner_id, new_owner_id, reason) = match &self.change {
+            CollectionChange::StatusChange {
+                old_status,
+                new_status,
+            } => (
+                Some(old_status.as_str()),
+                Some(new_status.as_str()),
+                None,
+                None,
+                None,
+            ),
+            CollectionChange::Transfer {
+                old_owner_id,
+                new_owner_id,
+            } => (None, None, Some(old_owner_id.0), Some(new_owner_id.0), None),
+            CollectionChange::Moderation {
+                old_status,
+                new_status,
+                reason,
+            } => (
+                Some(old_status.as_str()),
+                Some(new_status.as_str()),
+                None,
+                None,
+                Some(reason.as_str()),
+            ),
+        };
+
+        sqlx::query!(
+            "
+            INSERT INTO collections_history (
+                collection_id, actor_id, action, old_status, new_status,
+                old_owner_id, new_owner_id, reason, created
+            )
+            VALUES (
+                $1, $2, $3, $4, $5,
+                $6, $7, $8, $9
+            )
+            ",
+            self.collection_id as CollectionId,
+            self.actor_id as UserId,
+            self.change.action(),
+            old_status,
+            new_status,
+            old_owner_id,
+            new_owner_id,
+            reason,
+            self.created,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_for_collection<'a, E>(
+        collection_id: CollectionId,
+        exec: E,
+    ) -> Result<Vec<CollectionHistoryEntry>, DatabaseError>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        use futures::TryStreamExt;
+
+        let entries = sqlx::query!(
+            "
+            SELECT collection_id, actor_id, action, old_status, new_status,
+            old_owner_id, new_owner_id, reason, created
+            FROM collections_history
+            WHERE collection_id = $1
+            ORDER BY created ASC, id ASC
+            ",
+            collection_id as CollectionId,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async {
+            Ok(e.right().map(|m| {
+                let change = match (m.action.as_str(), m.old_owner_id, m.new_owner_id) {
+                    ("transfer", Some(old_owner_id), Some(new_owner_id)) => {
+                        CollectionChange::Transfer {
+                            old_owner_id: UserId(old_owner_id),
+                            new_owner_id: UserId(new_owner_id),
+                        }
+                    }
+                    ("moderation", _, _) => CollectionChange::Moderation {
+                        old_status: CollectionStatus::from(
+                            m.old_status.as_deref().unwrap_or_default(),
+                        ),
+                        new_status: CollectionStatus::from(
+                            m.new_status.as_deref().unwrap_or_default(),
+                        ),
+                        reason: m.reason.clone().unwrap_or_default(),
+                    },
+                    _ => CollectionChange::StatusChange {
+                        old_status: CollectionStatus::from(
+                            m.old_status.as_deref().unwrap_or_default(),
+                        ),
+                        new_status: CollectionStatus::from(
+                            m.new_status.as_deref().unwrap_or_default(),
+                        ),
+                    },
+                };
+
+                CollectionHistoryEntry {
+                    collection_id: CollectionId(m.collection_id),
+                    actor_id: UserId(m.actor_id),
+                    change,
+                    created: m.created,
+                }
+            }))
+        })
+        .try_collect::<Vec<CollectionHistoryEntry>>()
+        .await?;
+
+        Ok(entries)
+    }
+}
@@ -2,6 +2,8 @@ use super::DatabaseError;
 use crate::models::ids::base62_impl::to_base62;
 use crate::models::ids::random_base62_rng;
 use censor::Censor;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlx_macros::Type;
 
@@ -12,7 +14,7 @@ macro_rules! generate_ids {
         $vis async fn $function_name(
             con: &mut sqlx::Transaction<'_, sqlx::Postgres>,
         ) -> Result<$return_type, DatabaseError> {
-            let mut rng = rand::thread_rng();
+            let mut rng = ChaCha20Rng::from_entropy();
             let length = $id_length;
             let mut id = random_base62_rng(&mut rng, length);
             let mut retry_count = 0;
@@ -145,7 +147,7 @@ generate_ids!(
     ImageId
 );
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Type, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Type, Serialize, Deserialize)]
 #[sqlx(transparent)]
 pub struct UserId(pub i64);
 
@@ -186,7 +188,7 @@ pub struct LoaderId(pub i32);
 #[sqlx(transparent)]
 pub struct CategoryId(pub i32);
 
-#[derive(Copy, Clone, Debug, Type, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Type, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[sqlx(transparent)]
 pub struct CollectionId(pub i64);
 
@@ -6,6 +6,7 @@ use crate::models::projects::{MonetizationStatus, ProjectStatus};
 use chrono::{DateTime, Utc};
 use redis::cmd;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 const PROJECTS_NAMESPACE: &str = "projects";
 const PROJECTS_SLUGS_NAMESPACE: &str = "projects_slugs";
@@ -451,6 +452,36 @@ impl Project {
             .map(|x| x.into_iter().next())
     }
 
+    /// Returns which of the given projects exist, without loading or caching them
+    pub async fn exists_many<'a, E>(
+        project_ids: &[ProjectId],
+        exec: E,
+    ) -> Result<HashSet<ProjectId>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        if project_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let ids = project_ids.iter().map(|x| x.0).collect::<Vec<i64>>();
+
+        let existing = sqlx::query!(
+            "
+            SELECT id FROM mods
+            WHERE id = ANY($1)
+            ",
+            &ids,
+        )
+        .fetch_all(exec)
+        .await?
+        .into_iter()
+        .map(|x| ProjectId(x.id))
+        .collect();
+
+        Ok(existing)
+    }
+
     pub async fn get_many_ids<'a, E>(
         project_ids: &[ProjectId],
         exec: E,
@@ -1,5 +1,6 @@
-use super::ids::{ProjectId, UserId};
+use super::ids::{CollectionId, ProjectId, UserId};
 use crate::database::models::DatabaseError;
+use crate::models::collections::CollectionStatus;
 use crate::models::ids::base62_impl::{parse_base62, to_base62};
 use crate::models::users::{Badges, RecipientType, RecipientWallet};
 use chrono::{DateTime, Utc};
@@ -12,6 +13,18 @@ const USER_USERNAMES_NAMESPACE: &str = "users_usernames";
 // const USERS_PROJECTS_NAMESPACE: &str = "users_projects";
 const DEFAULT_EXPIRY: i64 = 1800; // 30 minutes
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserCollectionStats {
+    pub collections: i64,
+    pub listed_collections: i64,
+    /// Collections which are hidden from the public, such as private or rejected ones
+    pub private_collections: i64,
+    /// Distinct projects across all of the collections
+    pub projects: i64,
+    /// Storage used by collection icons
+    pub icon_bytes: i64,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct User {
     pub id: UserId,
@@ -294,6 +307,29 @@ impl User {
         Ok(user_pass.map(|x| UserId(x.id)))
     }
 
+    /// Whether the GitHub account of the user is banned. Users without one can't be banned.
+    pub async fn is_banned<'a, E>(&self, exec: E) -> Result<bool, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let Some(github_id) = self.github_id else {
+            return Ok(false);
+        };
+
+        let banned = sqlx::query!(
+            "
+            SELECT EXISTS(SELECT 1 FROM banned_users WHERE github_id = $1)
+            ",
+            github_id
+        )
+        .fetch_one(exec)
+        .await?
+        .exists
+        .unwrap_or(false);
+
+        Ok(banned)
+    }
+
     pub async fn get_projects<'a, E>(
         user_id: UserId,
         exec: E,
@@ -320,6 +356,183 @@ impl User {
         Ok(projects)
     }
 
+    pub async fn get_collections<'a, E>(
+        user_id: UserId,
+        statuses: &[String],
+        offset: i64,
+        limit: i64,
+        exec: E,
+    ) -> Result<(Vec<CollectionId>, i64), sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres> + Copy,
+    {
+        use futures::stream::TryStreamExt;
+
+        let total_count = sqlx::query!(
+            "
+            SELECT COUNT(c.id) count FROM collections c
+            WHERE c.user_id = $1 AND c.status = ANY($2) AND c.deleted_at IS NULL
+            ",
+            user_id as UserId,
+            statuses,
+        )
+        .fetch_one(exec)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        let collections = sqlx::query!(
+            "
+            SELECT c.id FROM collections c
+            WHERE c.user_id = $1 AND c.status = ANY($2) AND c.deleted_at IS NULL
+            ORDER BY c.created DESC, c.id DESC
+            OFFSET $3 LIMIT $4
+            ",
+            user_id as UserId,
+            statuses,
+            offset,
+            limit,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async { Ok(e.right().map(|m| CollectionId(m.id))) })
+        .try_collect::<Vec<CollectionId>>()
+        .await?;
+
+        Ok((collections, total_count))
+    }
+
+    /// The ids and last update times of all collections a user owns, without loading the
+    /// collections themselves
+    pub async fn get_collections_index<'a, E>(
+        user_id: UserId,
+        exec: E,
+    ) -> Result<Vec<(CollectionId, DateTime<Utc>)>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        use futures::stream::TryStreamExt;
+
+        let collections = sqlx::query!(
+            "
+            SELECT c.id, c.updated FROM collections c
+            WHERE c.user_id = $1 AND c.deleted_at IS NULL
+            ORDER BY c.created DESC, c.id DESC
+            ",
+            user_id as UserId,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async { Ok(e.right().map(|m| (CollectionId(m.id), m.updated))) })
+        .try_collect::<Vec<_>>()
+        .await?;
+
+        Ok(collections)
+    }
+
+    /// The collections a user has pinned, in the order they were pinned
+    pub async fn get_pinned_collections<'a, E>(
+        user_id: UserId,
+        exec: E,
+    ) -> Result<Vec<CollectionId>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres> + Copy,
+    {
+        use futures::stream::TryStreamExt;
+
+        let collections = sqlx::query!(
+            "
+            SELECT cp.collection_id FROM collection_pins cp
+            INNER JOIN collections c ON c.id = cp.collection_id
+            WHERE cp.user_id = $1 AND c.deleted_at IS NULL
+            ORDER BY cp.ordinal, cp.created
+            ",
+            user_id as UserId,
+        )
+        .fetch_many(exec)
+        .try_filter_map(|e| async { Ok(e.right().map(|m| CollectionId(m.collection_id))) })
+        .try_collect::<Vec<CollectionId>>()
+        .await?;
+
+        Ok(collections)
+    }
+
+    /// Counts the project memberships across all of a user's collections
+    pub async fn get_collected_projects_count<'a, E>(
+        user_id: UserId,
+        exec: E,
+    ) -> Result<i64, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres>,
+    {
+        let count = sqlx::query!(
+            "
+            SELECT COUNT(cm.mod_id) count FROM collections_mods cm
+            INNER JOIN collections c ON c.id = cm.collection_id
+            WHERE c.user_id = $1 AND c.deleted_at IS NULL
+            ",
+            user_id as UserId,
+        )
+        .fetch_one(exec)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        Ok(count)
+    }
+
+    /// Aggregates statistics over a user's collections with the given statuses
+    pub async fn get_collection_stats<'a, E>(
+        user_id: UserId,
+        statuses: &[String],
+        exec: E,
+    ) -> Result<UserCollectionStats, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = sqlx::Postgres> + Copy,
+    {
+        let hidden_statuses = CollectionStatus::iterator()
+            .filter(|x| x.is_hidden())
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+
+        let collections = sqlx::query!(
+            "
+            SELECT COUNT(c.id) total,
+            COUNT(c.id) FILTER (WHERE c.status = $3) listed,
+            COUNT(c.id) FILTER (WHERE c.status = ANY($4)) private,
+            SUM(c.icon_size)::bigint icon_bytes
+            FROM collections c
+            WHERE c.user_id = $1 AND c.status = ANY($2) AND c.deleted_at IS NULL
+            ",
+            user_id as UserId,
+            statuses,
+            CollectionStatus::Listed.as_str(),
+            &hidden_statuses,
+        )
+        .fetch_one(exec)
+        .await?;
+
+        let projects = sqlx::query!(
+            "
+            SELECT COUNT(DISTINCT cm.mod_id) count FROM collections_mods cm
+            INNER JOIN collections c ON c.id = cm.collection_id
+            WHERE c.user_id = $1 AND c.status = ANY($2) AND c.deleted_at IS NULL
+            ",
+            user_id as UserId,
+            statuses,
+        )
+        .fetch_one(exec)
+        .await?
+        .count
+        .unwrap_or(0);
+
+        Ok(UserCollectionStats {
+            collections: collections.total.unwrap_or(0),
+            listed_collections: collections.listed.unwrap_or(0),
+            private_collections: collections.private.unwrap_or(0),
+            projects,
+            icon_bytes: collections.icon_bytes.unwrap_or(0),
+        })
+    }
+
     pub async fn get_backup_codes<'a, E>(
         user_id: UserId,
         exec: E,
@@ -480,6 +693,72 @@ impl User {
             .execute(&mut *transaction)
             .await?;
 
+            sqlx::query!(
+                "
+                DELETE FROM collection_follows
+                WHERE follower_id = $1
+                ",
+                id as UserId,
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            sqlx::query!(
+                "
+                DELETE FROM collection_pins
+                WHERE user_id = $1
+                ",
+                id as UserId,
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            sqlx::query!(
+                "
+                DELETE FROM collection_shares
+                WHERE user_id = $1
+                ",
+                id as UserId,
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            sqlx::query!(
+                "
+                UPDATE collections_history
+                SET actor_id = $2
+                WHERE actor_id = $1
+                ",
+                id as UserId,
+                deleted_user as UserId,
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            sqlx::query!(
+                "
+                UPDATE collections_history
+                SET old_owner_id = $2
+                WHERE old_owner_id = $1
+                ",
+                id as UserId,
+                deleted_user as UserId,
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            sqlx::query!(
+                "
+                UPDATE collections_history
+                SET new_owner_id = $2
+                WHERE new_owner_id = $1
+                ",
+                id as UserId,
+                deleted_user as UserId,
+            )
+            .execute(&mut *transaction)
+            .await?;
+
             sqlx::query!(
                 "
                 DELETE FROM team_members
@@ -1,5 +1,9 @@
 use crate::file_hosting::S3Host;
 use crate::queue::analytics::AnalyticsQueue;
+use crate::queue::cdn_deletions::retry_pending_cdn_deletions;
+use crate::queue::collection_outbox::deliver_collection_outbox;
+use crate::queue::collection_views::flush_collection_views;
+use crate::queue::collections::{purge_deleted_collections, unlist_underfilled_collections};
 use crate::queue::download::DownloadQueue;
 use crate::queue::payouts::{process_payout, PayoutsQueue};
 use crate::queue::session::AuthQueue;
@@ -272,6 +276,44 @@ async fn main() -> std::io::Result<()> {
         }
     });
 
+    let pool_ref = pool.clone();
+    scheduler.run(std::time::Duration::from_secs(30), move || {
+        let pool_ref = pool_ref.clone();
+
+        async move {
+            let result = deliver_collection_outbox(&pool_ref).await;
+            if let Err(e) = result {
+                warn!("Delivering collection outbox events failed: {:?}", e);
+            }
+        }
+    });
+
+    let redis_ref = redis_pool.clone();
+    scheduler.run(std::time::Duration::from_secs(30), move || {
+        let redis_ref = redis_ref.clone();
+
+        async move {
+            let result = database::models::Collection::clear_stale_caches(&redis_ref).await;
+            if let Err(e) = result {
+                warn!("Clearing stale collection caches failed: {:?}", e);
+            }
+        }
+    });
+
+    let pool_ref = pool.clone();
+    let redis_ref = redis_pool.clone();
+    scheduler.run(std::time::Duration::from_secs(60 * 5), move || {
+        let pool_ref = pool_ref.clone();
+        let redis_ref = redis_ref.clone();
+
+        async move {
+            let result = flush_collection_views(&pool_ref, &redis_ref).await;
+            if let Err(e) = result {
+                warn!("Flushing collection views failed: {:?}", e);
+            }
+        }
+    });
+
     let session_queue = web::Data::new(AuthQueue::new());
 
     let pool_ref = pool.clone();
@@ -355,6 +397,59 @@ async fn main() -> std::io::Result<()> {
         });
     }
 
+    {
+        let pool_ref = pool.clone();
+        let redis_ref = redis_pool.clone();
+        let file_host_ref = file_host.clone();
+        scheduler.run(std::time::Duration::from_secs(60 * 60), move || {
+            let pool_ref = pool_ref.clone();
+            let redis_ref = redis_ref.clone();
+            let file_host_ref = file_host_ref.clone();
+
+            async move {
+                info!("Purging deleted collections");
+                let result =
+                    purge_deleted_collections(&pool_ref, &redis_ref, &*file_host_ref).await;
+                if let Err(e) = result {
+                    warn!("Purging deleted collections failed: {:?}", e);
+                }
+                info!("Done purging deleted collections");
+            }
+        });
+    }
+
+    {
+        let pool_ref = pool.clone();
+        let redis_ref = redis_pool.clone();
+        scheduler.run(std::time::Duration::from_secs(60 * 30), move || {
+            let pool_ref = pool_ref.clone();
+            let redis_ref = redis_ref.clone();
+
+            async move {
+                let result = unlist_underfilled_collections(&pool_ref, &redis_ref).await;
+                if let Err(e) = result {
+                    warn!("Unlisting underfilled collections failed: {:?}", e);
+                }
+            }
+        });
+    }
+
+    {
+        let pool_ref = pool.clone();
+        let file_host_ref = file_host.clone();
+        scheduler.run(std::time::Duration::from_secs(60 * 10), move || {
+            let pool_ref = pool_ref.clone();
+            let file_host_ref = file_host_ref.clone();
+
+            async move {
+                let result = retry_pending_cdn_deletions(&pool_ref, &*file_host_ref).await;
+                if let Err(e) = result {
+                    warn!("Retrying pending CDN deletions failed: {:?}", e);
+                }
+            }
+        });
+    }
+
     let ip_salt = Pepper {
         pepper: models::ids::Base62Id(models::ids::random_base62(11)).to_string(),
     };
@@ -369,6 +464,8 @@ async fn main() -> std::io::Result<()> {
     // Init App
     HttpServer::new(move || {
         App::new()
+            // Negotiates brotli, gzip or zstd from Accept-Encoding for every scope, including
+            // the v2 and v3 collection routes
             .wrap(actix_web::middleware::Compress::default())
             .wrap(
                 RateLimiter::new(MemoryStoreActor::from(store.clone()).start())
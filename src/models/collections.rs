@@ -1,5 +1,7 @@
 use super::{
-    ids::{Base62Id, ProjectId},
+    ids::{Base62Id, ProjectId, VersionId},
+    projects::Project,
+    teams::TeamId,
     users::UserId,
 };
 use crate::database;
@@ -23,14 +25,22 @@ pub struct Collection {
     pub title: String,
     /// A short description of the collection.
     pub description: String,
+    /// A unique, url-safe name the collection can be accessed by instead of its ID.
+    pub slug: Option<String>,
+    /// A long form description of the collection, in markdown.
+    pub body: String,
 
     /// An icon URL for the collection.
     pub icon_url: Option<String>,
     /// Color of the collection.
     pub color: Option<u32>,
+    /// Icon colors of the first few projects in the collection, for cover art.
+    pub colors: Vec<u32>,
 
     /// The status of the collectin (eg: whether collection is public or not)
     pub status: CollectionStatus,
+    /// The categories this collection is tagged with.
+    pub categories: Vec<String>,
 
     /// The date at which the collection was first published.
     pub created: DateTime<Utc>,
@@ -38,8 +48,150 @@ pub struct Collection {
     /// The date at which the collection was updated.
     pub updated: DateTime<Utc>,
 
+    /// The number of users following this collection.
+    pub follows: u32,
+    /// The number of times this collection was viewed, only included for its owner and
+    /// moderators.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub views: Option<u64>,
+
+    /// A project in this collection highlighted by its owner.
+    pub featured_project: Option<ProjectId>,
+    /// Incremented whenever the collection changes. Send it in an `If-Match` header when editing
+    /// to avoid overwriting someone else's changes.
+    pub version: u32,
     /// A list of ProjectIds that are in this collection.
     pub projects: Vec<ProjectId>,
+    /// Collections contained in this collection, limited to the ones visible to the viewer.
+    pub child_collections: Vec<CollectionId>,
+    /// The total number of projects in this collection, only included when `projects` is paged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_count: Option<u32>,
+
+    /// Summaries of the projects in this collection, only included when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded_projects: Option<Vec<CollectionProject>>,
+
+    /// The number of projects in this collection which the user can't see, and which are left out
+    /// of the expanded projects and groups. Only included when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden_project_count: Option<u32>,
+
+    /// The projects in this collection grouped by their owning team, only included when
+    /// requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_groups: Option<Vec<CollectionProjectGroup>>,
+
+    /// Whether the authenticated user can edit this collection, only included for authenticated
+    /// requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editable: Option<bool>,
+
+    /// The public profile of the owner, only included when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<CollectionOwner>,
+}
+
+/// The metadata of a collection, without its projects
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionMetadata {
+    pub id: CollectionId,
+    pub user: UserId,
+    pub title: String,
+    pub description: String,
+    pub status: CollectionStatus,
+    pub follows: u32,
+    /// The number of projects in this collection.
+    pub project_count: u32,
+}
+
+impl CollectionMetadata {
+    pub fn new(c: database::models::Collection, project_count: i64) -> Self {
+        Self {
+            id: c.id.into(),
+            user: c.user_id.into(),
+            title: c.title,
+            description: c.description,
+            status: c.status,
+            follows: c.follows as u32,
+            project_count: project_count as u32,
+        }
+    }
+}
+
+/// The format version of exported collection manifests, bumped on incompatible changes
+pub const COLLECTION_MANIFEST_FORMAT_VERSION: u32 = 1;
+
+/// A portable export of a collection, which can be imported as a new collection
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionManifest {
+    pub format_version: u32,
+    pub title: String,
+    pub description: String,
+    pub projects: Vec<CollectionManifestProject>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionManifestProject {
+    /// The slug of the project, or its ID if it has no slug.
+    pub slug: String,
+    /// The latest version of the project compatible with the export filters, if there is one.
+    #[serde(default)]
+    pub version_id: Option<VersionId>,
+    #[serde(default)]
+    pub version_number: Option<String>,
+}
+
+/// The public profile of a collection's owner. Only fields shown on every user's public profile
+/// are included.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionOwner {
+    pub id: UserId,
+    pub username: String,
+    pub avatar_url: Option<String>,
+}
+
+impl From<database::models::User> for CollectionOwner {
+    fn from(user: database::models::User) -> Self {
+        Self {
+            id: user.id.into(),
+            username: user.username,
+            avatar_url: user.avatar_url,
+        }
+    }
+}
+
+/// A lightweight summary of a project in a collection
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionProject {
+    pub id: ProjectId,
+    pub title: String,
+    pub icon_url: Option<String>,
+    pub project_type: String,
+    pub downloads: u32,
+}
+
+/// Projects in a collection which are owned by the same team
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionProjectGroup {
+    pub team_id: TeamId,
+    /// The owner of the team, if it still has one
+    pub owner: Option<UserId>,
+    pub project_count: u32,
+    /// The projects of the team, in collection order
+    pub projects: Vec<ProjectId>,
+}
+
+impl From<Project> for CollectionProject {
+    fn from(p: Project) -> Self {
+        Self {
+            id: p.id,
+            title: p.title,
+            icon_url: p.icon_url,
+            project_type: p.project_type,
+            downloads: p.downloads,
+        }
+    }
 }
 
 impl From<database::models::Collection> for Collection {
@@ -50,11 +202,90 @@ impl From<database::models::Collection> for Collection {
             created: c.created,
             title: c.title,
             description: c.description,
+            slug: c.slug,
+            body: c.body,
             updated: c.updated,
+            featured_project: c.featured_project.map(|x| x.into()),
+            version: c.version as u32,
             projects: c.projects.into_iter().map(|x| x.into()).collect(),
+            child_collections: c.child_collections.into_iter().map(|x| x.into()).collect(),
             icon_url: c.icon_url,
             color: c.color,
+            colors: c.colors,
             status: c.status,
+            categories: c.categories,
+            follows: c.follows as u32,
+            views: None,
+            project_count: None,
+            expanded_projects: None,
+            hidden_project_count: None,
+            project_groups: None,
+            editable: None,
+            owner: None,
+        }
+    }
+}
+
+/// A recorded change to a collection
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionHistoryEntry {
+    /// The user that made the change.
+    pub actor: UserId,
+    #[serde(flatten)]
+    pub change: CollectionChange,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CollectionChange {
+    StatusChange {
+        old_status: CollectionStatus,
+        new_status: CollectionStatus,
+    },
+    Transfer {
+        old_owner: UserId,
+        new_owner: UserId,
+    },
+    Moderation {
+        old_status: CollectionStatus,
+        new_status: CollectionStatus,
+        reason: String,
+    },
+}
+
+impl From<database::models::collection_item::CollectionHistoryEntry> for CollectionHistoryEntry {
+    fn from(e: database::models::collection_item::CollectionHistoryEntry) -> Self {
+        use database::models::collection_item::CollectionChange as DBCollectionChange;
+
+        Self {
+            actor: e.actor_id.into(),
+            change: match e.change {
+                DBCollectionChange::StatusChange {
+                    old_status,
+                    new_status,
+                } => CollectionChange::StatusChange {
+                    old_status,
+                    new_status,
+                },
+                DBCollectionChange::Transfer {
+                    old_owner_id,
+                    new_owner_id,
+                } => CollectionChange::Transfer {
+                    old_owner: old_owner_id.into(),
+                    new_owner: new_owner_id.into(),
+                },
+                DBCollectionChange::Moderation {
+                    old_status,
+                    new_status,
+                    reason,
+                } => CollectionChange::Moderation {
+                    old_status,
+                    new_status,
+                    reason,
+                },
+            },
+            created: e.created,
         }
     }
 }
@@ -62,35 +293,61 @@ impl From<database::models::Collection> for Collection {
 /// A status decides the visibility of a collection in search, URLs, and the whole site itself.
 /// Listed - collection is displayed on search, and accessible by URL (for if/when search is implemented for collections)
 /// Unlisted - collection is not displayed on search, but accessible by URL
+/// Private - collection is only accessible by its owner and the users it is shared with
 /// Rejected - collection is disabled
 #[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum CollectionStatus {
     Listed,
     Unlisted,
+    Private,
     Rejected,
     Unknown,
 }
 
+// `as_str` is the one mapping between statuses and the strings stored in the database and sent
+// over the API. Parsing goes through it as well, so the two can't drift apart.
 impl std::fmt::Display for CollectionStatus {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(fmt, "{}", self.as_str())
     }
 }
 
+impl From<&str> for CollectionStatus {
+    /// Parses a stored status, falling back to `Unknown` for unrecognized strings
+    fn from(string: &str) -> Self {
+        CollectionStatus::iterator()
+            .find(|x| x.as_str() == string)
+            .unwrap_or(CollectionStatus::Unknown)
+    }
+}
+
+impl std::str::FromStr for CollectionStatus {
+    type Err = std::convert::Infallible;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Ok(CollectionStatus::from(string))
+    }
+}
+
 impl CollectionStatus {
-    pub fn from_str(string: &str) -> CollectionStatus {
-        match string {
-            "listed" => CollectionStatus::Listed,
-            "unlisted" => CollectionStatus::Unlisted,
-            "rejected" => CollectionStatus::Rejected,
-            _ => CollectionStatus::Unknown,
-        }
+    pub fn iterator() -> impl Iterator<Item = CollectionStatus> {
+        [
+            CollectionStatus::Listed,
+            CollectionStatus::Unlisted,
+            CollectionStatus::Private,
+            CollectionStatus::Rejected,
+            CollectionStatus::Unknown,
+        ]
+        .iter()
+        .copied()
     }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             CollectionStatus::Listed => "listed",
             CollectionStatus::Unlisted => "unlisted",
+            CollectionStatus::Private => "private",
             CollectionStatus::Rejected => "rejected",
             CollectionStatus::Unknown => "unknown",
         }
@@ -100,6 +357,7 @@ impl CollectionStatus {
     pub fn is_hidden(&self) -> bool {
         match self {
             CollectionStatus::Rejected => true,
+            CollectionStatus::Private => true,
 
             CollectionStatus::Listed => false,
             CollectionStatus::Unlisted => false,
@@ -111,6 +369,7 @@ impl CollectionStatus {
         match self {
             CollectionStatus::Listed => true,
             CollectionStatus::Unlisted => true,
+            CollectionStatus::Private => true,
             CollectionStatus::Rejected => false,
             CollectionStatus::Unknown => false,
         }
@@ -120,8 +379,158 @@ impl CollectionStatus {
         match self {
             CollectionStatus::Listed => true,
             CollectionStatus::Unlisted => true,
+            CollectionStatus::Private => true,
             CollectionStatus::Rejected => false,
             CollectionStatus::Unknown => false,
         }
     }
+
+    /// Whether a collection with this status may be changed to `target`. Moderators may set any
+    /// known status, while owners may only move between requestable statuses, and not out of
+    /// a status a moderator has rejected.
+    pub fn can_transition_to(&self, target: CollectionStatus, is_mod: bool) -> bool {
+        if target == CollectionStatus::Unknown {
+            return false;
+        }
+
+        if *self == target || is_mod {
+            return true;
+        }
+
+        self.is_approved() && target.can_be_requested()
+    }
+}
+
+/// The order collections are browsed in. Each sorts descending, so the newest, most followed or
+/// largest collections come first.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionSort {
+    Created,
+    #[default]
+    Updated,
+    /// Only meaningful with collection follows enabled, otherwise every collection has 0 follows
+    Follows,
+    /// The number of projects in the collection
+    Projects,
+}
+
+impl CollectionSort {
+    pub fn from_str(string: &str) -> Option<CollectionSort> {
+        match string {
+            "created" => Some(CollectionSort::Created),
+            "updated" => Some(CollectionSort::Updated),
+            "follows" => Some(CollectionSort::Follows),
+            "projects" => Some(CollectionSort::Projects),
+            _ => None,
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// What a user a collection is shared with may change about it, besides viewing it
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct CollectionPermissions: u64 {
+        const ADD_PROJECTS = 1 << 0;
+        const REMOVE_PROJECTS = 1 << 1;
+        /// Everything besides the projects, such as the title, status and project order
+        const EDIT_METADATA = 1 << 2;
+
+        const ALL = 0b111;
+    }
+}
+
+impl CollectionPermissions {
+    /// The names of the permissions which are set, as they are reported in errors
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (CollectionPermissions::ADD_PROJECTS, "ADD_PROJECTS"),
+            (CollectionPermissions::REMOVE_PROJECTS, "REMOVE_PROJECTS"),
+            (CollectionPermissions::EDIT_METADATA, "EDIT_METADATA"),
+        ]
+        .iter()
+        .filter(|(permission, _)| self.contains(*permission))
+        .map(|(_, name)| *name)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_transition_matrix() {
+        use CollectionStatus::*;
+
+        let statuses = [Listed, Unlisted, Private, Rejected, Unknown];
+        // Rows are the current status and columns the target, in the order of `statuses`
+        let owner = [
+            [true, true, true, false, false],
+            [true, true, true, false, false],
+            [true, true, true, false, false],
+            [false, false, false, true, false],
+            [false, false, false, false, false],
+        ];
+        let moderator = [
+            [true, true, true, true, false],
+            [true, true, true, true, false],
+            [true, true, true, true, false],
+            [true, true, true, true, false],
+            [true, true, true, true, false],
+        ];
+
+        for (from, (owner_row, moderator_row)) in statuses.iter().zip(owner.iter().zip(&moderator))
+        {
+            for (to, (&owner_allowed, &moderator_allowed)) in
+                statuses.iter().zip(owner_row.iter().zip(moderator_row))
+            {
+                assert_eq!(
+                    from.can_transition_to(*to, false),
+                    owner_allowed,
+                    "owner {from} -> {to}"
+                );
+                assert_eq!(
+                    from.can_transition_to(*to, true),
+                    moderator_allowed,
+                    "moderator {from} -> {to}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn collection_status_strings_round_trip() {
+        for status in CollectionStatus::iterator() {
+            // Fails to compile when a variant is added, as a reminder to add it to `iterator`
+            match status {
+                CollectionStatus::Listed
+                | CollectionStatus::Unlisted
+                | CollectionStatus::Private
+                | CollectionStatus::Rejected
+                | CollectionStatus::Unknown => {}
+            }
+
+            assert_eq!(CollectionStatus::from(status.as_str()), status);
+            assert_eq!(status.to_string().parse::<CollectionStatus>(), Ok(status));
+            assert_eq!(
+                serde_json::to_value(status).unwrap(),
+                serde_json::Value::String(status.to_string())
+            );
+            assert_eq!(
+                serde_json::from_value::<CollectionStatus>(serde_json::Value::String(
+                    status.to_string()
+                ))
+                .unwrap(),
+                status
+            );
+        }
+
+        assert_eq!(CollectionStatus::iterator().count(), 5);
+        assert_eq!(
+            CollectionStatus::from("archived"),
+            CollectionStatus::Unknown
+        );
+    }
 }
@@ -2,7 +2,10 @@ use super::ids::Base62Id;
 use super::users::UserId;
 use crate::database::models::notification_item::Notification as DBNotification;
 use crate::database::models::notification_item::NotificationAction as DBNotificationAction;
-use crate::models::ids::{ProjectId, ReportId, TeamId, ThreadId, ThreadMessageId, VersionId};
+use crate::models::collections::CollectionStatus;
+use crate::models::ids::{
+    CollectionId, ProjectId, ReportId, TeamId, ThreadId, ThreadMessageId, VersionId,
+};
 use crate::models::projects::ProjectStatus;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -54,6 +57,25 @@ pub enum NotificationBody {
         project_id: Option<ProjectId>,
         report_id: Option<ReportId>,
     },
+    CollectionUpdate {
+        collection_id: CollectionId,
+        added_projects: Vec<ProjectId>,
+    },
+    CollectionTransfer {
+        collection_id: CollectionId,
+        old_owner_id: UserId,
+    },
+    CollectionModerated {
+        collection_id: CollectionId,
+        new_status: CollectionStatus,
+        reason: String,
+    },
+    CollectionMembershipChange {
+        collection_id: CollectionId,
+        editor_id: UserId,
+        added_count: u32,
+        removed_count: u32,
+    },
     LegacyMarkdown {
         notification_type: Option<String>,
         title: String,
@@ -137,6 +159,62 @@ impl From<DBNotification> for Notification {
                     },
                     vec![],
                 ),
+                NotificationBody::CollectionUpdate {
+                    collection_id,
+                    added_projects,
+                } => (
+                    Some("collection_update".to_string()),
+                    "A collection you follow has been updated!".to_string(),
+                    format!(
+                        "{} project(s) have been added to the collection {}",
+                        added_projects.len(),
+                        collection_id
+                    ),
+                    format!("/collection/{}", collection_id),
+                    vec![],
+                ),
+                NotificationBody::CollectionTransfer {
+                    collection_id,
+                    old_owner_id,
+                } => (
+                    Some("collection_transfer".to_string()),
+                    "A collection has been transferred to you!".to_string(),
+                    format!(
+                        "The collection {} has been transferred to you by user {}",
+                        collection_id, old_owner_id
+                    ),
+                    format!("/collection/{}", collection_id),
+                    vec![],
+                ),
+                NotificationBody::CollectionModerated {
+                    collection_id,
+                    new_status,
+                    reason,
+                } => (
+                    Some("collection_moderated".to_string()),
+                    "A moderator has changed the status of your collection!".to_string(),
+                    format!(
+                        "The collection {} has been set to {} by a moderator: {}",
+                        collection_id, new_status, reason
+                    ),
+                    format!("/collection/{}", collection_id),
+                    vec![],
+                ),
+                NotificationBody::CollectionMembershipChange {
+                    collection_id,
+                    editor_id,
+                    added_count,
+                    removed_count,
+                } => (
+                    Some("collection_membership_change".to_string()),
+                    "The projects in your collection have changed!".to_string(),
+                    format!(
+                        "User {} added {} and removed {} project(s) in the collection {}",
+                        editor_id, added_count, removed_count, collection_id
+                    ),
+                    format!("/collection/{}", collection_id),
+                    vec![],
+                ),
                 NotificationBody::LegacyMarkdown {
                     notification_type,
                     title,
@@ -0,0 +1,103 @@
+//! CDN objects whose deletion failed are recorded and retried in the background, so the
+//! database writes which made them unused don't depend on the file host being reachable.
+use crate::file_hosting::FileHost;
+use crate::routes::ApiError;
+use crate::util::metrics::PENDING_CDN_DELETIONS;
+use log::warn;
+use sqlx::PgPool;
+
+/// How many pending deletions a single sweep retries
+const SWEEP_BATCH_SIZE: i64 = 100;
+
+/// Deletes a file from the CDN, recording it for `retry_pending_cdn_deletions` if that fails
+pub async fn delete_or_defer(
+    file_id: &str,
+    file_name: &str,
+    file_host: &(dyn FileHost + Send + Sync),
+    pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    if let Err(err) = file_host.delete_file_version(file_id, file_name).await {
+        warn!("Deleting {file_name} from the CDN failed, it will be retried: {err:?}");
+
+        sqlx::query!(
+            "
+            INSERT INTO pending_cdn_deletions (file_id, file_name)
+            VALUES ($1, $2)
+            ",
+            file_id,
+            file_name,
+        )
+        .execute(pool)
+        .await?;
+
+        PENDING_CDN_DELETIONS.inc();
+    }
+
+    Ok(())
+}
+
+/// Retries the oldest pending deletions and updates the size of the backlog
+pub async fn retry_pending_cdn_deletions(
+    pool: &PgPool,
+    file_host: &(dyn FileHost + Send + Sync),
+) -> Result<(), ApiError> {
+    let pending = sqlx::query!(
+        "
+        SELECT id, file_id, file_name FROM pending_cdn_deletions
+        ORDER BY id
+        LIMIT $1
+        ",
+        SWEEP_BATCH_SIZE,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for deletion in pending {
+        match file_host
+            .delete_file_version(&deletion.file_id, &deletion.file_name)
+            .await
+        {
+            Ok(_) => {
+                sqlx::query!(
+                    "
+                    DELETE FROM pending_cdn_deletions
+                    WHERE id = $1
+                    ",
+                    deletion.id,
+                )
+                .execute(pool)
+                .await?;
+            }
+            Err(err) => {
+                warn!(
+                    "Retrying the deletion of {} from the CDN failed: {err:?}",
+                    deletion.file_name
+                );
+
+                sqlx::query!(
+                    "
+                    UPDATE pending_cdn_deletions
+                    SET attempts = attempts + 1
+                    WHERE id = $1
+                    ",
+                    deletion.id,
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+
+    let backlog = sqlx::query!(
+        "
+        SELECT COUNT(*) count FROM pending_cdn_deletions
+        "
+    )
+    .fetch_one(pool)
+    .await?
+    .count
+    .unwrap_or(0);
+    PENDING_CDN_DELETIONS.set(backlog);
+
+    Ok(())
+}
@@ -0,0 +1,443 @@
+use crate::database::models::ids::{CollectionId, ProjectId, UserId};
+use crate::database::models::notification_item::NotificationBuilder;
+use crate::database::models::DatabaseError;
+use crate::models::collections::CollectionStatus;
+use crate::models::notifications::NotificationBody;
+use crate::queue::collection_webhooks::{send_webhook, webhook_config, CollectionListedEvent};
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+
+const MAX_ATTEMPTS: i32 = 5;
+const RETRY_BASE_SECONDS: i64 = 30;
+/// Membership changes by the same editor within this window are sent as a single notification
+const MEMBERSHIP_BATCH_SECONDS: i64 = 60 * 5;
+/// The most events delivered in one run
+const DELIVERY_BATCH_SIZE: i64 = 100;
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// An event about a collection change, recorded in the collection outbox within the transaction
+/// of the change and delivered in the background by `deliver_collection_outbox`
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CollectionOutboxEvent {
+    /// Sent to the collection webhook when a collection becomes listed
+    Listed(CollectionListedEvent),
+    /// Sent to the collection webhook when a listed collection stops being listed
+    Unlisted(CollectionListedEvent),
+    /// Sent to the owner of a collection as a notification when someone they shared it with adds
+    /// or removes projects
+    MembershipChange {
+        collection_id: CollectionId,
+        owner_id: UserId,
+        editor_id: UserId,
+        added: Vec<ProjectId>,
+        removed: Vec<ProjectId>,
+    },
+}
+
+impl CollectionOutboxEvent {
+    /// Records the event, so it is delivered exactly when the transaction commits. Events with a
+    /// dedup key which was recorded before are dropped.
+    pub async fn enqueue(
+        &self,
+        dedup_key: &str,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "
+            INSERT INTO collection_outbox (dedup_key, payload)
+            VALUES ($1, $2)
+            ON CONFLICT (dedup_key) DO NOTHING
+            ",
+            dedup_key,
+            serde_json::to_value(self)?,
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Whether a change made a collection listed or unlisted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ListingChange {
+    Listed,
+    Unlisted,
+}
+
+impl ListingChange {
+    /// How changing the status of a collection affects whether it is listed
+    pub fn between(old: CollectionStatus, new: CollectionStatus) -> Option<Self> {
+        match (
+            old == CollectionStatus::Listed,
+            new == CollectionStatus::Listed,
+        ) {
+            (false, true) => Some(ListingChange::Listed),
+            (true, false) => Some(ListingChange::Unlisted),
+            _ => None,
+        }
+    }
+}
+
+/// A change to a collection, recorded by every route which changes its projects or whether it
+/// is listed, so the owner and the webhook hear about all of them
+pub struct CollectionMutation {
+    pub collection_id: CollectionId,
+    pub owner_id: UserId,
+    /// `None` for changes made by the server itself
+    pub editor_id: Option<UserId>,
+    pub listing: Option<ListingChange>,
+    pub added: Vec<ProjectId>,
+    pub removed: Vec<ProjectId>,
+}
+
+impl CollectionMutation {
+    /// Records the events of the change in its transaction. This must be called after the
+    /// collection's version was bumped, as the version keeps the dedup keys unique.
+    pub async fn enqueue(
+        self,
+        transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    ) -> Result<(), DatabaseError> {
+        // Shared editors changing the projects are reported to the owner, but owners aren't
+        // told about their own changes
+        let membership_editor = self.editor_id.filter(|editor_id| {
+            *editor_id != self.owner_id && (!self.added.is_empty() || !self.removed.is_empty())
+        });
+        let listing = self.listing.filter(|_| webhook_config().is_some());
+
+        if listing.is_none() && membership_editor.is_none() {
+            return Ok(());
+        }
+
+        let version = sqlx::query!(
+            "
+            SELECT version FROM collections
+            WHERE id = $1
+            ",
+            self.collection_id as CollectionId,
+        )
+        .fetch_one(&mut *transaction)
+        .await?
+        .version;
+        let collection_id: crate::models::ids::CollectionId = self.collection_id.into();
+
+        if let Some(listing) = listing {
+            let event = CollectionListedEvent {
+                collection_id,
+                owner_id: self.owner_id.into(),
+                timestamp: Utc::now(),
+            };
+
+            match listing {
+                ListingChange::Listed => {
+                    CollectionOutboxEvent::Listed(event)
+                        .enqueue(
+                            &format!("collection_listed:{collection_id}:{version}"),
+                            transaction,
+                        )
+                        .await?
+                }
+                ListingChange::Unlisted => {
+                    CollectionOutboxEvent::Unlisted(event)
+                        .enqueue(
+                            &format!("collection_unlisted:{collection_id}:{version}"),
+                            transaction,
+                        )
+                        .await?
+                }
+            }
+        }
+
+        if let Some(editor_id) = membership_editor {
+            CollectionOutboxEvent::MembershipChange {
+                collection_id: self.collection_id,
+                owner_id: self.owner_id,
+                editor_id,
+                added: self.added,
+                removed: self.removed,
+            }
+            .enqueue(
+                &format!("collection_membership:{collection_id}:{version}"),
+                transaction,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Claimed events are skipped by other deliveries for this long, which is longer than delivering
+/// a full batch takes. Events of a delivery which stopped midway are picked up again afterwards.
+const CLAIM_SECONDS: i64 = 60 * 30;
+
+struct PendingMembershipChange {
+    id: i64,
+    created: DateTime<Utc>,
+    owner_id: UserId,
+    added: Vec<ProjectId>,
+    removed: Vec<ProjectId>,
+    attempts: i32,
+}
+
+/// Events to deliver again later, in the column layout of the update which reschedules them
+#[derive(Default)]
+struct RescheduledEvents {
+    ids: Vec<i64>,
+    attempts: Vec<i32>,
+    next_attempts: Vec<DateTime<Utc>>,
+}
+
+impl RescheduledEvents {
+    fn push(&mut self, id: i64, attempts: i32, next_attempt: DateTime<Utc>) {
+        self.ids.push(id);
+        self.attempts.push(attempts);
+        self.next_attempts.push(next_attempt);
+    }
+}
+
+/// Delivers the events in the collection outbox, retrying failed webhooks with backoff. Events
+/// are claimed before they are delivered, so several instances can deliver at once without
+/// holding locks while webhooks are sent, and notifications are inserted in the same transaction
+/// which marks their events as processed. Webhooks are delivered at least once, with the dedup
+/// key sent along to tell redeliveries apart.
+pub async fn deliver_collection_outbox(pool: &PgPool) -> Result<(), DatabaseError> {
+    let rows = sqlx::query!(
+        "
+        UPDATE collection_outbox
+        SET next_attempt = NOW() + make_interval(secs => $2)
+        WHERE id IN (
+            SELECT id FROM collection_outbox
+            WHERE processed IS NULL AND next_attempt <= NOW()
+            ORDER BY id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, dedup_key, payload, attempts, created
+        ",
+        DELIVERY_BATCH_SIZE,
+        CLAIM_SECONDS as f64,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let webhook = webhook_config();
+    let client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let now = Utc::now();
+
+    let mut processed = Vec::new();
+    let mut rescheduled = RescheduledEvents::default();
+    let mut membership_changes = HashMap::<_, Vec<_>>::new();
+
+    for row in rows {
+        let event = match serde_json::from_value::<CollectionOutboxEvent>(row.payload) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!(
+                    "Dropping unreadable collection outbox event {}: {err}",
+                    row.dedup_key
+                );
+                processed.push(row.id);
+                continue;
+            }
+        };
+
+        let webhook_event = match &event {
+            CollectionOutboxEvent::Listed(_) => "collection_listed",
+            _ => "collection_unlisted",
+        };
+
+        match event {
+            CollectionOutboxEvent::Listed(event) | CollectionOutboxEvent::Unlisted(event) => {
+                let Some((url, secret)) = &webhook else {
+                    warn!(
+                        "Dropping collection webhook {}, the webhook URL or secret is not configured",
+                        row.dedup_key
+                    );
+                    processed.push(row.id);
+                    continue;
+                };
+
+                let attempts = row.attempts + 1;
+                match send_webhook(&client, url, secret, &row.dedup_key, webhook_event, &event)
+                    .await
+                {
+                    Ok(()) => processed.push(row.id),
+                    Err(err) if attempts >= MAX_ATTEMPTS => {
+                        warn!(
+                            "Giving up on collection webhook {} after {} attempts: {}",
+                            row.dedup_key, attempts, err
+                        );
+                        processed.push(row.id);
+                    }
+                    Err(_) => rescheduled.push(
+                        row.id,
+                        attempts,
+                        now + Duration::seconds(RETRY_BASE_SECONDS * 2_i64.pow(attempts as u32)),
+                    ),
+                }
+            }
+            CollectionOutboxEvent::MembershipChange {
+                collection_id,
+                owner_id,
+                editor_id,
+                added,
+                removed,
+            } => {
+                membership_changes
+                    .entry((collection_id, editor_id))
+                    .or_default()
+                    .push(PendingMembershipChange {
+                        id: row.id,
+                        created: row.created,
+                        owner_id,
+                        added,
+                        removed,
+                        attempts: row.attempts,
+                    });
+            }
+        }
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    // Changes are batched from the first one, so they wait until its window is over
+    let cutoff = now - Duration::seconds(MEMBERSHIP_BATCH_SECONDS);
+    for ((collection_id, editor_id), changes) in membership_changes {
+        let first_created = changes.iter().map(|x| x.created).min().unwrap_or(now);
+        if first_created > cutoff {
+            let window_end = first_created + Duration::seconds(MEMBERSHIP_BATCH_SECONDS);
+            for change in &changes {
+                rescheduled.push(change.id, change.attempts, window_end);
+            }
+            continue;
+        }
+
+        let (added, removed) = net_membership_change(
+            changes
+                .iter()
+                .map(|x| (x.added.as_slice(), x.removed.as_slice())),
+        );
+
+        if !added.is_empty() || !removed.is_empty() {
+            NotificationBuilder {
+                body: NotificationBody::CollectionMembershipChange {
+                    collection_id: collection_id.into(),
+                    editor_id: editor_id.into(),
+                    added_count: added.len() as u32,
+                    removed_count: removed.len() as u32,
+                },
+            }
+            .insert(changes[0].owner_id, &mut transaction)
+            .await?;
+        }
+
+        processed.extend(changes.iter().map(|x| x.id));
+    }
+
+    sqlx::query!(
+        "
+        UPDATE collection_outbox
+        SET processed = NOW()
+        WHERE id = ANY($1)
+        ",
+        &processed,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "
+        UPDATE collection_outbox o
+        SET attempts = r.attempts, next_attempt = r.next_attempt
+        FROM UNNEST($1::bigint[], $2::integer[], $3::timestamptz[]) AS r(id, attempts, next_attempt)
+        WHERE o.id = r.id
+        ",
+        &rescheduled.ids,
+        &rescheduled.attempts,
+        &rescheduled.next_attempts,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // Processed events are kept for a while, so their dedup keys keep rejecting redeliveries
+    sqlx::query!(
+        "
+        DELETE FROM collection_outbox
+        WHERE processed < NOW() - INTERVAL '7 days'
+        "
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// The net change of a series of membership changes, where a project added and removed again
+// cancels out
+fn net_membership_change<'a>(
+    changes: impl IntoIterator<Item = (&'a [ProjectId], &'a [ProjectId])>,
+) -> (HashSet<ProjectId>, HashSet<ProjectId>) {
+    let mut net_added = HashSet::new();
+    let mut net_removed = HashSet::new();
+
+    for (added, removed) in changes {
+        for project in added {
+            if !net_removed.remove(project) {
+                net_added.insert(*project);
+            }
+        }
+        for project in removed {
+            if !net_added.remove(project) {
+                net_removed.insert(*project);
+            }
+        }
+    }
+
+    (net_added, net_removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn membership_changes_are_netted() {
+        let (added, removed) = net_membership_change([
+            (&[ProjectId(1), ProjectId(2)][..], &[][..]),
+            (&[ProjectId(3)][..], &[ProjectId(2), ProjectId(4)][..]),
+        ]);
+
+        assert_eq!(added, HashSet::from([ProjectId(1), ProjectId(3)]));
+        assert_eq!(removed, HashSet::from([ProjectId(4)]));
+    }
+
+    #[test]
+    fn only_leaving_or_entering_listed_is_a_listing_change() {
+        use CollectionStatus::*;
+
+        assert_eq!(
+            ListingChange::between(Unlisted, Listed),
+            Some(ListingChange::Listed)
+        );
+        assert_eq!(
+            ListingChange::between(Listed, Private),
+            Some(ListingChange::Unlisted)
+        );
+        assert_eq!(ListingChange::between(Private, Unlisted), None);
+        assert_eq!(ListingChange::between(Listed, Listed), None);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::database::models::ids::CollectionId;
+use crate::database::models::DatabaseError;
+use log::warn;
+use redis::cmd;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+const VIEWS_SEEN_NAMESPACE: &str = "collection_views_seen";
+const VIEWS_PENDING_KEY: &str = "collection_views_pending";
+const VIEWS_FLUSHING_KEY: &str = "collection_views_flushing";
+/// A viewer is counted at most once per collection in this window
+const VIEW_DEDUP_SECONDS: i64 = 60 * 60;
+
+/// Counts a view of a collection in the background, so that counting never delays or fails the
+/// request the view came from
+pub fn count_collection_view(collection_id: CollectionId, ip: String, redis: deadpool_redis::Pool) {
+    actix_rt::spawn(async move {
+        if let Err(err) = record_collection_view(collection_id, &ip, &redis).await {
+            warn!(
+                "Failed to count a view of collection {}: {err}",
+                collection_id.0
+            );
+        }
+    });
+}
+
+/// Counts a view of a collection, unless the same IP viewed it within the last hour. Views are
+/// kept in Redis until `flush_collection_views` writes them to the database.
+async fn record_collection_view(
+    collection_id: CollectionId,
+    ip: &str,
+    redis: &deadpool_redis::Pool,
+) -> Result<(), DatabaseError> {
+    let mut redis = redis.get().await?;
+
+    // IPs are only kept hashed
+    let ip_hash = sha1::Sha1::from(ip).hexdigest();
+    let first_view = cmd("SET")
+        .arg(format!(
+            "{}:{}:{}",
+            VIEWS_SEEN_NAMESPACE, collection_id.0, ip_hash
+        ))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(VIEW_DEDUP_SECONDS)
+        .query_async::<_, Option<String>>(&mut redis)
+        .await?
+        .is_some();
+
+    if first_view {
+        cmd("HINCRBY")
+            .arg(VIEWS_PENDING_KEY)
+            .arg(collection_id.0)
+            .arg(1)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Adds the views counted since the last flush to the stored totals
+pub async fn flush_collection_views(
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+) -> Result<(), DatabaseError> {
+    let mut redis = redis.get().await?;
+
+    // Pending views are moved aside so new views keep being counted while they are written. If a
+    // previous flush failed, its views are written first.
+    let flushing = cmd("EXISTS")
+        .arg(VIEWS_FLUSHING_KEY)
+        .query_async::<_, bool>(&mut redis)
+        .await?;
+    if !flushing {
+        let pending = cmd("EXISTS")
+            .arg(VIEWS_PENDING_KEY)
+            .query_async::<_, bool>(&mut redis)
+            .await?;
+        if !pending {
+            return Ok(());
+        }
+
+        cmd("RENAME")
+            .arg(VIEWS_PENDING_KEY)
+            .arg(VIEWS_FLUSHING_KEY)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+    }
+
+    let views = cmd("HGETALL")
+        .arg(VIEWS_FLUSHING_KEY)
+        .query_async::<_, HashMap<i64, i64>>(&mut redis)
+        .await?;
+    let (collection_ids, counts): (Vec<i64>, Vec<i64>) = views.into_iter().unzip();
+
+    // Views of collections which were purged in the meantime are dropped
+    sqlx::query!(
+        "
+        INSERT INTO collection_views (collection_id, views)
+        SELECT v.collection_id, v.views
+        FROM UNNEST($1::bigint[], $2::bigint[]) AS v(collection_id, views)
+        INNER JOIN collections c ON c.id = v.collection_id
+        ON CONFLICT (collection_id) DO UPDATE
+        SET views = collection_views.views + EXCLUDED.views
+        ",
+        &collection_ids,
+        &counts,
+    )
+    .execute(pool)
+    .await?;
+
+    cmd("DEL")
+        .arg(VIEWS_FLUSHING_KEY)
+        .query_async::<_, ()>(&mut redis)
+        .await?;
+
+    Ok(())
+}
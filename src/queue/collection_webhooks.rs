@@ -0,0 +1,66 @@
+use crate::models::ids::{CollectionId, UserId};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+// Collection webhooks are delivered through the collection outbox, which retries failed
+// deliveries with backoff
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CollectionListedEvent {
+    pub collection_id: CollectionId,
+    pub owner_id: UserId,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Both kinds of events share a body, told apart by its `event` field
+#[derive(Serialize)]
+struct WebhookBody<'a> {
+    event: &'a str,
+    #[serde(flatten)]
+    data: &'a CollectionListedEvent,
+}
+
+/// The webhook is disabled unless both a URL and a signing secret are configured
+pub fn webhook_config() -> Option<(String, String)> {
+    let url = dotenvy::var("COLLECTION_WEBHOOK_URL")
+        .ok()
+        .filter(|x| !x.is_empty())?;
+    let secret = dotenvy::var("COLLECTION_WEBHOOK_SECRET")
+        .ok()
+        .filter(|x| !x.is_empty())?;
+
+    Some((url, secret))
+}
+
+/// Sends an event to the webhook. Redelivered events have the same `X-Labrinth-Delivery` header,
+/// so the receiver can skip events it already handled.
+pub async fn send_webhook(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    delivery_id: &str,
+    event: &str,
+    data: &CollectionListedEvent,
+) -> Result<(), String> {
+    let body = serde_json::to_vec(&WebhookBody { event, data }).map_err(|err| err.to_string())?;
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|err| err.to_string())?;
+    mac.update(&body);
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Labrinth-Signature", format!("sha256={signature}"))
+        .header("X-Labrinth-Delivery", delivery_id)
+        .body(body)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use crate::database::models::collection_item::{listed_min_projects, DELETED_RETENTION_DAYS};
+use crate::database::models::Collection;
+use crate::file_hosting::FileHost;
+use crate::queue::cdn_deletions::delete_or_defer;
+use crate::queue::collection_outbox::{CollectionMutation, ListingChange};
+use crate::routes::ApiError;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+/// Permanently removes collections that were soft-deleted longer than the restore window ago,
+/// along with their icons on the CDN.
+pub async fn purge_deleted_collections(
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+    file_host: &(dyn FileHost + Send + Sync),
+) -> Result<(), ApiError> {
+    let cutoff = Utc::now() - Duration::days(DELETED_RETENTION_DAYS);
+    let expired = Collection::get_deleted_before(cutoff, pool).await?;
+
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    let cdn_url = dotenvy::var("CDN_URL")?;
+    for icon in expired.iter().filter_map(|(_, icon)| icon.as_ref()) {
+        if let Some(icon_path) = icon.split(&format!("{cdn_url}/")).nth(1) {
+            delete_or_defer("", icon_path, file_host, pool).await?;
+        }
+    }
+
+    let ids = expired.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+
+    let mut transaction = pool.begin().await?;
+    Collection::purge_many(&ids, &mut transaction, redis).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Unlists listed collections which lost projects since they were listed, and no longer have
+/// enough of them to be listed.
+pub async fn unlist_underfilled_collections(
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+) -> Result<(), ApiError> {
+    let mut transaction = pool.begin().await?;
+
+    let unlisted = Collection::unlist_underfilled(listed_min_projects(), &mut *transaction).await?;
+    for (collection_id, owner_id) in &unlisted {
+        CollectionMutation {
+            collection_id: *collection_id,
+            owner_id: *owner_id,
+            editor_id: None,
+            listing: Some(ListingChange::Unlisted),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+        .enqueue(&mut transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    let ids = unlisted.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+    Collection::clear_cache_many(&ids, redis).await?;
+
+    Ok(())
+}
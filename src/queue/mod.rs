@@ -1,4 +1,9 @@
 pub mod analytics;
+pub mod cdn_deletions;
+pub mod collection_outbox;
+pub mod collection_views;
+pub mod collection_webhooks;
+pub mod collections;
 pub mod download;
 pub mod maxmind;
 pub mod payouts;
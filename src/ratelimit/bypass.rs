@@ -0,0 +1,84 @@
+//! Lets trusted bots read collections beyond the usual rate limits
+use crate::database::models::pat_item::PersonalAccessToken;
+use crate::models::ids::{PatId, UserId};
+use crate::models::pats::Scopes;
+use crate::util::env::parse_strings_from_var;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::{header, Method};
+use actix_web::web;
+use chrono::Utc;
+use log::info;
+use sqlx::PgPool;
+
+/// Whether a request reads collections with one of the personal access tokens listed by ID in
+/// `COLLECTION_RATE_LIMIT_BYPASS_PATS`, which can read collections without being rate limited.
+/// The token is resolved rather than trusting the IP, and every use is logged so it can be
+/// audited which bot used the allowance.
+pub async fn is_collection_read_bypass(req: &ServiceRequest) -> bool {
+    if req.method() != Method::GET || !is_collection_path(req.path()) {
+        return false;
+    }
+    let bypass_pats =
+        parse_strings_from_var("COLLECTION_RATE_LIMIT_BYPASS_PATS").unwrap_or_default();
+    if bypass_pats.is_empty() {
+        return false;
+    }
+
+    let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .filter(|x| x.starts_with("mrp_"))
+    else {
+        return false;
+    };
+    let (Some(pool), Some(redis)) = (
+        req.app_data::<web::Data<PgPool>>(),
+        req.app_data::<web::Data<deadpool_redis::Pool>>(),
+    ) else {
+        return false;
+    };
+
+    let Ok(Some(pat)) = PersonalAccessToken::get(token, &***pool, redis).await else {
+        return false;
+    };
+    let pat_id = PatId::from(pat.id);
+    if pat.expires < Utc::now()
+        || !pat.scopes.contains(Scopes::COLLECTION_READ)
+        || !bypass_pats.contains(&pat_id.to_string())
+    {
+        return false;
+    }
+
+    info!(
+        "Collection read rate limit bypassed by PAT {} of user {} for {}",
+        pat_id,
+        UserId::from(pat.user_id),
+        req.path()
+    );
+
+    true
+}
+
+// Collection reads of both API versions, such as /v3/collection/{id} and /v2/collections
+fn is_collection_path(path: &str) -> bool {
+    let mut segments = path.trim_start_matches('/').split('/');
+
+    matches!(segments.next(), Some("v2" | "v3"))
+        && matches!(segments.next(), Some("collection" | "collections"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collection_paths() {
+        assert!(is_collection_path("/v3/collection/abc"));
+        assert!(is_collection_path("/v2/collections"));
+        assert!(is_collection_path("/v3/collections/search"));
+        assert!(!is_collection_path("/v3/project/abc"));
+        assert!(!is_collection_path("/v3/collectionsx"));
+        assert!(!is_collection_path("/collection/abc"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::ratelimit::bypass::is_collection_read_bypass;
 use crate::ratelimit::errors::ARError;
 use crate::ratelimit::{ActorMessage, ActorResponse};
 use actix::dev::*;
@@ -158,6 +159,10 @@ where
                 }
             }
 
+            if is_collection_read_bypass(&req).await {
+                return srv.call(req).await;
+            }
+
             let remaining: ActorResponse = store
                 .send(ActorMessage::Get(String::from(&identifier)))
                 .await
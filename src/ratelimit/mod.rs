@@ -6,11 +6,13 @@ use std::time::Duration;
 use crate::ratelimit::errors::ARError;
 use actix::dev::*;
 
+pub mod bypass;
 pub mod errors;
 pub mod memory;
 /// The code for this module was directly taken from https://github.com/TerminalWitchcraft/actix-ratelimit
 /// with some modifications including upgrading it to Actix 4!
 pub mod middleware;
+pub mod user;
 
 /// Represents message that can be handled by a `StoreActor`
 pub enum ActorMessage {
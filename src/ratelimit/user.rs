@@ -0,0 +1,55 @@
+//! Redis-backed limits on how often a single user may perform an action
+use crate::database::models::{DatabaseError, UserId};
+use redis::cmd;
+
+const USER_LIMITS_NAMESPACE: &str = "user_limits";
+
+/// Counts one use of `action` by `user_id` against a fixed window of `window` seconds.
+///
+/// Returns `Some(seconds)` until the window resets if the user has already used the action
+/// `max` times in the current window.
+pub async fn check_user_limit(
+    action: &str,
+    user_id: UserId,
+    max: u32,
+    window: i64,
+    redis: &deadpool_redis::Pool,
+) -> Result<Option<i64>, DatabaseError> {
+    let mut redis = redis.get().await?;
+    let key = format!("{}:{}:{}", USER_LIMITS_NAMESPACE, action, user_id.0);
+
+    let count = cmd("INCR")
+        .arg(&key)
+        .query_async::<_, u32>(&mut redis)
+        .await?;
+
+    if count == 1 {
+        cmd("EXPIRE")
+            .arg(&key)
+            .arg(window)
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+    }
+
+    if count > max {
+        let ttl = cmd("TTL")
+            .arg(&key)
+            .query_async::<_, i64>(&mut redis)
+            .await?;
+
+        // A key without an expiry would never reset, so give it one
+        if ttl < 0 {
+            cmd("EXPIRE")
+                .arg(&key)
+                .arg(window)
+                .query_async::<_, ()>(&mut redis)
+                .await?;
+
+            return Ok(Some(window));
+        }
+
+        return Ok(Some(ttl));
+    }
+
+    Ok(None)
+}
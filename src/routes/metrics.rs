@@ -0,0 +1,11 @@
+use crate::util::metrics;
+use actix_web::HttpResponse;
+
+pub async fn metrics_get() -> HttpResponse {
+    match metrics::gather() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
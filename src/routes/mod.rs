@@ -2,6 +2,7 @@ use crate::file_hosting::FileHostingError;
 use crate::routes::analytics::{page_view_ingest, playtime_ingest};
 use crate::util::cors::default_cors;
 use crate::util::env::parse_strings_from_var;
+use crate::util::guards::admin_key_guard;
 use actix_cors::Cors;
 use actix_files::Files;
 use actix_web::http::StatusCode;
@@ -14,6 +15,7 @@ pub mod v3;
 mod analytics;
 mod index;
 mod maven;
+mod metrics;
 mod not_found;
 mod updates;
 
@@ -66,6 +68,12 @@ pub fn root_config(cfg: &mut web::ServiceConfig) {
             }.boxed_local()
         })
     );
+    cfg.route(
+        "metrics",
+        web::get()
+            .guard(actix_web::guard::fn_guard(admin_key_guard))
+            .to(metrics::metrics_get),
+    );
     cfg.service(
         web::scope("")
             .wrap(default_cors())
@@ -118,6 +126,14 @@ pub enum ApiError {
     PasswordStrengthCheck(#[from] zxcvbn::ZxcvbnError),
     #[error("{0}")]
     Mail(#[from] crate::auth::email::MailError),
+    #[error("The requested resource was not found")]
+    NotFound,
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Payload Too Large: {0}")]
+    PayloadTooLarge(String),
+    #[error("You are being rate-limited. Please wait {0} seconds.")]
+    RateLimited(i64),
 }
 
 impl actix_web::ResponseError for ApiError {
@@ -144,11 +160,24 @@ impl actix_web::ResponseError for ApiError {
             ApiError::PasswordHashing(..) => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::PasswordStrengthCheck(..) => StatusCode::BAD_REQUEST,
             ApiError::Mail(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::Forbidden(..) => StatusCode::FORBIDDEN,
+            ApiError::PayloadTooLarge(..) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::RateLimited(..) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(crate::models::error::ApiError {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let ApiError::RateLimited(retry_after) = self {
+            response.insert_header((
+                actix_web::http::header::RETRY_AFTER,
+                retry_after.to_string(),
+            ));
+        }
+
+        response.json(crate::models::error::ApiError {
             error: match self {
                 ApiError::Env(..) => "environment_error",
                 ApiError::SqlxDatabase(..) => "database_error",
@@ -171,6 +200,10 @@ impl actix_web::ResponseError for ApiError {
                 ApiError::PasswordStrengthCheck(..) => "strength_check_error",
                 ApiError::Mail(..) => "mail_error",
                 ApiError::Clickhouse(..) => "clickhouse_error",
+                ApiError::NotFound => "not_found",
+                ApiError::Forbidden(..) => "forbidden",
+                ApiError::PayloadTooLarge(..) => "payload_too_large",
+                ApiError::RateLimited(..) => "ratelimit_error",
             },
             description: &self.to_string(),
         })
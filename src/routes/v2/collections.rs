@@ -1,19 +1,26 @@
-use crate::auth::checks::{filter_authorized_collections, is_authorized_collection};
-use crate::auth::get_user_from_headers;
+use crate::auth::checks::{
+    filter_authorized_collections, is_authorized_collection, is_authorized_collection_edit,
+};
+use crate::auth::{get_user_from_headers, is_authorized};
 use crate::database;
 use crate::database::models::{collection_item, generate_collection_id, project_item};
 use crate::file_hosting::FileHost;
-use crate::models::collections::{Collection, CollectionStatus};
+use crate::models::collections::CollectionStatus;
 use crate::models::ids::base62_impl::parse_base62;
 use crate::models::ids::{CollectionId, ProjectId};
 use crate::models::pats::Scopes;
+use crate::models::users::UserId;
+use crate::queue::cdn_deletions::delete_or_defer;
+use crate::queue::collection_outbox::{CollectionMutation, ListingChange};
 use crate::queue::session::AuthQueue;
+use crate::ratelimit::user::check_user_limit;
 use crate::routes::ApiError;
+use crate::util::env::parse_var;
 use crate::util::routes::read_from_payload;
-use crate::util::validate::validation_errors_to_string;
+use crate::util::validate::{normalize_text, validation_errors_to_string};
 use actix_web::web::Data;
 use actix_web::{delete, get, patch, post, web, HttpRequest, HttpResponse};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use std::sync::Arc;
@@ -21,6 +28,9 @@ use validator::Validate;
 
 use super::project_creation::CreateError;
 
+// The v2 collection routes are frozen for compatibility, with the request and response shapes
+// they always had. New collection features are only exposed through v3.
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(collections_get);
     cfg.service(collection_create);
@@ -34,15 +44,126 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     );
 }
 
+/// A collection as returned from the v2 API
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LegacyCollection {
+    /// The ID of the collection, encoded as a base62 string.
+    pub id: CollectionId,
+    /// The person that has ownership of this collection.
+    pub user: UserId,
+    /// The title or name of the collection.
+    pub title: String,
+    /// A short description of the collection.
+    pub description: String,
+
+    /// An icon URL for the collection.
+    pub icon_url: Option<String>,
+    /// Color of the collection.
+    pub color: Option<u32>,
+
+    /// The status of the collectin (eg: whether collection is public or not)
+    pub status: CollectionStatus,
+
+    /// The date at which the collection was first published.
+    pub created: DateTime<Utc>,
+
+    /// The date at which the collection was updated.
+    pub updated: DateTime<Utc>,
+
+    /// A list of ProjectIds that are in this collection.
+    pub projects: Vec<ProjectId>,
+}
+
+impl From<database::models::Collection> for LegacyCollection {
+    fn from(c: database::models::Collection) -> Self {
+        Self {
+            id: c.id.into(),
+            user: c.user_id.into(),
+            created: c.created,
+            title: c.title,
+            description: c.description,
+            updated: c.updated,
+            projects: c.projects.into_iter().map(|x| x.into()).collect(),
+            icon_url: c.icon_url,
+            color: c.color,
+            status: c.status,
+        }
+    }
+}
+
+impl From<crate::models::collections::Collection> for LegacyCollection {
+    fn from(c: crate::models::collections::Collection) -> Self {
+        Self {
+            id: c.id,
+            user: c.user,
+            created: c.created,
+            title: c.title,
+            description: c.description,
+            updated: c.updated,
+            projects: c.projects,
+            icon_url: c.icon_url,
+            color: c.color,
+            status: c.status,
+        }
+    }
+}
+
+fn validate_title(title: &str) -> Result<(), validator::ValidationError> {
+    crate::util::validate::validate_name(title)?;
+    crate::util::validate::validate_display_text(title)
+}
+
+// Projects which aren't publicly visible can only be collected by their members
+async fn check_projects_collectable(
+    projects: &[project_item::QueryProject],
+    user_option: &Option<crate::models::users::User>,
+    pool: &web::Data<PgPool>,
+) -> Result<(), ApiError> {
+    for project in projects {
+        if !is_authorized(&project.inner, user_option, pool).await? {
+            return Err(ApiError::InvalidInput(format!(
+                "The specified project {} is not publicly visible and cannot be added to a collection!",
+                ProjectId::from(project.inner.id)
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// The projects limit applies to the owner of a collection, but not to moderators
+async fn check_projects_limit(
+    user: &crate::models::users::User,
+    owner_id: database::models::UserId,
+    removed: usize,
+    added: usize,
+    pool: &PgPool,
+) -> Result<(), ApiError> {
+    if user.role.is_mod() || added <= removed {
+        return Ok(());
+    }
+
+    let limit = collection_item::collection_projects_limit();
+    let current = database::models::User::get_collected_projects_count(owner_id, pool).await?;
+
+    if current - removed as i64 + added as i64 > limit {
+        return Err(ApiError::InvalidInput(format!(
+            "A user can have at most {limit} projects across all of their collections, and this one currently has {current}!"
+        )));
+    }
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Validate, Clone)]
 pub struct CollectionCreateData {
-    #[validate(
-        length(min = 3, max = 64),
-        custom(function = "crate::util::validate::validate_name")
-    )]
+    #[validate(length(min = 3), custom(function = "validate_title"))]
     /// The title or name of the project.
     pub title: String,
-    #[validate(length(min = 3, max = 255))]
+    #[validate(
+        length(min = 3, max = 255),
+        custom(function = "crate::util::validate::validate_display_text")
+    )]
     /// A short description of the collection.
     pub description: String,
     #[validate(length(max = 32))]
@@ -76,23 +197,55 @@ pub async fn collection_create(
         .validate()
         .map_err(|err| CreateError::InvalidInput(validation_errors_to_string(err, None)))?;
 
+    // Shares the limit of v3 creations
+    if !current_user.role.is_mod() {
+        if let Some(retry_after) = check_user_limit(
+            "collection_create",
+            current_user.id.into(),
+            parse_var("COLLECTION_CREATE_LIMIT").unwrap_or(30),
+            60 * 60,
+            &redis,
+        )
+        .await?
+        {
+            return Err(CreateError::RateLimited(retry_after));
+        }
+    }
+
     let mut transaction = client.begin().await?;
 
     let collection_id: CollectionId = generate_collection_id(&mut transaction).await?.into();
 
-    let initial_project_ids =
+    let initial_projects =
         project_item::Project::get_many(&collection_create_data.projects, &mut transaction, &redis)
-            .await?
-            .into_iter()
-            .map(|x| x.inner.id.into())
-            .collect::<Vec<ProjectId>>();
+            .await?;
+    let current_user_option = Some(current_user.clone());
+    check_projects_collectable(&initial_projects, &current_user_option, &client)
+        .await
+        .map_err(|err| CreateError::InvalidInput(err.to_string()))?;
+    check_projects_limit(
+        &current_user,
+        current_user.id.into(),
+        0,
+        initial_projects.len(),
+        &client,
+    )
+    .await
+    .map_err(|err| CreateError::InvalidInput(err.to_string()))?;
+
+    let initial_project_ids = initial_projects
+        .into_iter()
+        .map(|x| x.inner.id.into())
+        .collect::<Vec<ProjectId>>();
 
     let collection_builder_actual = collection_item::CollectionBuilder {
         collection_id: collection_id.into(),
         user_id: current_user.id.into(),
-        title: collection_create_data.title,
-        description: collection_create_data.description,
+        title: normalize_text(&collection_create_data.title),
+        description: normalize_text(&collection_create_data.description),
+        slug: None,
         status: CollectionStatus::Listed,
+        categories: Vec::new(),
         projects: initial_project_ids
             .iter()
             .copied()
@@ -103,8 +256,9 @@ pub async fn collection_create(
 
     let now = Utc::now();
     collection_builder_actual.insert(&mut transaction).await?;
+    database::models::Collection::update_colors(collection_id.into(), &mut transaction).await?;
 
-    let response = crate::models::collections::Collection {
+    let response = LegacyCollection {
         id: collection_id,
         user: collection_builder.user_id.into(),
         title: collection_builder.title.clone(),
@@ -152,7 +306,11 @@ pub async fn collections_get(
     .map(|x| x.1)
     .ok();
 
-    let collections = filter_authorized_collections(collections_data, &user_option, &pool).await?;
+    let collections = filter_authorized_collections(collections_data, &user_option, &pool)
+        .await?
+        .into_iter()
+        .map(LegacyCollection::from)
+        .collect::<Vec<_>>();
 
     Ok(HttpResponse::Ok().json(collections))
 }
@@ -182,7 +340,7 @@ pub async fn collection_get(
 
     if let Some(data) = collection_data {
         if is_authorized_collection(&data, &user_option).await? {
-            return Ok(HttpResponse::Ok().json(Collection::from(data)));
+            return Ok(HttpResponse::Ok().json(LegacyCollection::from(data)));
         }
     }
     Ok(HttpResponse::NotFound().body(""))
@@ -190,12 +348,12 @@ pub async fn collection_get(
 
 #[derive(Deserialize, Validate)]
 pub struct EditCollection {
+    #[validate(length(min = 3), custom(function = "validate_title"))]
+    pub title: Option<String>,
     #[validate(
-        length(min = 3, max = 64),
-        custom(function = "crate::util::validate::validate_name")
+        length(min = 3, max = 256),
+        custom(function = "crate::util::validate::validate_display_text")
     )]
-    pub title: Option<String>,
-    #[validate(length(min = 3, max = 256))]
     pub description: Option<String>,
     pub status: Option<CollectionStatus>,
     #[validate(length(max = 64))]
@@ -231,102 +389,153 @@ pub async fn collection_edit(
     let result = database::models::Collection::get(id, &**pool, &redis).await?;
 
     if let Some(collection_item) = result {
-        if !is_authorized_collection(&collection_item, &user_option).await? {
+        let Some(user) = user_option
+            .as_ref()
+            .filter(|x| is_authorized_collection_edit(&collection_item, x))
+        else {
             return Ok(HttpResponse::Unauthorized().body(""));
-        }
+        };
 
         let id = collection_item.id;
 
+        // v2 edits replace the projects of a collection with `new_projects`
+        let mut projects = collection_item.projects.clone();
+        if let Some(new_project_ids) = &new_collection.new_projects {
+            let new_projects =
+                database::models::Project::get_many(new_project_ids, &**pool, &redis).await?;
+            if let Some(project_id) = new_project_ids.iter().find(|x| {
+                !new_projects.iter().any(|project| {
+                    project.inner.id.0 as u64 == parse_base62(x).unwrap_or_default()
+                        || project
+                            .inner
+                            .slug
+                            .as_ref()
+                            .is_some_and(|slug| slug.eq_ignore_ascii_case(x))
+                })
+            }) {
+                return Err(ApiError::InvalidInput(format!(
+                    "The specified project {project_id} does not exist!"
+                )));
+            }
+            check_projects_collectable(&new_projects, &user_option, &pool).await?;
+            check_projects_limit(
+                user,
+                collection_item.user_id,
+                collection_item.projects.len(),
+                new_projects.len(),
+                &pool,
+            )
+            .await?;
+
+            projects = new_projects.into_iter().map(|x| x.inner.id).collect();
+        }
+
+        if let Some(status) = &new_collection.status {
+            if !collection_item
+                .status
+                .can_transition_to(*status, user.role.is_mod())
+            {
+                return Err(ApiError::CustomAuthentication(
+                    "You don't have permission to set this status!".to_string(),
+                ));
+            }
+
+            let min_projects = collection_item::listed_min_projects();
+            if *status == CollectionStatus::Listed
+                && collection_item.status != *status
+                && (projects.len() as i64) < min_projects
+            {
+                return Err(ApiError::InvalidInput(format!(
+                    "A collection needs at least {min_projects} projects to be listed!"
+                )));
+            }
+        }
+
         let mut transaction = pool.begin().await?;
 
         if let Some(title) = &new_collection.title {
-            sqlx::query!(
-                "
-                UPDATE collections
-                SET title = $1
-                WHERE (id = $2)
-                ",
-                title.trim(),
-                id as database::models::ids::CollectionId,
-            )
-            .execute(&mut *transaction)
-            .await?;
+            database::models::Collection::set_title(id, &normalize_text(title), &mut transaction)
+                .await?;
         }
 
         if let Some(description) = &new_collection.description {
-            sqlx::query!(
-                "
-                UPDATE collections
-                SET description = $1
-                WHERE (id = $2)
-                ",
-                description,
-                id as database::models::ids::CollectionId,
+            database::models::Collection::set_description(
+                id,
+                &normalize_text(description),
+                &mut transaction,
             )
-            .execute(&mut *transaction)
             .await?;
         }
 
+        let mut listing = None;
         if let Some(status) = &new_collection.status {
-            if let Some(user) = user_option {
-                if !(user.role.is_mod()
-                    || collection_item.status.is_approved() && status.can_be_requested())
-                {
-                    return Err(ApiError::CustomAuthentication(
-                        "You don't have permission to set this status!".to_string(),
-                    ));
+            if *status != collection_item.status {
+                listing = ListingChange::between(collection_item.status, *status);
+
+                collection_item::CollectionHistoryEntry {
+                    collection_id: id,
+                    actor_id: user.id.into(),
+                    change: collection_item::CollectionChange::StatusChange {
+                        old_status: collection_item.status,
+                        new_status: *status,
+                    },
+                    created: Utc::now(),
                 }
-
-                sqlx::query!(
-                    "
-                    UPDATE collections
-                    SET status = $1
-                    WHERE (id = $2)
-                    ",
-                    status.to_string(),
-                    id as database::models::ids::CollectionId,
-                )
-                .execute(&mut *transaction)
+                .insert(&mut transaction)
                 .await?;
             }
+
+            database::models::Collection::set_status(id, *status, &mut transaction).await?;
         }
 
-        if let Some(new_project_ids) = &new_collection.new_projects {
-            // Delete all existing projects
-            sqlx::query!(
-                "
-                DELETE FROM collections_mods
-                WHERE collection_id = $1
-                ",
-                collection_item.id as database::models::ids::CollectionId,
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        if new_collection.new_projects.is_some() {
+            let old_projects =
+                database::models::Collection::clear_projects(id, &mut transaction).await?;
+            database::models::Collection::add_projects(id, &projects, &mut transaction).await?;
+
+            added = projects
+                .iter()
+                .filter(|x| !old_projects.contains(x))
+                .copied()
+                .collect();
+            removed = old_projects
+                .into_iter()
+                .filter(|x| !projects.contains(x))
+                .collect();
+
+            database::models::Collection::notify_followers(
+                id,
+                &added,
+                Some(user.id.into()),
+                &mut transaction,
             )
-            .execute(&mut *transaction)
             .await?;
-
-            for project_id in new_project_ids {
-                let project = database::models::Project::get(project_id, &**pool, &redis)
-                    .await?
-                    .ok_or_else(|| {
-                        ApiError::InvalidInput(format!(
-                            "The specified project {project_id} does not exist!"
-                        ))
-                    })?;
-
-                // Insert- don't throw an error if it already exists
-                sqlx::query!(
-                    "
-                            INSERT INTO collections_mods (collection_id, mod_id)
-                            VALUES ($1, $2)
-                            ON CONFLICT DO NOTHING
-                            ",
-                    collection_item.id as database::models::ids::CollectionId,
-                    project.inner.id as database::models::ids::ProjectId,
-                )
-                .execute(&mut *transaction)
+            database::models::Collection::clear_removed_featured_project(id, &mut transaction)
                 .await?;
-            }
+            database::models::Collection::update_colors(id, &mut transaction).await?;
         }
 
+        if new_collection.title.is_some()
+            || new_collection.description.is_some()
+            || new_collection.status.is_some()
+            || new_collection.new_projects.is_some()
+        {
+            database::models::Collection::touch(id, &mut transaction).await?;
+        }
+
+        CollectionMutation {
+            collection_id: id,
+            owner_id: collection_item.user_id,
+            editor_id: Some(user.id.into()),
+            listing,
+            added,
+            removed,
+        }
+        .enqueue(&mut transaction)
+        .await?;
+
         database::models::Collection::clear_cache(collection_item.id, &redis).await?;
 
         transaction.commit().await?;
@@ -374,20 +583,29 @@ pub async fn collection_icon_edit(
                 ApiError::InvalidInput("The specified collection does not exist!".to_string())
             })?;
 
-        if !is_authorized_collection(&collection_item, &user_option).await? {
+        let Some(user) = user_option.filter(|x| is_authorized_collection_edit(&collection_item, x))
+        else {
             return Ok(HttpResponse::Unauthorized().body(""));
-        }
-
-        if let Some(icon) = collection_item.icon_url {
-            let name = icon.split(&format!("{cdn_url}/")).nth(1);
-
-            if let Some(icon_path) = name {
-                file_host.delete_file_version("", icon_path).await?;
+        };
+
+        // Shares the limit of v3 icon edits
+        if !user.role.is_mod() {
+            if let Some(retry_after) = check_user_limit(
+                "collection_icon_edit",
+                user.id.into(),
+                parse_var("COLLECTION_ICON_EDIT_LIMIT").unwrap_or(10),
+                60,
+                &redis,
+            )
+            .await?
+            {
+                return Err(ApiError::RateLimited(retry_after));
             }
         }
 
         let bytes =
             read_from_payload(&mut payload, 262144, "Icons must be smaller than 256KiB").await?;
+        let bytes = crate::util::img::process_icon(bytes.freeze(), &ext.ext)?;
 
         let color = crate::util::img::get_color_from_img(&bytes)?;
 
@@ -397,28 +615,52 @@ pub async fn collection_icon_edit(
             .upload_file(
                 content_type,
                 &format!("data/{}/{}.{}", collection_id, hash, ext.ext),
-                bytes.freeze(),
+                bytes,
             )
             .await?;
 
         let mut transaction = pool.begin().await?;
 
-        sqlx::query!(
-            "
-            UPDATE collections
-            SET icon_url = $1, color = $2
-            WHERE (id = $3)
-            ",
-            format!("{}/{}", cdn_url, upload_data.file_name),
-            color.map(|x| x as i32),
-            collection_item.id as database::models::ids::CollectionId,
+        let result = database::models::Collection::set_icon(
+            collection_item.id,
+            &format!("{}/{}", cdn_url, upload_data.file_name),
+            color,
+            upload_data.content_length as i64,
+            None,
+            &mut transaction,
         )
-        .execute(&mut *transaction)
-        .await?;
+        .await;
+
+        // The old icon is only deleted once the new one is committed, and re-uploading the same
+        // icon gives the same path, which stays in use either way
+        let old_icon_path = collection_item
+            .icon_url
+            .as_ref()
+            .and_then(|x| x.split(&format!("{cdn_url}/")).nth(1));
+        let reuploaded = old_icon_path == Some(upload_data.file_name.as_str());
+
+        let result = match result {
+            Ok(_) => transaction.commit().await.map_err(Into::into),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            if !reuploaded {
+                delete_or_defer(
+                    &upload_data.file_id,
+                    &upload_data.file_name,
+                    &***file_host,
+                    &pool,
+                )
+                .await?;
+            }
+            return Err(err.into());
+        }
 
-        database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+        if let Some(icon_path) = old_icon_path.filter(|_| !reuploaded) {
+            delete_or_defer("", icon_path, &***file_host, &pool).await?;
+        }
 
-        transaction.commit().await?;
+        database::models::Collection::clear_cache(collection_item.id, &redis).await?;
 
         Ok(HttpResponse::NoContent().body(""))
     } else {
@@ -455,36 +697,31 @@ pub async fn delete_collection_icon(
         .ok_or_else(|| {
             ApiError::InvalidInput("The specified collection does not exist!".to_string())
         })?;
-    if !is_authorized_collection(&collection_item, &user_option).await? {
+    if !user_option
+        .as_ref()
+        .is_some_and(|x| is_authorized_collection_edit(&collection_item, x))
+    {
         return Ok(HttpResponse::Unauthorized().body(""));
     }
 
+    let mut transaction = pool.begin().await?;
+
+    database::models::Collection::clear_icon(collection_item.id, &mut transaction).await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    // The icon is only deleted once nothing points at it
     let cdn_url = dotenvy::var("CDN_URL")?;
     if let Some(icon) = collection_item.icon_url {
         let name = icon.split(&format!("{cdn_url}/")).nth(1);
 
         if let Some(icon_path) = name {
-            file_host.delete_file_version("", icon_path).await?;
+            delete_or_defer("", icon_path, &***file_host, &pool).await?;
         }
     }
 
-    let mut transaction = pool.begin().await?;
-
-    sqlx::query!(
-        "
-        UPDATE collections
-        SET icon_url = NULL, color = NULL
-        WHERE (id = $1)
-        ",
-        collection_item.id as database::models::ids::CollectionId,
-    )
-    .execute(&mut *transaction)
-    .await?;
-
-    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
-
-    transaction.commit().await?;
-
     Ok(HttpResponse::NoContent().body(""))
 }
 
@@ -514,13 +751,28 @@ pub async fn collection_delete(
         .ok_or_else(|| {
             ApiError::InvalidInput("The specified collection does not exist!".to_string())
         })?;
-    if !is_authorized_collection(&collection, &user_option).await? {
+    let Some(user) = user_option
+        .as_ref()
+        .filter(|x| is_authorized_collection_edit(&collection, x))
+    else {
         return Ok(HttpResponse::Unauthorized().body(""));
-    }
+    };
     let mut transaction = pool.begin().await?;
 
     let result =
         database::models::Collection::remove(collection.id, &mut transaction, &redis).await?;
+    if result.is_some() {
+        CollectionMutation {
+            collection_id: collection.id,
+            owner_id: collection.user_id,
+            editor_id: Some(user.id.into()),
+            listing: ListingChange::between(collection.status, CollectionStatus::Unlisted),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+        .enqueue(&mut transaction)
+        .await?;
+    }
     database::models::Collection::clear_cache(collection.id, &redis).await?;
 
     transaction.commit().await?;
@@ -12,7 +12,7 @@ mod tags;
 mod teams;
 mod threads;
 mod users;
-mod version_creation;
+pub(crate) mod version_creation;
 mod version_file;
 mod versions;
 
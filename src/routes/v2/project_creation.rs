@@ -77,6 +77,8 @@ pub enum CreateError {
     CustomAuthenticationError(String),
     #[error("Image Parsing Error: {0}")]
     ImageError(#[from] ImageError),
+    #[error("You are being rate-limited. Please wait {0} seconds.")]
+    RateLimited(i64),
 }
 
 impl actix_web::ResponseError for CreateError {
@@ -102,11 +104,21 @@ impl actix_web::ResponseError for CreateError {
             CreateError::ValidationError(..) => StatusCode::BAD_REQUEST,
             CreateError::FileValidationError(..) => StatusCode::BAD_REQUEST,
             CreateError::ImageError(..) => StatusCode::BAD_REQUEST,
+            CreateError::RateLimited(..) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(ApiError {
+        let mut response = HttpResponse::build(self.status_code());
+
+        if let CreateError::RateLimited(retry_after) = self {
+            response.insert_header((
+                actix_web::http::header::RETRY_AFTER,
+                retry_after.to_string(),
+            ));
+        }
+
+        response.json(ApiError {
             error: match self {
                 CreateError::EnvError(..) => "environment_error",
                 CreateError::SqlxDatabaseError(..) => "database_error",
@@ -128,6 +140,7 @@ impl actix_web::ResponseError for CreateError {
                 CreateError::ValidationError(..) => "invalid_input",
                 CreateError::FileValidationError(..) => "invalid_input",
                 CreateError::ImageError(..) => "invalid_image",
+                CreateError::RateLimited(..) => "ratelimit_error",
             },
             description: &self.to_string(),
         })
@@ -145,7 +158,7 @@ fn default_requested_status() -> ProjectStatus {
 #[derive(Serialize, Deserialize, Validate, Clone)]
 struct ProjectCreateData {
     #[validate(
-        length(min = 3, max = 64),
+        length(min = 3),
         custom(function = "crate::util::validate::validate_name")
     )]
     #[serde(alias = "mod_name")]
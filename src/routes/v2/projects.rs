@@ -278,7 +278,7 @@ pub async fn dependency_list(
 #[derive(Deserialize, Validate)]
 pub struct EditProject {
     #[validate(
-        length(min = 3, max = 64),
+        length(min = 3),
         custom(function = "crate::util::validate::validate_name")
     )]
     pub title: Option<String>,
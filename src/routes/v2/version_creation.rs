@@ -46,7 +46,7 @@ pub struct InitialVersionData {
     )]
     pub version_number: String,
     #[validate(
-        length(min = 1, max = 64),
+        length(min = 1),
         custom(function = "crate::util::validate::validate_name")
     )]
     #[serde(alias = "name")]
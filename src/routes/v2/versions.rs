@@ -280,7 +280,7 @@ pub async fn version_get(
 #[derive(Serialize, Deserialize, Validate)]
 pub struct EditVersion {
     #[validate(
-        length(min = 1, max = 64),
+        length(min = 1),
         custom(function = "crate::util::validate::validate_name")
     )]
     pub name: Option<String>,
@@ -0,0 +1,246 @@
+use crate::auth::checks::{
+    filter_authorized_child_collections, filter_authorized_projects, filter_authorized_versions,
+    is_authorized_collection,
+};
+use crate::auth::get_user_from_headers;
+use crate::database;
+use crate::database::models::collection_item;
+use crate::models::collections::{
+    Collection, CollectionManifest, CollectionManifestProject, CollectionStatus,
+    COLLECTION_MANIFEST_FORMAT_VERSION,
+};
+use crate::models::ids::CollectionId;
+use crate::models::pats::Scopes;
+use crate::queue::session::AuthQueue;
+use crate::routes::ApiError;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{resolve_collection_id, version_supports};
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionIndexEntry {
+    pub id: CollectionId,
+    pub updated: DateTime<Utc>,
+}
+
+/// Lists the ids and last update times of all of the authenticated user's collections, so a
+/// client can fetch only the ones which changed since it last synced
+pub async fn user_collections_index(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let index = database::models::User::get_collections_index(user.id.into(), &**pool)
+        .await?
+        .into_iter()
+        .map(|(id, updated)| CollectionIndexEntry {
+            id: id.into(),
+            updated,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(index))
+}
+
+/// The number of collections loaded at a time while exporting a user's collections
+const COLLECTIONS_EXPORT_PAGE_SIZE: i64 = 100;
+
+// Streams all of the authenticated user's collections, as one JSON object per line. They are
+// loaded a page at a time, so users with many collections don't need them all buffered at once.
+pub async fn user_collections_export(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let user_id: database::models::UserId = user.id.into();
+    let statuses = CollectionStatus::iterator()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>();
+
+    let user_option = Some(user);
+    let pages = futures::stream::try_unfold(Some(0), move |offset| {
+        let pool = pool.clone();
+        let redis = redis.clone();
+        let statuses = statuses.clone();
+        let user_option = user_option.clone();
+
+        async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+
+            let (collection_ids, _) = database::models::User::get_collections(
+                user_id,
+                &statuses,
+                offset,
+                COLLECTIONS_EXPORT_PAGE_SIZE,
+                &**pool,
+            )
+            .await?;
+            let mut collections =
+                database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+            collection_item::sort_by_ids(&mut collections, &collection_ids, |x| x.id);
+
+            // Only the API representation is exported, which leaves out who it is shared with.
+            // Projects and child collections the user can't see are left out as well, as they
+            // belong to other users.
+            let mut collections = collections
+                .into_iter()
+                .map(Collection::from)
+                .collect::<Vec<_>>();
+            filter_authorized_child_collections(&mut collections, &user_option, &pool).await?;
+
+            let mut project_ids = collections
+                .iter()
+                .flat_map(|x| x.projects.iter().map(|y| (*y).into()))
+                .collect::<Vec<database::models::ProjectId>>();
+            project_ids.sort_unstable_by_key(|x| x.0);
+            project_ids.dedup();
+            let projects =
+                database::models::Project::get_many_ids(&project_ids, &**pool, &redis).await?;
+            let visible_projects = filter_authorized_projects(projects, &user_option, &pool)
+                .await?
+                .into_iter()
+                .map(|x| database::models::ProjectId::from(x.id))
+                .collect::<std::collections::HashSet<_>>();
+
+            let mut body = Vec::new();
+            for mut collection in collections {
+                collection
+                    .projects
+                    .retain(|x| visible_projects.contains(&(*x).into()));
+                collection.featured_project = collection
+                    .featured_project
+                    .filter(|x| visible_projects.contains(&(*x).into()));
+
+                serde_json::to_writer(&mut body, &collection)?;
+                body.push(b'\n');
+            }
+
+            let next_offset = (collection_ids.len() as i64 == COLLECTIONS_EXPORT_PAGE_SIZE)
+                .then_some(offset + COLLECTIONS_EXPORT_PAGE_SIZE);
+
+            Ok::<_, ApiError>(Some((bytes::Bytes::from(body), next_offset)))
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"collections.ndjson\"",
+        ))
+        .streaming(pages))
+}
+
+#[derive(Deserialize)]
+pub struct CollectionExportQuery {
+    /// Only versions supporting this game version are exported
+    pub game_version: Option<String>,
+    /// Only versions supporting this loader are exported
+    pub loader: Option<String>,
+}
+
+pub async fn collection_export(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionExportQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let collection =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    // Unlisted collections can be viewed by anyone with the link, but only listed ones can be
+    // exported anonymously
+    if (user_option.is_none() && collection.status != CollectionStatus::Listed)
+        || !is_authorized_collection(&collection, &user_option).await?
+    {
+        return Err(ApiError::NotFound);
+    }
+
+    let projects =
+        database::models::Project::get_many_ids(&collection.projects, &**pool, &redis).await?;
+    let mut projects = filter_authorized_projects(projects, &user_option, &pool).await?;
+    collection_item::sort_by_ids(&mut projects, &collection.projects, |x| x.id.into());
+
+    let version_ids = projects
+        .iter()
+        .flat_map(|x| x.versions.iter().map(|y| (*y).into()))
+        .collect::<Vec<database::models::ids::VersionId>>();
+    let versions = database::models::Version::get_many(&version_ids, &**pool, &redis)
+        .await?
+        .into_iter()
+        .filter(|x| version_supports(x, query.loader.as_ref(), query.game_version.as_ref()))
+        .collect::<Vec<_>>();
+    let versions = filter_authorized_versions(versions, &user_option, &pool).await?;
+
+    let projects = projects
+        .into_iter()
+        .map(|project| {
+            let version = versions
+                .iter()
+                .filter(|x| x.project_id == project.id)
+                .max_by_key(|x| x.date_published);
+            let id = project.id;
+
+            CollectionManifestProject {
+                slug: project.slug.unwrap_or_else(|| id.to_string()),
+                version_id: version.map(|x| x.id),
+                version_number: version.map(|x| x.version_number.clone()),
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(CollectionManifest {
+        format_version: COLLECTION_MANIFEST_FORMAT_VERSION,
+        title: collection.title,
+        description: collection.description,
+        projects,
+    }))
+}
@@ -0,0 +1,624 @@
+use crate::auth::checks::{
+    filter_authorized_projects, is_authorized_collection, is_authorized_collection_edit,
+};
+use crate::auth::get_user_from_headers;
+use crate::database;
+use crate::database::models::collection_item;
+use crate::file_hosting::FileHost;
+use crate::models::ids::CollectionId;
+use crate::models::pats::Scopes;
+use crate::queue::cdn_deletions::delete_or_defer;
+use crate::queue::session::AuthQueue;
+use crate::ratelimit::user::check_user_limit;
+use crate::routes::ApiError;
+use crate::util::env::parse_var;
+use crate::util::routes::read_from_payload;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use super::{collection_cache_control, edit_permission_error, resolve_collection_id};
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionIconQuery {
+    pub ext: String,
+    /// The original name of the icon file, kept for downloads
+    pub filename: Option<String>,
+}
+
+/// Reduces an uploaded file name to its last path component made of a conservative set of
+/// characters, so it can be quoted in a `Content-Disposition` header as is. Returns nothing
+/// when no usable name remains.
+pub(super) fn sanitize_icon_filename(filename: &str) -> Option<String> {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ' '))
+        .take(255)
+        .collect::<String>();
+    let name = name.trim().trim_start_matches('.');
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+// Loads a collection for icon retrieval if the user is allowed to see it
+async fn get_icon_collection(
+    req: &HttpRequest,
+    info: web::Path<(String,)>,
+    pool: &web::Data<PgPool>,
+    redis: &web::Data<deadpool_redis::Pool>,
+    session_queue: &web::Data<AuthQueue>,
+) -> Result<Option<database::models::Collection>, ApiError> {
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, pool).await?;
+    let collection_data = database::models::Collection::get(id, &***pool, redis).await?;
+    let user_option = get_user_from_headers(
+        req,
+        &***pool,
+        redis,
+        session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    if let Some(collection) = collection_data {
+        if is_authorized_collection(&collection, &user_option).await? {
+            return Ok(Some(collection));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Icons can't be uploaded any bigger than this, so larger downloads are cut off
+const ICON_MAX_SIZE: usize = 262144;
+const ICON_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn icon_client() -> Result<reqwest::Client, ApiError> {
+    reqwest::Client::builder()
+        .timeout(ICON_FETCH_TIMEOUT)
+        .build()
+        .map_err(|err| crate::file_hosting::FileHostingError::from(err).into())
+}
+
+// Downloads an icon from the CDN with its content type. Returns nothing when the CDN doesn't
+// have the icon, and fails on icons bigger than any upload.
+pub(super) async fn fetch_icon(
+    icon_url: &str,
+) -> Result<Option<(Option<String>, bytes::Bytes)>, ApiError> {
+    let fetch_error =
+        |err: reqwest::Error| ApiError::from(crate::file_hosting::FileHostingError::from(err));
+
+    let mut response = icon_client()?
+        .get(icon_url)
+        .send()
+        .await
+        .map_err(fetch_error)?;
+
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+
+    let too_large =
+        || ApiError::InvalidInput(format!("The icon at {icon_url} is larger than 256KiB"));
+    if response
+        .content_length()
+        .is_some_and(|x| x > ICON_MAX_SIZE as u64)
+    {
+        return Err(too_large());
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_string());
+
+    let mut bytes = bytes::BytesMut::new();
+    while let Some(chunk) = response.chunk().await.map_err(fetch_error)? {
+        if bytes.len() + chunk.len() > ICON_MAX_SIZE {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(Some((content_type, bytes.freeze())))
+}
+
+// Composes an icon from the first member project icons, which is cached until the collection
+// changes. Only projects everyone can see are shown, so the icon is the same for every viewer.
+// Returns nothing when no such project has an icon.
+async fn get_generated_icon(
+    collection: &database::models::Collection,
+    pool: &web::Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+) -> Result<Option<Vec<u8>>, ApiError> {
+    if let Some(icon) = database::models::Collection::get_cached_icon(collection.id, redis).await? {
+        return Ok(Some(icon));
+    }
+
+    let mut projects = filter_authorized_projects(
+        database::models::Project::get_many_ids(&collection.projects, &***pool, redis).await?,
+        &None,
+        pool,
+    )
+    .await?;
+    collection_item::sort_by_ids(&mut projects, &collection.projects, |x| x.id.into());
+    let icon_urls = projects
+        .into_iter()
+        .filter_map(|x| x.icon_url)
+        .take(4)
+        .collect::<Vec<_>>();
+
+    if icon_urls.is_empty() {
+        return Ok(None);
+    }
+
+    let mut icons = Vec::new();
+    // Icons which fail to download are left as empty tiles
+    for icon_url in icon_urls {
+        let icon = match fetch_icon(&icon_url).await {
+            Ok(Some((_, icon))) => icon,
+            _ => bytes::Bytes::new(),
+        };
+        icons.push(icon);
+    }
+
+    let icon = crate::util::img::composite_icons(&icons)?;
+    database::models::Collection::set_cached_icon(collection.id, &icon, redis).await?;
+
+    Ok(Some(icon))
+}
+
+// Collections with a custom icon redirect to it, others are given a generated icon
+pub async fn collection_icon_get(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(collection) = get_icon_collection(&req, info, &pool, &redis, &session_queue).await?
+    else {
+        return Err(ApiError::NotFound);
+    };
+
+    if let Some(icon_url) = &collection.icon_url {
+        return Ok(HttpResponse::TemporaryRedirect()
+            .append_header(("location", &**icon_url))
+            .body(""));
+    }
+
+    let Some(icon) = get_generated_icon(&collection, &pool, &redis).await? else {
+        return Err(ApiError::NotFound);
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header((
+            header::CACHE_CONTROL,
+            collection_cache_control(collection.status, false),
+        ))
+        .body(icon))
+}
+
+// Describes a collection's icon without sending it. Custom icons are described by the CDN's
+// metadata, so neither the server nor the client downloads them.
+pub async fn collection_icon_head(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(collection) = get_icon_collection(&req, info, &pool, &redis, &session_queue).await?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    let (content_type, content_length) = if let Some(icon_url) = &collection.icon_url {
+        let response = icon_client()?
+            .head(icon_url)
+            .send()
+            .await
+            .map_err(crate::file_hosting::FileHostingError::from)?;
+
+        if !response.status().is_success() {
+            return Ok(HttpResponse::NotFound().finish());
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let Some(content_length) = response.content_length() else {
+            return Ok(HttpResponse::NotFound().finish());
+        };
+
+        (content_type, content_length)
+    } else if let Some(icon) = get_generated_icon(&collection, &pool, &redis).await? {
+        ("image/png".to_string(), icon.len() as u64)
+    } else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    // An empty stream with a fixed length keeps the Content-Length header instead of
+    // replacing it with the length of the empty body
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .no_chunking(content_length)
+        .streaming(futures::stream::empty::<Result<bytes::Bytes, ApiError>>()))
+}
+
+// Proxies the icon so it can be served under its original file name. Icons uploaded without a
+// name are named after their CDN path, and generated icons after the collection.
+pub async fn collection_icon_download(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(collection) = get_icon_collection(&req, info, &pool, &redis, &session_queue).await?
+    else {
+        return Err(ApiError::NotFound);
+    };
+
+    let (content_type, filename, icon) = if let Some(icon_url) = &collection.icon_url {
+        let Some((content_type, icon)) = fetch_icon(icon_url).await? else {
+            return Err(ApiError::NotFound);
+        };
+
+        let content_type = content_type.unwrap_or_else(|| "application/octet-stream".to_string());
+        let filename = collection
+            .icon_filename
+            .clone()
+            .or_else(|| sanitize_icon_filename(icon_url))
+            .unwrap_or_else(|| "icon".to_string());
+
+        (content_type, filename, icon)
+    } else if let Some(icon) = get_generated_icon(&collection, &pool, &redis).await? {
+        (
+            "image/png".to_string(),
+            format!("{}.png", CollectionId::from(collection.id)),
+            bytes::Bytes::from(icon),
+        )
+    } else {
+        return Err(ApiError::NotFound);
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"{filename}\""),
+        ))
+        .body(icon))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn collection_icon_edit(
+    web::Query(ext): web::Query<CollectionIconQuery>,
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    file_host: web::Data<Arc<dyn FileHost + Send + Sync>>,
+    mut payload: web::Payload,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    if let Some(content_type) = crate::util::ext::get_image_content_type(&ext.ext) {
+        let collection_item =
+            get_icon_editable_collection(&req, &info.into_inner().0, &pool, &redis, &session_queue)
+                .await?;
+
+        let bytes =
+            read_from_payload(&mut payload, 262144, "Icons must be smaller than 256KiB").await?;
+
+        set_collection_icon(
+            collection_item,
+            bytes.freeze(),
+            &ext.ext,
+            content_type,
+            ext.filename.as_deref(),
+            &pool,
+            &redis,
+            &file_host,
+        )
+        .await?;
+
+        Ok(HttpResponse::NoContent().body(""))
+    } else {
+        Err(ApiError::InvalidInput(format!(
+            "Invalid format for collection icon: {}",
+            ext.ext
+        )))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionIconUrl {
+    /// A public https URL of the new icon
+    pub url: String,
+}
+
+/// Sets the icon of a collection to an image fetched from a URL. The format is taken from the
+/// content type of the response, falling back to the extension of the URL.
+pub async fn collection_icon_edit_from_url(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    file_host: web::Data<Arc<dyn FileHost + Send + Sync>>,
+    body: web::Json<CollectionIconUrl>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let collection_item =
+        get_icon_editable_collection(&req, &info.into_inner().0, &pool, &redis, &session_queue)
+            .await?;
+
+    let file = crate::util::remote::fetch_public_file(
+        &body.url,
+        262144,
+        "Icons must be smaller than 256KiB",
+    )
+    .await?;
+
+    let filename = file
+        .url
+        .path_segments()
+        .and_then(|mut x| x.next_back())
+        .map(|x| {
+            urlencoding::decode(x)
+                .map(|x| x.into_owned())
+                .unwrap_or_default()
+        });
+    let ext = file
+        .content_type
+        .as_deref()
+        .and_then(crate::util::ext::get_image_ext)
+        .map(|x| x.to_string())
+        .or_else(|| {
+            filename
+                .as_deref()
+                .and_then(|x| x.rsplit_once('.'))
+                .map(|x| x.1.to_lowercase())
+        });
+
+    let Some((ext, content_type)) = ext.and_then(|ext| {
+        crate::util::ext::get_image_content_type(&ext).map(|content_type| (ext, content_type))
+    }) else {
+        return Err(ApiError::InvalidInput(format!(
+            "{} does not point to a supported image",
+            file.url
+        )));
+    };
+
+    set_collection_icon(
+        collection_item,
+        file.bytes,
+        &ext,
+        content_type,
+        filename.as_deref(),
+        &pool,
+        &redis,
+        &file_host,
+    )
+    .await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+// Gets a collection whose icon the user is about to change, checking their permission and the
+// icon edit rate limit
+async fn get_icon_editable_collection(
+    req: &HttpRequest,
+    string: &str,
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+    session_queue: &AuthQueue,
+) -> Result<database::models::Collection, ApiError> {
+    let user_option = get_user_from_headers(
+        req,
+        pool,
+        redis,
+        session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let id = resolve_collection_id(string, pool).await?;
+    let collection_item = database::models::Collection::get(id, pool, redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+
+    let Some(user) = user_option.filter(|x| is_authorized_collection_edit(&collection_item, x))
+    else {
+        return Err(edit_permission_error());
+    };
+
+    if !user.role.is_mod() {
+        if let Some(retry_after) = check_user_limit(
+            "collection_icon_edit",
+            user.id.into(),
+            parse_var("COLLECTION_ICON_EDIT_LIMIT").unwrap_or(10),
+            60,
+            redis,
+        )
+        .await?
+        {
+            return Err(ApiError::RateLimited(retry_after));
+        }
+    }
+
+    Ok(collection_item)
+}
+
+// Processes and uploads a new icon, then points the collection at it and deletes its old icon
+#[allow(clippy::too_many_arguments)]
+async fn set_collection_icon(
+    collection_item: database::models::Collection,
+    bytes: bytes::Bytes,
+    ext: &str,
+    content_type: &str,
+    filename: Option<&str>,
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+    file_host: &Arc<dyn FileHost + Send + Sync>,
+) -> Result<(), ApiError> {
+    let cdn_url = dotenvy::var("CDN_URL")?;
+
+    let bytes = crate::util::img::process_icon(bytes, ext)?;
+
+    let color = crate::util::img::get_color_from_img(&bytes)?;
+
+    // The new icon is uploaded before anything is written, so a failed upload leaves the
+    // current icon in place. The old icon is only deleted once the new one is committed.
+    let hash = sha1::Sha1::from(&bytes).hexdigest();
+    let collection_id: CollectionId = collection_item.id.into();
+    let upload_data = file_host
+        .upload_file(
+            content_type,
+            &format!("data/{}/{}.{}", collection_id, hash, ext),
+            bytes,
+        )
+        .await?;
+
+    let mut transaction = pool.begin().await?;
+
+    let result = database::models::Collection::set_icon(
+        collection_item.id,
+        &format!("{}/{}", cdn_url, upload_data.file_name),
+        color,
+        upload_data.content_length as i64,
+        filename.and_then(sanitize_icon_filename).as_deref(),
+        &mut transaction,
+    )
+    .await;
+
+    // Re-uploading the same icon gives the same path, which stays in use either way
+    let old_icon_path = collection_item
+        .icon_url
+        .as_ref()
+        .and_then(|x| x.split(&format!("{cdn_url}/")).nth(1));
+    let reuploaded = old_icon_path == Some(upload_data.file_name.as_str());
+
+    let result = match result {
+        Ok(_) => transaction.commit().await.map_err(Into::into),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        if !reuploaded {
+            delete_or_defer(
+                &upload_data.file_id,
+                &upload_data.file_name,
+                &**file_host,
+                pool,
+            )
+            .await?;
+        }
+        return Err(err.into());
+    }
+
+    if let Some(icon_path) = old_icon_path.filter(|_| !reuploaded) {
+        delete_or_defer("", icon_path, &**file_host, pool).await?;
+    }
+
+    database::models::Collection::clear_cache(collection_item.id, redis).await?;
+
+    Ok(())
+}
+
+pub async fn delete_collection_icon(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    file_host: web::Data<Arc<dyn FileHost + Send + Sync>>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item = database::models::Collection::get(id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+    if !user_option
+        .as_ref()
+        .map(|x| is_authorized_collection_edit(&collection_item, x))
+        .unwrap_or(false)
+    {
+        return Err(edit_permission_error());
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    database::models::Collection::clear_icon(collection_item.id, &mut transaction).await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    // The icon is only deleted once nothing points at it. A failed deletion is retried later
+    // rather than failing the request.
+    let cdn_url = dotenvy::var("CDN_URL")?;
+    if let Some(icon) = collection_item.icon_url {
+        let name = icon.split(&format!("{cdn_url}/")).nth(1);
+
+        if let Some(icon_path) = name {
+            delete_or_defer("", icon_path, &***file_host, &pool).await?;
+        }
+    }
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icon_filenames_are_sanitized() {
+        assert_eq!(
+            sanitize_icon_filename("My Icon.png").as_deref(),
+            Some("My Icon.png")
+        );
+        assert_eq!(
+            sanitize_icon_filename("C:\\icons\\../logo.webp").as_deref(),
+            Some("logo.webp")
+        );
+        assert_eq!(
+            sanitize_icon_filename("a\"\r\nSet-Cookie: x.png").as_deref(),
+            Some("aSet-Cookie x.png")
+        );
+        assert_eq!(sanitize_icon_filename("../.."), None);
+        assert_eq!(sanitize_icon_filename(""), None);
+    }
+}
@@ -0,0 +1,5160 @@
+use crate::auth::checks::{
+    collection_edit_permissions, filter_authorized_child_collections,
+    filter_authorized_collections, filter_authorized_projects, filter_authorized_resources,
+    filter_authorized_versions, is_authorized_collection, is_authorized_collection_edit,
+    AuthorizedResource, ResourceId,
+};
+use crate::auth::{check_is_moderator_from_headers, get_user_from_headers, is_authorized};
+use crate::database;
+use crate::database::models::notification_item::NotificationBuilder;
+use crate::database::models::{collection_item, generate_collection_id, project_item};
+use crate::file_hosting::FileHost;
+use crate::models::collections::{
+    Collection, CollectionHistoryEntry, CollectionManifest, CollectionOwner, CollectionPermissions,
+    CollectionProject, CollectionProjectGroup, CollectionStatus,
+    COLLECTION_MANIFEST_FORMAT_VERSION,
+};
+use crate::models::ids::base62_impl::parse_base62;
+use crate::models::ids::{CollectionId, ProjectId};
+use crate::models::notifications::NotificationBody;
+use crate::models::pats::Scopes;
+use crate::models::projects::{ProjectStatus, SearchRequest};
+use crate::queue::cdn_deletions::delete_or_defer;
+use crate::queue::collection_outbox::{CollectionMutation, ListingChange};
+use crate::queue::collection_views::count_collection_view;
+use crate::queue::session::AuthQueue;
+use crate::ratelimit::user::check_user_limit;
+use crate::routes::ApiError;
+use crate::search::{search_for_project, SearchConfig, SearchError};
+use crate::util::cors::collections_read_cors;
+use crate::util::env::parse_var;
+use crate::util::markdown::render_markdown;
+use crate::util::routes::{read_from_field, read_from_payload};
+use crate::util::validate::{normalize_text, validation_errors_to_string};
+use actix_multipart::Multipart;
+use actix_web::http::{header, Method};
+use actix_web::web::Data;
+use actix_web::{guard, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::Instrument;
+use validator::Validate;
+
+use crate::routes::v2::project_creation::CreateError;
+use crate::routes::v2::version_creation::get_name_ext;
+
+pub mod export;
+pub mod icons;
+pub mod search;
+pub mod shares;
+
+use icons::{fetch_icon, sanitize_icon_filename};
+use search::{CollectionSearchPage, CollectionSearchResult};
+
+/// The public collection reads, which can be embedded on other sites. These are registered
+/// outside of the v3 scope, so they use `collections_read_cors` rather than the default CORS.
+/// Other methods on the same paths fall through to the v3 scope.
+pub fn read_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("v3/collections")
+            .guard(guard::fn_guard(is_read_request))
+            .wrap_fn(crate::util::metrics::record_request)
+            .wrap(collections_read_cors())
+            .route(web::get().to(collections_get)),
+    );
+    cfg.service(
+        web::resource("v3/collection/{id}")
+            .guard(guard::fn_guard(is_read_request))
+            .wrap_fn(crate::util::metrics::record_request)
+            .wrap(collections_read_cors())
+            .route(web::get().to(collection_get)),
+    );
+}
+
+// Matches reads, and the CORS preflight requests sent before them
+fn is_read_request(ctx: &guard::GuardContext) -> bool {
+    let head = ctx.head();
+
+    head.method == Method::GET
+        || (head.method == Method::OPTIONS
+            && head
+                .headers
+                .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+                .is_some_and(|x| x == "GET"))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "collections/search",
+        web::get().to(search::collections_search),
+    );
+    cfg.route(
+        "collections/recent",
+        web::get().to(search::collections_recent),
+    );
+    cfg.route(
+        "collections/trending",
+        web::get().to(search::collections_trending),
+    );
+    cfg.route(
+        "collections/categories",
+        web::get().to(search::collection_categories_list),
+    );
+    cfg.route("collections", web::delete().to(collections_delete));
+    cfg.route("collection", web::post().to(collection_create));
+    cfg.route("collections/import", web::post().to(collections_import));
+    cfg.route(
+        "collections/validate",
+        web::post().to(collection_create_validate),
+    );
+    cfg.route(
+        "project/{id}/collections",
+        web::get().to(project_collections_membership),
+    );
+    cfg.route(
+        "project/{id}/in_collections",
+        web::get().to(project_in_collections),
+    );
+    cfg.route(
+        "user/collections/pinned",
+        web::get().to(user_pinned_collections),
+    );
+    cfg.route(
+        "user/collections/export",
+        web::get().to(export::user_collections_export),
+    );
+    cfg.route(
+        "user/collections/index",
+        web::get().to(export::user_collections_index),
+    );
+    cfg.route(
+        "user/{user_id}/collections",
+        web::get().to(user_collections_list),
+    );
+    cfg.route(
+        "user/{user_id}/collections/stats",
+        web::get().to(user_collections_stats),
+    );
+    cfg.service(
+        web::scope("collection")
+            .route("{id}", web::delete().to(collection_delete))
+            .service(
+                web::resource("{id}")
+                    .guard(guard::Patch())
+                    .app_data(collection_edit_json_config())
+                    .route(web::patch().to(collection_edit)),
+            )
+            .route("{id}/projects", web::post().to(collection_projects_add))
+            .route(
+                "{id}/projects",
+                web::delete().to(collection_projects_remove),
+            )
+            .route(
+                "{id}/projects/from_search",
+                web::post().to(collection_projects_add_from_search),
+            )
+            .route(
+                "{id}/projects/all",
+                web::delete().to(collection_projects_clear),
+            )
+            .route("{id}/icon", web::get().to(icons::collection_icon_get))
+            .route("{id}/icon", web::head().to(icons::collection_icon_head))
+            .route("{id}/icon", web::patch().to(icons::collection_icon_edit))
+            .route("{id}/icon", web::delete().to(icons::delete_collection_icon))
+            .route(
+                "{id}/icon/url",
+                web::patch().to(icons::collection_icon_edit_from_url),
+            )
+            .route(
+                "{id}/icon/download",
+                web::get().to(icons::collection_icon_download),
+            )
+            .route("{id}/restore", web::post().to(collection_restore))
+            .route("{id}/transfer", web::post().to(collection_transfer))
+            .route("{id}/moderate", web::post().to(collection_moderate))
+            .route("{id}/duplicate", web::post().to(collection_duplicate))
+            .route("{id}/share", web::post().to(shares::collection_share))
+            .route("{id}/share", web::delete().to(shares::collection_unshare))
+            .route("{id}/follow", web::post().to(collection_follow))
+            .route("{id}/follow", web::delete().to(collection_unfollow))
+            .route("{id}/pin", web::post().to(collection_pin))
+            .route("{id}/pin", web::delete().to(collection_unpin))
+            .route("{id}/history", web::get().to(collection_history))
+            .route("{id}/body", web::get().to(collection_body_get))
+            .route(
+                "{id}/search",
+                web::get().to(search::collection_projects_search),
+            )
+            .route("{id}/export", web::get().to(export::collection_export))
+            .route(
+                "{id}/compatibility",
+                web::get().to(collection_compatibility),
+            )
+            .route("{id}/move", web::post().to(collection_project_move)),
+    );
+}
+
+#[derive(Serialize, Deserialize, Validate, Clone)]
+pub struct CollectionCreateData {
+    #[validate(length(min = 3), custom(function = "validate_collection_title"))]
+    /// The title or name of the project.
+    pub title: String,
+    #[validate(custom(function = "validate_collection_description"))]
+    /// A short description of the collection.
+    pub description: String,
+    #[validate(
+        length(min = 3, max = 64),
+        regex = "crate::util::validate::RE_LOWERCASE_SLUG",
+        custom(function = "validate_collection_slug")
+    )]
+    #[serde(default)]
+    /// A unique name to access the collection by instead of its id
+    pub slug: Option<String>,
+    #[validate(length(max = 32))]
+    #[serde(default = "Vec::new")]
+    /// A list of initial projects to use with the created collection
+    pub projects: Vec<String>,
+    #[validate(length(max = 8))]
+    #[serde(default = "Vec::new")]
+    /// The categories to tag the collection with
+    pub categories: Vec<String>,
+}
+
+/// Length bounds of a collection description, shared by creation and editing. The column allows
+/// longer values, so descriptions stored under the previous limits of 255 and 256 remain valid.
+pub const COLLECTION_DESCRIPTION_MIN_LENGTH: usize = 3;
+pub const COLLECTION_DESCRIPTION_MAX_LENGTH: usize = 256;
+/// The most projects a collection can be created with, as validated on `CollectionCreateData`
+const COLLECTION_CREATE_MAX_PROJECTS: usize = 32;
+
+fn validate_collection_title(title: &str) -> Result<(), validator::ValidationError> {
+    crate::util::validate::validate_name(title)?;
+    crate::util::validate::validate_display_text(title)
+}
+
+/// Slugs which would be confused with the routes under `/collection`
+const RESERVED_COLLECTION_SLUGS: &[&str] = &[
+    "collection",
+    "collections",
+    "deleted",
+    "import",
+    "new",
+    "random",
+    "recent",
+    "search",
+    "validate",
+];
+
+fn validate_collection_slug(slug: &str) -> Result<(), validator::ValidationError> {
+    if RESERVED_COLLECTION_SLUGS.contains(&slug) {
+        return Err(validator::ValidationError::new("reserved slug"));
+    }
+
+    Ok(())
+}
+
+// Checks that a slug isn't taken by another collection, or the id of one. Slugs are matched
+// case-insensitively, like the unique index on them.
+async fn check_collection_slug_available(
+    slug: &str,
+    collection_id: Option<database::models::CollectionId>,
+    pool: &PgPool,
+) -> Result<(), ApiError> {
+    if let Ok(slug_id) = parse_collection_id(slug) {
+        if Some(slug_id) != collection_id
+            && database::models::Collection::get_without_projects(slug_id, pool)
+                .await?
+                .is_some()
+        {
+            return Err(ApiError::InvalidInput(
+                "Slug collides with another collection's id!".to_string(),
+            ));
+        }
+    }
+
+    let existing = database::models::Collection::get_id_from_slug(slug, pool).await?;
+    if existing.is_some_and(|x| Some(x) != collection_id) {
+        return Err(ApiError::InvalidInput(format!(
+            "The slug {slug} is already used by another collection!"
+        )));
+    }
+
+    Ok(())
+}
+
+// Whether a path segment could be a collection slug. Slugs are matched case-insensitively.
+fn is_collection_slug(string: &str) -> bool {
+    let slug = string.to_lowercase();
+
+    (3..=64).contains(&slug.len())
+        && crate::util::validate::RE_LOWERCASE_SLUG.is_match(&slug)
+        && validate_collection_slug(&slug).is_ok()
+}
+
+/// Resolves a collection path segment, which is either a base62 id or a slug. Ids take
+/// precedence, so a slug can't shadow a collection created after it. A segment which is neither is
+/// rejected as invalid input.
+pub async fn resolve_collection_id(
+    string: &str,
+    pool: &PgPool,
+) -> Result<database::models::CollectionId, ApiError> {
+    let id = parse_collection_id(string);
+    let slug = Some(string).filter(|x| is_collection_slug(x));
+    if let (Err(_), None) = (&id, slug) {
+        return id;
+    }
+
+    database::models::Collection::resolve_id(id.ok(), slug, pool)
+        .await?
+        .ok_or(ApiError::NotFound)
+}
+
+// Whether a project id or slug names the project. Slugs are compared case-insensitively.
+fn project_matches(id: u64, slug: Option<&String>, project_string: &str) -> bool {
+    parse_base62(project_string).ok() == Some(id)
+        || slug
+            .map(|slug| slug.eq_ignore_ascii_case(project_string))
+            .unwrap_or(false)
+}
+
+fn validate_collection_description(description: &str) -> Result<(), validator::ValidationError> {
+    crate::util::validate::validate_display_text(description)?;
+
+    let length = description.chars().count();
+
+    if !(COLLECTION_DESCRIPTION_MIN_LENGTH..=COLLECTION_DESCRIPTION_MAX_LENGTH).contains(&length) {
+        return Err(validator::ValidationError::new("length"));
+    }
+
+    Ok(())
+}
+
+// Categories must be known collection tags. Unknown ones are rejected by name.
+async fn check_collection_categories(categories: &[String], pool: &PgPool) -> Result<(), ApiError> {
+    if categories.is_empty() {
+        return Ok(());
+    }
+
+    let known = database::models::Collection::list_categories(pool).await?;
+    let unknown = categories
+        .iter()
+        .filter(|x| !known.contains(x))
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>();
+
+    if !unknown.is_empty() {
+        return Err(ApiError::InvalidInput(format!(
+            "Unknown collection categories: {}",
+            unknown.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// An icon sent alongside the collection data in a multipart create request
+pub struct CollectionCreateIcon {
+    pub ext: String,
+    /// The sanitized name the icon file was uploaded with
+    pub filename: Option<String>,
+    pub data: bytes::Bytes,
+}
+
+pub async fn collection_create(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    client: Data<PgPool>,
+    redis: Data<deadpool_redis::Pool>,
+    file_host: Data<Arc<dyn FileHost + Send + Sync>>,
+    session_queue: Data<AuthQueue>,
+) -> Result<HttpResponse, CreateError> {
+    let is_multipart = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.starts_with("multipart/form-data"))
+        .unwrap_or(false);
+
+    let (collection_create_data, icon) = if is_multipart {
+        read_create_multipart(Multipart::new(req.headers(), payload)).await?
+    } else {
+        let bytes = read_from_payload(
+            &mut payload,
+            262144,
+            "Collection data must be smaller than 256KiB",
+        )
+        .await
+        .map_err(api_to_create_error)?;
+
+        (serde_json::from_slice(&bytes)?, None)
+    };
+
+    collection_create_inner(
+        req,
+        collection_create_data,
+        icon,
+        client,
+        redis,
+        file_host,
+        session_queue,
+    )
+    .await
+}
+
+// Reads a multipart create request: a JSON `data` part and an optional `icon` file part
+async fn read_create_multipart(
+    mut payload: Multipart,
+) -> Result<(CollectionCreateData, Option<CollectionCreateIcon>), CreateError> {
+    let mut collection_create_data = None;
+    let mut icon = None;
+
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+        let content_disposition = field.content_disposition().clone();
+        let name = content_disposition
+            .get_name()
+            .ok_or_else(|| CreateError::MissingValueError("Missing content name".to_string()))?;
+
+        match name {
+            "data" => {
+                let data = read_from_field(
+                    &mut field,
+                    262144,
+                    "Collection data must be smaller than 256KiB",
+                )
+                .await?;
+                collection_create_data = Some(serde_json::from_slice(&data)?);
+            }
+            "icon" => {
+                if icon.is_some() {
+                    return Err(CreateError::InvalidInput(String::from(
+                        "Only one icon may be uploaded",
+                    )));
+                }
+
+                let (file_name, file_extension) = get_name_ext(&content_disposition)?;
+                let data = read_from_field(&mut field, 262144, "Icons must be smaller than 256KiB")
+                    .await?;
+                icon = Some(CollectionCreateIcon {
+                    ext: file_extension.to_string(),
+                    filename: sanitize_icon_filename(file_name),
+                    data: data.freeze(),
+                });
+            }
+            _ => {
+                return Err(CreateError::InvalidInput(format!(
+                    "Unexpected multipart field: {name}"
+                )));
+            }
+        }
+    }
+
+    let collection_create_data = collection_create_data.ok_or_else(|| {
+        CreateError::MissingValueError("No `data` field in multipart upload".to_string())
+    })?;
+
+    Ok((collection_create_data, icon))
+}
+
+fn api_to_create_error(err: ApiError) -> CreateError {
+    match err {
+        ApiError::InvalidInput(msg) => CreateError::InvalidInput(msg),
+        ApiError::RateLimited(retry_after) => CreateError::RateLimited(retry_after),
+        err => CreateError::InvalidInput(err.to_string()),
+    }
+}
+
+pub async fn collection_create_inner(
+    req: HttpRequest,
+    collection_create_data: CollectionCreateData,
+    icon: Option<CollectionCreateIcon>,
+    client: Data<PgPool>,
+    redis: Data<deadpool_redis::Pool>,
+    file_host: Data<Arc<dyn FileHost + Send + Sync>>,
+    session_queue: Data<AuthQueue>,
+) -> Result<HttpResponse, CreateError> {
+    // The currently logged in user
+    let current_user = get_user_from_headers(
+        &req,
+        &**client,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_CREATE]),
+    )
+    .await?
+    .1;
+
+    // Retried requests with the same key return the collection the first request created
+    let idempotency_key = idempotency_key(&req)?;
+    if let Some(key) = &idempotency_key {
+        match database::models::Collection::claim_create_key(current_user.id.into(), key, &redis)
+            .await?
+        {
+            collection_item::CreateKeyClaim::Claimed => {}
+            collection_item::CreateKeyClaim::Pending => {
+                return Err(CreateError::InvalidInput(
+                    "A request with this idempotency key is still being processed".to_string(),
+                ));
+            }
+            collection_item::CreateKeyClaim::Created(id) => {
+                if let Some(collection) =
+                    database::models::Collection::get(id, &**client, &redis).await?
+                {
+                    return Ok(HttpResponse::Ok().json(CollectionCreateResponse {
+                        collection: collection.into(),
+                        warnings: Vec::new(),
+                    }));
+                }
+            }
+        }
+    }
+
+    let user_id = current_user.id.into();
+    let result = create_collection(
+        current_user,
+        collection_create_data,
+        icon,
+        &client,
+        &redis,
+        &file_host,
+    )
+    .await;
+
+    if let Some(key) = &idempotency_key {
+        match &result {
+            Ok(response) => {
+                database::models::Collection::complete_create_key(
+                    user_id,
+                    key,
+                    response.collection.id.into(),
+                    &redis,
+                )
+                .await?
+            }
+            Err(_) => {
+                database::models::Collection::release_create_key(user_id, key, &redis).await?
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(result?))
+}
+
+fn idempotency_key(req: &HttpRequest) -> Result<Option<String>, CreateError> {
+    let Some(value) = req.headers().get("Idempotency-Key") else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && x.len() <= 255)
+        .map(|x| Some(x.to_string()))
+        .ok_or_else(|| {
+            CreateError::InvalidInput(
+                "The Idempotency-Key header must be between 1 and 255 visible characters"
+                    .to_string(),
+            )
+        })
+}
+
+/// The projects of a collection creation request which could be added to it
+#[derive(Serialize, Deserialize)]
+pub struct CollectionCreateValidation {
+    /// Public projects, in the order given and without duplicates
+    pub valid_projects: Vec<ProjectId>,
+    /// Ids or slugs which don't name a public project
+    pub invalid_projects: Vec<String>,
+}
+
+// Validates collection creation data without authentication or persisting anything, so clients
+// can check it before signing in. Only public projects are confirmed; private ones are reported
+// as invalid like those which don't exist, so their existence isn't revealed.
+pub async fn collection_create_validate(
+    web::Json(collection_create_data): web::Json<CollectionCreateData>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+) -> Result<HttpResponse, ApiError> {
+    collection_create_data
+        .validate()
+        .map_err(|err| ApiError::Validation(validation_errors_to_string(err, None)))?;
+    check_collection_categories(&collection_create_data.categories, &pool).await?;
+
+    let projects =
+        project_item::Project::get_many(&collection_create_data.projects, &**pool, &redis).await?;
+
+    let mut validation = CollectionCreateValidation {
+        valid_projects: Vec::new(),
+        invalid_projects: Vec::new(),
+    };
+    for project_string in collection_create_data.projects {
+        let project = projects
+            .iter()
+            .find(|x| project_matches(x.inner.id.0 as u64, x.inner.slug.as_ref(), &project_string));
+
+        match project {
+            Some(project) if is_authorized(&project.inner, &None, &pool).await? => {
+                let project_id = ProjectId::from(project.inner.id);
+                if !validation.valid_projects.contains(&project_id) {
+                    validation.valid_projects.push(project_id);
+                }
+            }
+            _ => validation.invalid_projects.push(project_string),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(validation))
+}
+
+pub async fn collections_import(
+    req: HttpRequest,
+    web::Json(manifest): web::Json<CollectionManifest>,
+    client: Data<PgPool>,
+    redis: Data<deadpool_redis::Pool>,
+    file_host: Data<Arc<dyn FileHost + Send + Sync>>,
+    session_queue: Data<AuthQueue>,
+) -> Result<HttpResponse, CreateError> {
+    let current_user = get_user_from_headers(
+        &req,
+        &**client,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_CREATE]),
+    )
+    .await?
+    .1;
+
+    if manifest.format_version > COLLECTION_MANIFEST_FORMAT_VERSION {
+        return Err(CreateError::InvalidInput(format!(
+            "Unsupported collection manifest format version {}",
+            manifest.format_version
+        )));
+    }
+
+    let slugs = manifest
+        .projects
+        .iter()
+        .map(|x| x.slug.clone())
+        .collect::<Vec<_>>();
+    let projects = project_item::Project::get_many(&slugs, &**client, &redis).await?;
+
+    // Projects which don't exist or which the user can't see are skipped, so that one stale
+    // entry doesn't fail the whole import
+    let current_user_option = Some(current_user.clone());
+    let mut resolved = Vec::new();
+    let mut resolved_ids = Vec::new();
+    let mut unresolved = Vec::new();
+    for slug in slugs {
+        let project = projects
+            .iter()
+            .find(|x| project_matches(x.inner.id.0 as u64, x.inner.slug.as_ref(), &slug));
+
+        match project {
+            Some(project) if resolved_ids.contains(&project.inner.id) => {}
+            Some(project)
+                if is_authorized(&project.inner, &current_user_option, &client)
+                    .await
+                    .map_err(api_to_create_error)? =>
+            {
+                resolved_ids.push(project.inner.id);
+                resolved.push(slug)
+            }
+            _ => unresolved.push(slug),
+        }
+    }
+    let (resolved, over_limit) = split_import_projects(resolved);
+
+    let collection_create_data = CollectionCreateData {
+        title: manifest.title,
+        description: manifest.description,
+        slug: None,
+        projects: resolved,
+        categories: Vec::new(),
+    };
+
+    let mut response = create_collection(
+        current_user,
+        collection_create_data,
+        None,
+        &client,
+        &redis,
+        &file_host,
+    )
+    .await?;
+    response.warnings.extend(
+        unresolved
+            .into_iter()
+            .map(|x| format!("The project {x} could not be found and was not imported")),
+    );
+    response.warnings.extend(over_limit.into_iter().map(|x| {
+        format!(
+            "The project {x} was not imported, as a collection is created with at most {} projects",
+            COLLECTION_CREATE_MAX_PROJECTS
+        )
+    }));
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Splits the projects of an import into the ones a collection can be created with, in the order
+// given, and the ones past the limit
+fn split_import_projects(mut projects: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let over_limit = projects.split_off(projects.len().min(COLLECTION_CREATE_MAX_PROJECTS));
+
+    (projects, over_limit)
+}
+
+// Creates a collection owned by the given user
+async fn create_collection(
+    current_user: crate::models::users::User,
+    mut collection_create_data: CollectionCreateData,
+    icon: Option<CollectionCreateIcon>,
+    client: &Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+    file_host: &Arc<dyn FileHost + Send + Sync>,
+) -> Result<CollectionCreateResponse, CreateError> {
+    collection_create_data
+        .validate()
+        .map_err(|err| CreateError::InvalidInput(validation_errors_to_string(err, None)))?;
+    collection_create_data.title = normalize_text(&collection_create_data.title);
+    collection_create_data.description = normalize_text(&collection_create_data.description);
+
+    if !current_user.role.is_mod() {
+        if let Some(retry_after) = check_user_limit(
+            "collection_create",
+            current_user.id.into(),
+            parse_var("COLLECTION_CREATE_LIMIT").unwrap_or(30),
+            60 * 60,
+            redis,
+        )
+        .await?
+        {
+            return Err(CreateError::RateLimited(retry_after));
+        }
+    }
+
+    check_collection_projects_limit(
+        &current_user,
+        current_user.id.into(),
+        0,
+        collection_create_data.projects.len(),
+        client,
+    )
+    .await
+    .map_err(api_to_create_error)?;
+    check_collection_categories(&collection_create_data.categories, client)
+        .await
+        .map_err(api_to_create_error)?;
+    if let Some(slug) = &collection_create_data.slug {
+        check_collection_slug_available(slug, None, client)
+            .await
+            .map_err(api_to_create_error)?;
+    }
+
+    let mut transaction = client.begin().await?;
+
+    // Duplicate titles are allowed, but clients are warned so they can ask the user
+    let duplicate_title = sqlx::query!(
+        "
+        SELECT EXISTS(
+            SELECT 1 FROM collections
+            WHERE lower(title) = lower($1) AND user_id = $2 AND deleted_at IS NULL
+        )
+        ",
+        collection_create_data.title,
+        database::models::ids::UserId::from(current_user.id) as database::models::ids::UserId,
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .exists
+    .unwrap_or(false);
+
+    let mut warnings = Vec::new();
+    if duplicate_title {
+        warnings.push(format!(
+            "You already have a collection named \"{}\"",
+            collection_create_data.title
+        ));
+    }
+
+    let collection_id: CollectionId = generate_collection_id(&mut transaction).await?.into();
+
+    let initial_projects =
+        project_item::Project::get_many(&collection_create_data.projects, &mut transaction, redis)
+            .await?;
+
+    let current_user_option = Some(current_user.clone());
+    for project in &initial_projects {
+        check_project_collectable(project, &current_user_option, client)
+            .await
+            .map_err(api_to_create_error)?;
+    }
+
+    // Order the projects as they were given, whether referenced by id or slug. Projects which
+    // don't exist are left out, and reported so clients can tell them apart from duplicates.
+    let mut requested_project_ids = Vec::new();
+    for project_string in &collection_create_data.projects {
+        let project = initial_projects
+            .iter()
+            .find(|x| project_matches(x.inner.id.0 as u64, x.inner.slug.as_ref(), project_string));
+
+        if let Some(project) = project {
+            requested_project_ids.push(ProjectId::from(project.inner.id));
+        } else {
+            warnings.push(format!(
+                "Project {project_string} could not be found and was not added"
+            ));
+        }
+    }
+
+    let (initial_project_ids, duplicate_count) = dedup_project_ids(requested_project_ids);
+    if duplicate_count > 0 {
+        warnings.push(format!(
+            "{duplicate_count} duplicate project(s) were removed from the collection"
+        ));
+    }
+
+    let collection_builder_actual = collection_item::CollectionBuilder {
+        collection_id: collection_id.into(),
+        user_id: current_user.id.into(),
+        title: collection_create_data.title,
+        description: collection_create_data.description,
+        slug: collection_create_data.slug,
+        status: CollectionStatus::Listed,
+        categories: collection_create_data.categories.clone(),
+        projects: initial_project_ids
+            .iter()
+            .copied()
+            .map(|x| x.into())
+            .collect(),
+    };
+    let collection_builder = collection_builder_actual.clone();
+
+    let now = Utc::now();
+    collection_builder_actual.insert(&mut transaction).await?;
+    let colors =
+        database::models::Collection::update_colors(collection_id.into(), &mut transaction).await?;
+
+    let mut icon_url = None;
+    let mut color = None;
+    let mut uploaded_icon = None;
+    if let Some(icon) = icon {
+        let content_type = crate::util::ext::get_image_content_type(&icon.ext)
+            .ok_or_else(|| CreateError::InvalidIconFormat(icon.ext.clone()))?;
+        let cdn_url = dotenvy::var("CDN_URL")?;
+
+        let bytes =
+            crate::util::img::process_icon(icon.data, &icon.ext).map_err(api_to_create_error)?;
+        color = crate::util::img::get_color_from_img(&bytes)?;
+
+        let hash = sha1::Sha1::from(&bytes).hexdigest();
+        let upload_data = file_host
+            .upload_file(
+                content_type,
+                &format!("data/{}/{}.{}", collection_id, hash, icon.ext),
+                bytes,
+            )
+            .await?;
+
+        let url = format!("{}/{}", cdn_url, upload_data.file_name);
+
+        let result = database::models::Collection::set_icon(
+            collection_builder.collection_id,
+            &url,
+            color,
+            upload_data.content_length as i64,
+            icon.filename.as_deref(),
+            &mut transaction,
+        )
+        .await;
+
+        // Nothing references the upload until the collection is committed
+        if let Err(err) = result {
+            delete_or_defer(
+                &upload_data.file_id,
+                &upload_data.file_name,
+                &**file_host,
+                client,
+            )
+            .await?;
+            return Err(err.into());
+        }
+
+        uploaded_icon = Some((upload_data.file_id, upload_data.file_name));
+        icon_url = Some(url);
+    }
+
+    let response = crate::models::collections::Collection {
+        id: collection_id,
+        user: collection_builder.user_id.into(),
+        title: collection_builder.title.clone(),
+        description: collection_builder.description.clone(),
+        slug: collection_builder.slug.clone(),
+        body: String::new(),
+        created: now,
+        updated: now,
+        icon_url,
+        color,
+        colors,
+        status: collection_builder.status,
+        categories: collection_builder.categories.clone(),
+        follows: 0,
+        views: None,
+        featured_project: None,
+        version: 1,
+        projects: initial_project_ids,
+        child_collections: Vec::new(),
+        project_count: None,
+        expanded_projects: None,
+        hidden_project_count: None,
+        project_groups: None,
+        editable: None,
+        owner: None,
+    };
+
+    if let Err(err) = transaction.commit().await {
+        if let Some((file_id, file_name)) = uploaded_icon {
+            delete_or_defer(&file_id, &file_name, &**file_host, client).await?;
+        }
+        return Err(err.into());
+    }
+
+    Ok(CollectionCreateResponse {
+        collection: response,
+        warnings,
+    })
+}
+
+/// Removes repeated projects while keeping the first occurrence of each, returning the
+/// remaining projects and how many duplicates were dropped.
+fn dedup_project_ids(project_ids: Vec<ProjectId>) -> (Vec<ProjectId>, usize) {
+    let total = project_ids.len();
+    let mut deduped = Vec::with_capacity(total);
+
+    for project_id in project_ids {
+        if !deduped.contains(&project_id) {
+            deduped.push(project_id);
+        }
+    }
+
+    let duplicate_count = total - deduped.len();
+    (deduped, duplicate_count)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionCreateResponse {
+    #[serde(flatten)]
+    pub collection: Collection,
+    /// Advisory messages about the new collection, such as a duplicate title
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionIds {
+    pub ids: String,
+}
+
+/// Parses a single base62 collection id, rejecting malformed ids as invalid input
+pub fn parse_collection_id(id: &str) -> Result<database::models::CollectionId, ApiError> {
+    parse_base62(id)
+        .map(|x| database::models::CollectionId(x as i64))
+        .map_err(|err| ApiError::InvalidInput(format!("Invalid collection id {id:?}: {err}")))
+}
+
+/// Parses a JSON array of base62 collection ids, as passed in the `ids` query parameter
+pub fn parse_collection_ids(ids: &str) -> Result<Vec<database::models::CollectionId>, ApiError> {
+    let ids = serde_json::from_str::<Vec<&str>>(ids).map_err(|_| {
+        ApiError::InvalidInput(format!(
+            "Invalid collection id list {ids:?}: expected a JSON array of ids"
+        ))
+    })?;
+
+    ids.into_iter().map(parse_collection_id).collect()
+}
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsGetQuery {
+    pub ids: String,
+    /// Only return collections which were updated after this time
+    pub since: Option<DateTime<Utc>>,
+    /// A comma separated list of the collection fields to return, defaulting to all of them
+    pub fields: Option<String>,
+}
+
+/// The fields of a serialized `Collection`, which can be requested as a sparse fieldset
+const COLLECTION_FIELDS: &[&str] = &[
+    "id",
+    "user",
+    "title",
+    "description",
+    "slug",
+    "body",
+    "icon_url",
+    "color",
+    "colors",
+    "status",
+    "categories",
+    "created",
+    "updated",
+    "follows",
+    "views",
+    "featured_project",
+    "version",
+    "projects",
+    "child_collections",
+    "project_count",
+    "expanded_projects",
+    "hidden_project_count",
+    "project_groups",
+    "editable",
+    "owner",
+];
+
+// Parses a sparse fieldset, rejecting fields a collection doesn't have
+fn parse_collection_fields(fields: &str) -> Result<Vec<&str>, ApiError> {
+    let fields = fields
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+
+    let unknown = fields
+        .iter()
+        .filter(|x| !COLLECTION_FIELDS.contains(x))
+        .copied()
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(ApiError::InvalidInput(format!(
+            "Unknown collection fields: {}",
+            unknown.join(", ")
+        )));
+    }
+
+    Ok(fields)
+}
+
+// Serializes collections with only the given fields
+fn sparse_collections(
+    collections: Vec<Collection>,
+    fields: &[&str],
+) -> Result<Vec<serde_json::Value>, ApiError> {
+    collections
+        .into_iter()
+        .map(|collection| {
+            let mut value = serde_json::to_value(collection)?;
+            if let Some(object) = value.as_object_mut() {
+                object.retain(|key, _| fields.contains(&key.as_str()));
+            }
+
+            Ok(value)
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsDelta<T = Collection> {
+    pub collections: Vec<T>,
+    /// The time this response was generated, to be used as `since` in the next request
+    pub server_time: DateTime<Utc>,
+}
+
+pub async fn collections_get(
+    req: HttpRequest,
+    web::Query(query): web::Query<CollectionsGetQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let server_time = Utc::now();
+    let ids = parse_collection_ids(&query.ids)?;
+    let fields = query
+        .fields
+        .as_deref()
+        .map(parse_collection_fields)
+        .transpose()?;
+
+    let mut collections_data =
+        database::models::Collection::get_many(&ids, &**pool, &redis).await?;
+
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    if let Some(since) = query.since {
+        collections_data.retain(|x| x.updated > since);
+    }
+
+    // Collections are returned in the order they were requested
+    collection_item::sort_by_ids(&mut collections_data, &ids, |x| x.id);
+    let collections = filter_authorized_collections(collections_data, &user_option, &pool).await?;
+
+    match (fields, query.since.is_some()) {
+        (Some(fields), true) => Ok(HttpResponse::Ok().json(CollectionsDelta {
+            collections: sparse_collections(collections, &fields)?,
+            server_time,
+        })),
+        (Some(fields), false) => {
+            Ok(HttpResponse::Ok().json(sparse_collections(collections, &fields)?))
+        }
+        (None, true) => Ok(HttpResponse::Ok().json(CollectionsDelta {
+            collections,
+            server_time,
+        })),
+        (None, false) => Ok(HttpResponse::Ok().json(collections)),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsDeleteResult {
+    pub deleted: Vec<CollectionId>,
+    pub skipped: Vec<CollectionId>,
+}
+
+pub async fn collections_delete(
+    req: HttpRequest,
+    web::Query(ids): web::Query<CollectionIds>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_DELETE]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let ids = parse_collection_ids(&ids.ids)?;
+
+    let collections_data = database::models::Collection::get_many(&ids, &**pool, &redis).await?;
+
+    let mut deleted = Vec::new();
+    for collection in &collections_data {
+        if user_option
+            .as_ref()
+            .map(|x| is_authorized_collection_edit(collection, x))
+            .unwrap_or(false)
+        {
+            deleted.push(collection.id);
+        }
+    }
+    let skipped = ids
+        .iter()
+        .filter(|x| !deleted.contains(x))
+        .map(|x| CollectionId::from(*x))
+        .collect();
+
+    let mut transaction = pool.begin().await?;
+
+    database::models::Collection::remove_many(&deleted, &mut transaction, &redis).await?;
+    for collection in collections_data.iter().filter(|x| deleted.contains(&x.id)) {
+        CollectionMutation {
+            collection_id: collection.id,
+            owner_id: collection.user_id,
+            editor_id: user_option.as_ref().map(|x| x.id.into()),
+            listing: ListingChange::between(collection.status, CollectionStatus::Unlisted),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+        .enqueue(&mut transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(CollectionsDeleteResult {
+        deleted: deleted.into_iter().map(CollectionId::from).collect(),
+        skipped,
+    }))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionGetQuery {
+    /// Comma-separated list of fields to expand, `projects` and `owner`
+    pub expand: Option<String>,
+    /// Number of member projects to skip, in collection order
+    pub project_offset: Option<u32>,
+    /// Maximum number of member projects to return
+    pub project_limit: Option<u32>,
+    /// Respond with a 403 naming the missing scope, rather than a 404, when the owner of a
+    /// hidden collection authenticates with a token that cannot read it
+    #[serde(default)]
+    pub scope_hint: bool,
+    /// Set to `metadata` to only return the collection's metadata and project count
+    pub fields: Option<String>,
+    /// Only return member projects of this project type, eg: `mod`
+    pub project_type: Option<String>,
+    /// Set to `author` to also return the member projects grouped by their owning team
+    pub group_by: Option<String>,
+    /// Include how many member projects can't be seen by the user, without naming them
+    #[serde(default)]
+    pub include_hidden_count: bool,
+}
+
+impl CollectionGetQuery {
+    fn expands(&self, field: &str) -> bool {
+        self.expand
+            .as_deref()
+            .map(|x| x.split(',').any(|x| x.trim() == field))
+            .unwrap_or(false)
+    }
+}
+
+// Groups the visible projects by their team, largest groups first. Groups of the same size keep
+// the collection order of their first project.
+async fn group_projects_by_team(
+    project_ids: &[database::models::ProjectId],
+    user_option: &Option<crate::models::users::User>,
+    pool: &web::Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+) -> Result<Vec<CollectionProjectGroup>, ApiError> {
+    let projects = database::models::Project::get_many_ids(project_ids, &***pool, redis).await?;
+    let mut projects = filter_authorized_projects(projects, user_option, pool).await?;
+    collection_item::sort_by_ids(&mut projects, project_ids, |x| x.id.into());
+
+    let mut groups: Vec<CollectionProjectGroup> = Vec::new();
+    for project in projects {
+        if let Some(group) = groups.iter_mut().find(|x| x.team_id == project.team) {
+            group.project_count += 1;
+            group.projects.push(project.id);
+        } else {
+            groups.push(CollectionProjectGroup {
+                team_id: project.team,
+                owner: None,
+                project_count: 1,
+                projects: vec![project.id],
+            });
+        }
+    }
+
+    let team_ids = groups
+        .iter()
+        .map(|x| x.team_id.into())
+        .collect::<Vec<database::models::TeamId>>();
+    let members =
+        database::models::TeamMember::get_from_team_full_many(&team_ids, &***pool, redis).await?;
+
+    for group in &mut groups {
+        group.owner = members
+            .iter()
+            .find(|x| {
+                crate::models::teams::TeamId::from(x.team_id) == group.team_id
+                    && x.role == crate::models::teams::OWNER_ROLE
+            })
+            .map(|x| x.user_id.into());
+    }
+
+    groups.sort_by_key(|x| std::cmp::Reverse(x.project_count));
+
+    Ok(groups)
+}
+
+// Only listed collections may be stored by shared caches. Responses carrying data for a specific
+// user are never cached, whatever the collection's status.
+fn collection_cache_control(status: CollectionStatus, personalized: bool) -> String {
+    if status == CollectionStatus::Listed && !personalized {
+        format!(
+            "public, max-age={}",
+            parse_var::<u32>("COLLECTION_CACHE_MAX_AGE").unwrap_or(300)
+        )
+    } else {
+        "private, no-store".to_string()
+    }
+}
+
+pub async fn collection_get(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionGetQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let string = info.into_inner().0;
+
+    let id = resolve_collection_id(&string, &pool).await?;
+
+    let metadata_only = match query.fields.as_deref() {
+        None => false,
+        Some("metadata") => true,
+        Some(fields) => {
+            return Err(ApiError::InvalidInput(format!(
+                "Unknown collection fields {fields}, expected metadata"
+            )))
+        }
+    };
+
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    // Metadata is read without the membership, which the full collection loads
+    if metadata_only {
+        if let Some((data, project_count)) =
+            database::models::Collection::get_without_projects(id, &**pool).await?
+        {
+            if is_authorized_collection(&data, &user_option).await? {
+                return Ok(HttpResponse::Ok().json(
+                    crate::models::collections::CollectionMetadata::new(data, project_count),
+                ));
+            }
+        }
+
+        return Err(ApiError::NotFound);
+    }
+
+    let group_by_author = match query.group_by.as_deref() {
+        None => false,
+        Some("author") => true,
+        Some(group_by) => {
+            return Err(ApiError::InvalidInput(format!(
+                "Unknown grouping {group_by}, expected author"
+            )))
+        }
+    };
+
+    let collection_data = database::models::Collection::get(id, &**pool, &redis).await?;
+
+    if let Some(project_type) = &query.project_type {
+        let project_types =
+            database::models::categories::ProjectType::list(&**pool, &redis).await?;
+
+        if !project_types.contains(project_type) {
+            return Err(ApiError::InvalidInput(format!(
+                "Unknown project type {project_type}, expected one of: {}",
+                project_types.join(", ")
+            )));
+        }
+    }
+
+    if let Some(mut data) = collection_data {
+        if is_authorized_collection(&data, &user_option).await? {
+            if let Some(project_type) = &query.project_type {
+                let matching = database::models::Collection::filter_projects_by_type(
+                    &data.projects,
+                    project_type,
+                    &**pool,
+                )
+                .await?;
+
+                data.projects.retain(|x| matching.contains(x));
+            }
+
+            // Counted over every matching project, like the groups below
+            let hidden_project_count = if query.include_hidden_count {
+                let projects =
+                    database::models::Project::get_many_ids(&data.projects, &**pool, &redis)
+                        .await?;
+                let visible_count = filter_authorized_projects(projects, &user_option, &pool)
+                    .await?
+                    .len();
+
+                Some(data.projects.len().saturating_sub(visible_count) as u32)
+            } else {
+                None
+            };
+
+            // Groups cover every matching project, not only the requested page
+            let project_groups = if group_by_author {
+                Some(group_projects_by_team(&data.projects, &user_option, &pool, &redis).await?)
+            } else {
+                None
+            };
+
+            // Page through the membership when requested. Projects are kept in collection order,
+            // and an offset past the end yields an empty page. Filtered responses always include
+            // the count of matching projects.
+            let project_count = if query.project_offset.is_some()
+                || query.project_limit.is_some()
+                || query.project_type.is_some()
+            {
+                let project_count = data.projects.len() as u32;
+                let offset = query.project_offset.unwrap_or(0) as usize;
+                let limit = query.project_limit.unwrap_or(u32::MAX) as usize;
+
+                data.projects = data.projects.into_iter().skip(offset).take(limit).collect();
+
+                Some(project_count)
+            } else {
+                None
+            };
+
+            // Expanded projects and child collections are authorized together, in collection order
+            let mut visible_child_collections = None;
+            let expanded_projects = if query.expands("projects") {
+                let resource_ids = data
+                    .projects
+                    .iter()
+                    .map(|x| ResourceId::Project(*x))
+                    .chain(
+                        data.child_collections
+                            .iter()
+                            .map(|x| ResourceId::Collection(*x)),
+                    )
+                    .collect::<Vec<_>>();
+
+                let mut projects = Vec::new();
+                let mut child_collections = Vec::new();
+                for resource in
+                    filter_authorized_resources(&resource_ids, &user_option, &pool, &redis).await?
+                {
+                    match resource {
+                        AuthorizedResource::Project(project) => {
+                            projects.push(CollectionProject::from(*project))
+                        }
+                        AuthorizedResource::Collection(collection) => {
+                            child_collections.push(collection.id)
+                        }
+                    }
+                }
+
+                visible_child_collections = Some(child_collections);
+                Some(projects)
+            } else {
+                None
+            };
+
+            // Read through the user cache, as the collection is cached without its owner
+            let owner = if query.expands("owner") {
+                database::models::User::get_id(data.user_id, &**pool, &redis)
+                    .await?
+                    .map(CollectionOwner::from)
+            } else {
+                None
+            };
+
+            if data.status == CollectionStatus::Listed {
+                if let Some(ip) = request_ip(&req) {
+                    count_collection_view(data.id, ip, (**redis).clone());
+                }
+            }
+
+            let views = if user_option
+                .as_ref()
+                .map(|x| x.id == data.user_id.into() || x.role.is_mod())
+                .unwrap_or(false)
+            {
+                Some(database::models::Collection::get_views(data.id, &**pool).await? as u64)
+            } else {
+                None
+            };
+
+            // Expanded, grouped and counted responses depend on project data, and view counts
+            // change independently of the collection, so none of them can be validated with the
+            // collection's entity tag. The tag is computed from the uncompressed collection, as
+            // compression is applied afterwards by the app-wide middleware.
+            let etag = if expanded_projects.is_none()
+                && owner.is_none()
+                && project_groups.is_none()
+                && hidden_project_count.is_none()
+                && views.is_none()
+            {
+                Some(data.etag()?)
+            } else {
+                None
+            };
+
+            // Computed per user, so authenticated responses are kept out of shared caches
+            let editable = user_option
+                .as_ref()
+                .map(|x| is_authorized_collection_edit(&data, x));
+
+            let cache_control = collection_cache_control(data.status, editable.is_some());
+
+            if let Some(etag) = &etag {
+                let matches = req
+                    .headers()
+                    .get(header::IF_NONE_MATCH)
+                    .and_then(|x| x.to_str().ok())
+                    .map(|x| {
+                        x.split(',')
+                            .map(|x| x.trim().trim_start_matches("W/"))
+                            .any(|x| x == "*" || x == etag)
+                    })
+                    .unwrap_or(false);
+
+                if matches {
+                    return Ok(HttpResponse::NotModified()
+                        .insert_header((header::ETAG, etag.clone()))
+                        .insert_header((header::CACHE_CONTROL, cache_control))
+                        .insert_header((header::VARY, "Authorization"))
+                        .finish());
+                }
+            }
+
+            let mut collection = Collection::from(data);
+            collection.expanded_projects = expanded_projects;
+            collection.project_groups = project_groups;
+            collection.hidden_project_count = hidden_project_count;
+            collection.editable = editable;
+            collection.project_count = project_count;
+            collection.views = views;
+            collection.owner = owner;
+            if let Some(child_collections) = visible_child_collections {
+                collection.child_collections = child_collections;
+            } else {
+                filter_authorized_child_collections(
+                    std::slice::from_mut(&mut collection),
+                    &user_option,
+                    &pool,
+                )
+                .await?;
+            }
+
+            let mut response = HttpResponse::Ok();
+            response
+                .insert_header((header::CACHE_CONTROL, cache_control))
+                .insert_header((header::VARY, "Authorization"));
+            if let Some(etag) = etag {
+                response.insert_header((header::ETAG, etag));
+            }
+
+            return Ok(response.json(collection));
+        }
+
+        // Only the owner learns that the collection exists, everyone else gets the usual 404
+        if query.scope_hint {
+            if let Ok((scopes, user)) =
+                get_user_from_headers(&req, &**pool, &redis, &session_queue, None).await
+            {
+                if user.id == data.user_id.into() && !scopes.contains(Scopes::COLLECTION_READ) {
+                    return Ok(HttpResponse::Forbidden().json(crate::models::error::ApiError {
+                        error: "insufficient_scope",
+                        description: "This token is missing the COLLECTION_READ scope required to read this collection",
+                    }));
+                }
+            }
+        }
+    }
+    Err(ApiError::NotFound)
+}
+
+// The IP of the client making a request, as reported by Cloudflare when it is in front of us
+fn request_ip(req: &HttpRequest) -> Option<String> {
+    let conn_info = req.connection_info().clone();
+    let ip_addr = if parse_var("CLOUDFLARE_INTEGRATION").unwrap_or(false) {
+        if let Some(header) = req.headers().get("CF-Connecting-IP") {
+            header.to_str().ok()
+        } else {
+            conn_info.peer_addr()
+        }
+    } else {
+        conn_info.peer_addr()
+    };
+
+    ip_addr.map(|x| x.to_string())
+}
+
+lazy_static! {
+    // An `@` which doesn't follow a word character, so email addresses aren't matched
+    static ref RE_PROJECT_MENTION: Regex = Regex::new(r"(?:^|[^\w@])@([\w-]{3,64})").unwrap();
+}
+
+/// The most project mentions in a body which are resolved
+const MAX_PROJECT_MENTIONS: usize = 32;
+
+// The distinct `@slug` mentions in a markdown body, in the order they first appear
+fn parse_project_mentions(body: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+
+    for captures in RE_PROJECT_MENTION.captures_iter(body) {
+        let mention = &captures[1];
+        if !mentions.iter().any(|x| x.eq_ignore_ascii_case(mention)) {
+            mentions.push(mention.to_string());
+        }
+        if mentions.len() >= MAX_PROJECT_MENTIONS {
+            break;
+        }
+    }
+
+    mentions
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionBodyQuery {
+    /// Return the rendered body as JSON, along with the projects it mentions
+    #[serde(default)]
+    pub mentions: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionProjectMention {
+    /// The mention as it was written, without the `@`
+    pub mention: String,
+    pub project_id: ProjectId,
+    pub slug: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionBody {
+    pub body_html: String,
+    /// The `@slug` mentions which name a project the user can see. Other mentions are left as
+    /// plain text.
+    pub mentioned_projects: Vec<CollectionProjectMention>,
+}
+
+pub async fn collection_body_get(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionBodyQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_data = database::models::Collection::get(id, &**pool, &redis).await?;
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    if let Some(data) = collection_data {
+        if is_authorized_collection(&data, &user_option).await? {
+            if !query.mentions {
+                return Ok(HttpResponse::Ok()
+                    .content_type("text/html; charset=utf-8")
+                    .body(data.body_html));
+            }
+
+            // Mentions are resolved when the body is read, so they follow projects' slug changes
+            let mentions = parse_project_mentions(&data.body);
+            let projects = database::models::Project::get_many(&mentions, &**pool, &redis).await?;
+            let projects = filter_authorized_projects(projects, &user_option, &pool).await?;
+
+            let mentioned_projects = mentions
+                .into_iter()
+                .filter_map(|mention| {
+                    let project = projects
+                        .iter()
+                        .find(|x| project_matches(x.id.0, x.slug.as_ref(), &mention))?;
+
+                    Some(CollectionProjectMention {
+                        project_id: project.id,
+                        slug: project.slug.clone(),
+                        mention,
+                    })
+                })
+                .collect();
+
+            return Ok(HttpResponse::Ok().json(CollectionBody {
+                body_html: data.body_html,
+                mentioned_projects,
+            }));
+        }
+    }
+    Err(ApiError::NotFound)
+}
+
+#[derive(Deserialize, Validate)]
+pub struct EditCollection {
+    #[validate(length(min = 3), custom(function = "validate_collection_title"))]
+    pub title: Option<String>,
+    #[validate(custom(function = "validate_collection_description"))]
+    pub description: Option<String>,
+    /// A new slug for the collection, or null to remove it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::double_option"
+    )]
+    #[validate(
+        length(min = 3, max = 64),
+        regex = "crate::util::validate::RE_LOWERCASE_SLUG",
+        custom(function = "validate_collection_slug")
+    )]
+    pub slug: Option<Option<String>>,
+    #[validate(length(max = 65536))]
+    pub body: Option<String>,
+    pub status: Option<CollectionStatus>,
+    /// Replaces the categories of the collection
+    #[validate(length(max = 8))]
+    pub categories: Option<Vec<String>>,
+    /// Projects to add to the collection, or to replace its projects with, depending on the
+    /// `project_mode` of the edit
+    #[validate(length(max = 64))]
+    pub new_projects: Option<Vec<String>>,
+    /// The display order of projects in the collection. Projects which are not listed keep
+    /// their relative order after the listed ones.
+    #[validate(length(max = 64))]
+    pub ordered_projects: Option<Vec<String>>,
+    /// Replaces the collections contained in this collection
+    #[validate(length(max = 64))]
+    pub child_collections: Option<Vec<String>>,
+    /// A member project to highlight at the top of the collection, or null to clear it
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::double_option"
+    )]
+    pub featured_project: Option<Option<String>>,
+}
+
+impl EditCollection {
+    /// The permissions needed to make this edit
+    pub fn required_permissions(
+        &self,
+        project_mode: CollectionProjectMode,
+    ) -> CollectionPermissions {
+        let mut permissions = CollectionPermissions::empty();
+
+        if self.new_projects.is_some() {
+            permissions |= match project_mode {
+                CollectionProjectMode::Merge => CollectionPermissions::ADD_PROJECTS,
+                CollectionProjectMode::Replace => {
+                    CollectionPermissions::ADD_PROJECTS | CollectionPermissions::REMOVE_PROJECTS
+                }
+            };
+        }
+
+        if self.title.is_some()
+            || self.description.is_some()
+            || self.slug.is_some()
+            || self.body.is_some()
+            || self.status.is_some()
+            || self.categories.is_some()
+            || self.ordered_projects.is_some()
+            || self.child_collections.is_some()
+            || self.featured_project.is_some()
+        {
+            permissions |= CollectionPermissions::EDIT_METADATA;
+        }
+
+        permissions
+    }
+}
+
+/// The largest collection edit body which is read. The longest edit is a `body` of 65536
+/// characters of up to 4 bytes each, with room to spare for the other fields and project lists.
+const COLLECTION_EDIT_MAX_BODY_SIZE: usize = 512 * 1024;
+
+// Rejects oversized edits before they are read in full, rather than after deserializing them
+fn collection_edit_json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(COLLECTION_EDIT_MAX_BODY_SIZE)
+        .error_handler(|err, _req| match err {
+            actix_web::error::JsonPayloadError::Overflow { .. }
+            | actix_web::error::JsonPayloadError::OverflowKnownLength { .. } => {
+                ApiError::PayloadTooLarge(format!(
+                    "Collection edits can be at most {COLLECTION_EDIT_MAX_BODY_SIZE} bytes"
+                ))
+                .into()
+            }
+            err => ApiError::Validation(err.to_string()).into(),
+        })
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EditCollectionQuery {
+    /// Validate the edit and return a preview of the result without persisting it
+    #[serde(default)]
+    pub dry_run: bool,
+    /// How `new_projects` changes the projects of the collection
+    #[serde(default)]
+    pub project_mode: CollectionProjectMode,
+    /// Respond with `CollectionEditWarnings` about things which look unintended but don't stop
+    /// the edit, such as adding archived projects. Dry runs don't include warnings.
+    #[serde(default)]
+    pub warnings: bool,
+}
+
+/// Non-fatal issues with an edit which was committed anyway
+#[derive(Serialize, Deserialize)]
+pub struct CollectionEditWarnings {
+    pub warnings: Vec<String>,
+}
+
+/// Descriptions shorter than this are allowed, but get a warning
+const COLLECTION_DESCRIPTION_SHORT_LENGTH: usize = 16;
+
+fn description_warning(description: &str) -> Option<String> {
+    (description.trim().chars().count() < COLLECTION_DESCRIPTION_SHORT_LENGTH).then(|| {
+        format!(
+            "The description is very short, consider describing the collection in at least {COLLECTION_DESCRIPTION_SHORT_LENGTH} characters"
+        )
+    })
+}
+
+// Collects the warnings for an edit, given the projects it added
+async fn collection_edit_warnings(
+    new_collection: &EditCollection,
+    added_projects: &[database::models::ProjectId],
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+) -> Result<Vec<String>, ApiError> {
+    let mut warnings = Vec::new();
+
+    if !added_projects.is_empty() {
+        let archived_count = database::models::Project::get_many_ids(added_projects, pool, redis)
+            .await?
+            .into_iter()
+            .filter(|x| x.inner.status == ProjectStatus::Archived)
+            .count();
+
+        if archived_count > 0 {
+            warnings.push(format!("{archived_count} added project(s) are archived"));
+        }
+    }
+
+    if let Some(warning) = new_collection
+        .description
+        .as_deref()
+        .and_then(description_warning)
+    {
+        warnings.push(warning);
+    }
+
+    Ok(warnings)
+}
+
+/// How the `new_projects` of an edit change the projects of a collection
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionProjectMode {
+    /// Adds the listed projects after the existing ones, keeping everything else
+    #[default]
+    Merge,
+    /// Replaces the projects with the listed ones, so an empty list empties the collection.
+    /// This is how v2 edits behave.
+    Replace,
+}
+
+impl CollectionProjectMode {
+    /// The projects of a collection after `new_projects` are applied
+    pub fn apply(
+        self,
+        existing: &[database::models::ids::ProjectId],
+        new_projects: Vec<database::models::ids::ProjectId>,
+    ) -> Vec<database::models::ids::ProjectId> {
+        match self {
+            CollectionProjectMode::Merge => {
+                let mut projects = existing.to_vec();
+                projects.extend(new_projects.into_iter().filter(|x| !existing.contains(x)));
+                projects
+            }
+            CollectionProjectMode::Replace => new_projects,
+        }
+    }
+}
+
+/// The result an edit would have, returned in dry-run mode
+#[derive(Serialize, Deserialize)]
+pub struct CollectionEditPreview {
+    pub title: String,
+    pub description: String,
+    pub slug: Option<String>,
+    pub status: CollectionStatus,
+    pub categories: Vec<String>,
+    pub featured_project: Option<ProjectId>,
+    pub projects: Vec<ProjectId>,
+    pub child_collections: Vec<CollectionId>,
+    pub added_projects: Vec<ProjectId>,
+    pub removed_projects: Vec<ProjectId>,
+}
+
+// Collections need a few projects to be listed, so public browsing isn't filled with empty ones
+fn check_listed_project_count(project_count: i64) -> Result<(), ApiError> {
+    let min_projects = collection_item::listed_min_projects();
+    if project_count < min_projects {
+        return Err(ApiError::InvalidInput(format!(
+            "A collection needs at least {min_projects} projects to be listed, and this one has {project_count}!"
+        )));
+    }
+
+    Ok(())
+}
+
+// Checks that replacing `removed` project memberships of the owner's collections with `added`
+// ones keeps them under the configured limit. The limit applies to the owner, whoever makes the
+// change, but moderators making it are not limited.
+async fn check_collection_projects_limit(
+    user: &crate::models::users::User,
+    owner_id: database::models::UserId,
+    removed: usize,
+    added: usize,
+    pool: &PgPool,
+) -> Result<(), ApiError> {
+    if user.role.is_mod() || added <= removed {
+        return Ok(());
+    }
+
+    let limit = collection_item::collection_projects_limit();
+    let current = database::models::User::get_collected_projects_count(owner_id, pool).await?;
+
+    if current - removed as i64 + added as i64 > limit {
+        return Err(ApiError::InvalidInput(
+            if database::models::UserId::from(user.id) == owner_id {
+                format!(
+                    "You can have at most {limit} projects across all of your collections, and you currently have {current}!"
+                )
+            } else {
+                format!(
+                    "The owner of this collection can have at most {limit} projects across all of their collections, and they currently have {current}!"
+                )
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+// Projects which aren't publicly visible, such as drafts, can only be collected by their members.
+// Anyone else gets an error naming the project rather than a collection with a broken entry.
+async fn check_project_collectable(
+    project: &project_item::QueryProject,
+    user_option: &Option<crate::models::users::User>,
+    pool: &web::Data<PgPool>,
+) -> Result<(), ApiError> {
+    if !is_authorized(&project.inner, user_option, pool).await? {
+        return Err(ApiError::InvalidInput(format!(
+            "The specified project {} is not publicly visible and cannot be added to a collection!",
+            ProjectId::from(project.inner.id)
+        )));
+    }
+
+    Ok(())
+}
+
+// Resolves project ids or slugs to the projects they name, in the order given. Ids are checked in
+// one query without loading the projects, and only slugs fall back to a full lookup.
+async fn resolve_existing_project_ids(
+    project_strings: &[String],
+    pool: &PgPool,
+    redis: &deadpool_redis::Pool,
+) -> Result<Vec<Option<database::models::ids::ProjectId>>, ApiError> {
+    let parsed_ids = project_strings
+        .iter()
+        .map(|x| {
+            parse_base62(x)
+                .ok()
+                .map(|x| database::models::ids::ProjectId(x as i64))
+        })
+        .collect::<Vec<_>>();
+    let existing = database::models::Project::exists_many(
+        &parsed_ids.iter().flatten().copied().collect::<Vec<_>>(),
+        pool,
+    )
+    .await?;
+
+    // Slugs may also parse as base62, so anything which isn't an existing id is looked up as one
+    let slugs = project_strings
+        .iter()
+        .zip(&parsed_ids)
+        .filter(|(_, id)| !id.map(|x| existing.contains(&x)).unwrap_or(false))
+        .map(|(x, _)| x.clone())
+        .collect::<Vec<_>>();
+    let slug_projects = database::models::Project::get_many(&slugs, pool, redis).await?;
+
+    Ok(project_strings
+        .iter()
+        .zip(parsed_ids)
+        .map(|(project_string, id)| {
+            id.filter(|x| existing.contains(x)).or_else(|| {
+                slug_projects
+                    .iter()
+                    .find(|x| {
+                        x.inner
+                            .slug
+                            .as_ref()
+                            .map(|slug| slug.eq_ignore_ascii_case(project_string))
+                            .unwrap_or(false)
+                    })
+                    .map(|x| x.inner.id)
+            })
+        })
+        .collect())
+}
+
+// Resolves project ids or slugs in one batch, in the order given and without repeats. Every
+// project has to exist and be collectable by the user.
+async fn resolve_collection_projects(
+    project_strings: &[String],
+    user_option: &Option<crate::models::users::User>,
+    pool: &web::Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+) -> Result<Vec<database::models::ids::ProjectId>, ApiError> {
+    let projects = database::models::Project::get_many(project_strings, &***pool, redis).await?;
+
+    let mut project_ids = Vec::new();
+    for project_string in project_strings {
+        let project = projects
+            .iter()
+            .find(|x| project_matches(x.inner.id.0 as u64, x.inner.slug.as_ref(), project_string))
+            .ok_or_else(|| {
+                ApiError::InvalidInput(format!(
+                    "The specified project {project_string} does not exist!"
+                ))
+            })?;
+        check_project_collectable(project, user_option, pool).await?;
+
+        if !project_ids.contains(&project.inner.id) {
+            project_ids.push(project.inner.id);
+        }
+    }
+
+    Ok(project_ids)
+}
+
+// Resolves the child collections of an edit. Children have to be visible to the editor, and
+// can't contain the parent themselves, directly or through their own children.
+async fn resolve_child_collections(
+    parent_id: database::models::CollectionId,
+    child_ids: &[String],
+    user_option: &Option<crate::models::users::User>,
+    pool: &web::Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+) -> Result<Vec<database::models::CollectionId>, ApiError> {
+    let mut ids = Vec::new();
+    for child_id in child_ids {
+        let id = parse_collection_id(child_id)?;
+        if id == parent_id {
+            return Err(ApiError::InvalidInput(
+                "A collection cannot contain itself!".to_string(),
+            ));
+        }
+
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    let children = database::models::Collection::get_many(&ids, &***pool, redis).await?;
+    for id in &ids {
+        let child = children.iter().find(|x| x.id == *id);
+
+        let visible = if let Some(child) = child {
+            is_authorized_collection(child, user_option).await?
+        } else {
+            false
+        };
+        if !visible {
+            return Err(ApiError::InvalidInput(format!(
+                "The specified collection {} does not exist!",
+                CollectionId::from(*id)
+            )));
+        }
+    }
+
+    if database::models::Collection::is_descendant_of(parent_id, &ids, &***pool).await? {
+        return Err(ApiError::InvalidInput(
+            "A collection cannot contain a collection which already contains it!".to_string(),
+        ));
+    }
+
+    Ok(ids)
+}
+
+fn edit_permission_error() -> ApiError {
+    ApiError::CustomAuthentication("You don't have permission to edit this collection!".to_string())
+}
+
+// Checks that the permissions granted to a user include all of the required ones. Users without
+// any permissions get the same error as anyone else who can't edit the collection.
+fn check_collection_permissions(
+    granted: CollectionPermissions,
+    required: CollectionPermissions,
+) -> Result<(), ApiError> {
+    if granted.is_empty() {
+        return Err(edit_permission_error());
+    }
+
+    let missing = required - granted;
+    if !missing.is_empty() {
+        return Err(ApiError::Forbidden(format!(
+            "You are missing the {} permission for this collection!",
+            missing.names().join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+fn check_status_permission(
+    collection: &database::models::Collection,
+    status: &CollectionStatus,
+    user: &crate::models::users::User,
+) -> Result<(), ApiError> {
+    if !collection
+        .status
+        .can_transition_to(*status, user.role.is_mod())
+    {
+        return Err(ApiError::InvalidInput(format!(
+            "The status of this collection cannot be changed from {} to {}",
+            collection.status, status
+        )));
+    }
+
+    Ok(())
+}
+
+// The collection version a client expects to edit, from an `If-Match` header. Versions may be
+// quoted like entity tags, and `*` matches any version.
+fn parse_expected_version(headers: &header::HeaderMap) -> Result<Option<i32>, ApiError> {
+    let value = if let Some(value) = headers.get(header::IF_MATCH) {
+        value
+    } else {
+        return Ok(None);
+    };
+
+    let value = value
+        .to_str()
+        .map(|x| x.trim().trim_start_matches("W/").trim_matches('"'))
+        .unwrap_or_default();
+    if value == "*" {
+        return Ok(None);
+    }
+
+    value.parse::<i32>().map(Some).map_err(|_| {
+        ApiError::InvalidInput("The If-Match header must contain a collection version!".to_string())
+    })
+}
+
+fn version_conflict() -> HttpResponse {
+    HttpResponse::Conflict().json(crate::models::error::ApiError {
+        error: "version_conflict",
+        description: "The collection has been modified since the expected version",
+    })
+}
+
+/// What an edit resolves to, validated without writing anything, so dry runs are checked exactly
+/// like the edits they preview
+struct CollectionEditPlan {
+    status: CollectionStatus,
+    /// The projects given in `new_projects`, in their order
+    new_projects: Vec<database::models::ProjectId>,
+    /// The projects given in `ordered_projects`, without duplicates
+    ordered_projects: Option<Vec<database::models::ProjectId>>,
+    /// All projects of the collection after the edit, in their order
+    projects: Vec<database::models::ProjectId>,
+    featured_project: Option<database::models::ProjectId>,
+    child_collections: Vec<database::models::CollectionId>,
+    added_projects: Vec<database::models::ProjectId>,
+    removed_projects: Vec<database::models::ProjectId>,
+}
+
+async fn plan_collection_edit(
+    collection_item: &database::models::Collection,
+    new_collection: &EditCollection,
+    project_mode: CollectionProjectMode,
+    user_option: &Option<crate::models::users::User>,
+    pool: &web::Data<PgPool>,
+    redis: &deadpool_redis::Pool,
+) -> Result<CollectionEditPlan, ApiError> {
+    let mut status = collection_item.status;
+    if let (Some(new_status), Some(user)) = (&new_collection.status, user_option) {
+        check_status_permission(collection_item, new_status, user)?;
+        status = *new_status;
+    }
+
+    let mut projects = collection_item.projects.clone();
+    let mut new_projects = Vec::new();
+    if let Some(new_project_ids) = &new_collection.new_projects {
+        new_projects =
+            resolve_collection_projects(new_project_ids, user_option, pool, redis).await?;
+        projects = project_mode.apply(&projects, new_projects.clone());
+
+        if let Some(user) = user_option {
+            check_collection_projects_limit(
+                user,
+                collection_item.user_id,
+                collection_item.projects.len(),
+                projects.len(),
+                pool,
+            )
+            .await?;
+        }
+    }
+
+    let mut ordered_projects = None;
+    if let Some(ordered_project_ids) = &new_collection.ordered_projects {
+        let mut ordered_ids = Vec::new();
+
+        let resolved_ids = resolve_existing_project_ids(ordered_project_ids, pool, redis).await?;
+        for (project_id, resolved_id) in ordered_project_ids.iter().zip(resolved_ids) {
+            let resolved_id = resolved_id
+                .filter(|x| projects.contains(x))
+                .ok_or_else(|| {
+                    ApiError::InvalidInput(format!(
+                        "The specified project {project_id} is not in this collection!"
+                    ))
+                })?;
+
+            if !ordered_ids.contains(&resolved_id) {
+                ordered_ids.push(resolved_id);
+            }
+        }
+
+        // Unordered projects are moved behind the ordered ones
+        projects.retain(|x| !ordered_ids.contains(x));
+        projects = ordered_ids.iter().copied().chain(projects).collect();
+        ordered_projects = Some(ordered_ids);
+    }
+
+    let featured_project = match &new_collection.featured_project {
+        Some(Some(project_id)) => {
+            let project = database::models::Project::get(project_id, &***pool, redis)
+                .await?
+                .filter(|x| projects.contains(&x.inner.id))
+                .ok_or_else(|| {
+                    ApiError::InvalidInput(format!(
+                        "The specified project {project_id} is not in this collection!"
+                    ))
+                })?;
+
+            Some(project.inner.id)
+        }
+        Some(None) => None,
+        None => collection_item
+            .featured_project
+            .filter(|x| projects.contains(x)),
+    };
+
+    let child_collections = if let Some(child_collection_ids) = &new_collection.child_collections {
+        resolve_child_collections(
+            collection_item.id,
+            child_collection_ids,
+            user_option,
+            pool,
+            redis,
+        )
+        .await?
+    } else {
+        collection_item.child_collections.clone()
+    };
+
+    // Checked against the projects after the edit, so projects can be added in the same edit
+    if status == CollectionStatus::Listed && collection_item.status != status {
+        check_listed_project_count(projects.len() as i64)?;
+    }
+
+    let added_projects = projects
+        .iter()
+        .filter(|x| !collection_item.projects.contains(x))
+        .copied()
+        .collect();
+    let removed_projects = collection_item
+        .projects
+        .iter()
+        .filter(|x| !projects.contains(x))
+        .copied()
+        .collect();
+
+    Ok(CollectionEditPlan {
+        status,
+        new_projects,
+        ordered_projects,
+        projects,
+        featured_project,
+        child_collections,
+        added_projects,
+        removed_projects,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn collection_edit(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(edit_query): web::Query<EditCollectionQuery>,
+    pool: web::Data<PgPool>,
+    new_collection: web::Json<EditCollection>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    new_collection
+        .validate()
+        .map_err(|err| ApiError::Validation(validation_errors_to_string(err, None)))?;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let result = database::models::Collection::get(id, &**pool, &redis).await?;
+
+    if let Some(collection_item) = result {
+        let Some(user) = &user_option else {
+            return Err(edit_permission_error());
+        };
+        check_collection_permissions(
+            collection_edit_permissions(&collection_item, user, &pool).await?,
+            new_collection.required_permissions(edit_query.project_mode),
+        )?;
+
+        let id = collection_item.id;
+        let expected_version = parse_expected_version(req.headers())?;
+
+        if let Some(categories) = &new_collection.categories {
+            check_collection_categories(categories, &pool).await?;
+        }
+        if let Some(Some(slug)) = &new_collection.slug {
+            check_collection_slug_available(slug, Some(id), &pool).await?;
+        }
+
+        let plan = plan_collection_edit(
+            &collection_item,
+            &new_collection,
+            edit_query.project_mode,
+            &user_option,
+            &pool,
+            &redis,
+        )
+        .await?;
+
+        if edit_query.dry_run {
+            if expected_version.is_some_and(|x| x != collection_item.version) {
+                return Ok(version_conflict());
+            }
+
+            return Ok(HttpResponse::Ok().json(CollectionEditPreview {
+                title: new_collection
+                    .title
+                    .as_deref()
+                    .map(normalize_text)
+                    .unwrap_or(collection_item.title),
+                description: new_collection
+                    .description
+                    .as_deref()
+                    .map(normalize_text)
+                    .unwrap_or(collection_item.description),
+                slug: new_collection.slug.clone().unwrap_or(collection_item.slug),
+                status: plan.status,
+                categories: new_collection
+                    .categories
+                    .clone()
+                    .unwrap_or(collection_item.categories),
+                featured_project: plan.featured_project.map(ProjectId::from),
+                projects: plan.projects.into_iter().map(ProjectId::from).collect(),
+                child_collections: plan
+                    .child_collections
+                    .into_iter()
+                    .map(CollectionId::from)
+                    .collect(),
+                added_projects: plan
+                    .added_projects
+                    .into_iter()
+                    .map(ProjectId::from)
+                    .collect(),
+                removed_projects: plan
+                    .removed_projects
+                    .into_iter()
+                    .map(ProjectId::from)
+                    .collect(),
+            }));
+        }
+
+        // Spans for each write, so slow edits can be broken down by step
+        let user_id = user_option.as_ref().map(|x| x.id.0);
+        let edit_span = |step: &'static str| {
+            tracing::debug_span!("collection_edit", step, collection_id = id.0, user_id)
+        };
+
+        let mut transaction = pool.begin().await?;
+
+        // The row stays locked until the edit is committed, so a concurrent edit waits and then
+        // sees the bumped version
+        let current_version =
+            database::models::Collection::lock_version(id, &mut transaction).await?;
+        if let Some(expected_version) = expected_version {
+            if current_version != Some(expected_version) {
+                return Ok(version_conflict());
+            }
+        }
+
+        write_collection_edit(
+            &collection_item,
+            &new_collection,
+            edit_query.project_mode,
+            &plan,
+            &user_option,
+            &edit_span,
+            &mut transaction,
+        )
+        .await?;
+
+        let warnings = if edit_query.warnings {
+            Some(
+                collection_edit_warnings(&new_collection, &plan.added_projects, &pool, &redis)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+        transaction.commit().instrument(edit_span("commit")).await?;
+
+        if let Some(warnings) = warnings {
+            return Ok(HttpResponse::Ok().json(CollectionEditWarnings { warnings }));
+        }
+
+        Ok(HttpResponse::NoContent().body(""))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+/// Writes a planned edit in its transaction, including the events it sends once committed
+async fn write_collection_edit(
+    collection_item: &database::models::Collection,
+    new_collection: &EditCollection,
+    project_mode: CollectionProjectMode,
+    plan: &CollectionEditPlan,
+    user_option: &Option<crate::models::users::User>,
+    edit_span: &impl Fn(&'static str) -> tracing::Span,
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+) -> Result<(), ApiError> {
+    let id = collection_item.id;
+
+    if let Some(title) = &new_collection.title {
+        database::models::Collection::set_title(id, &normalize_text(title), &mut *transaction)
+            .instrument(edit_span("title"))
+            .await?;
+    }
+
+    if let Some(description) = &new_collection.description {
+        database::models::Collection::set_description(
+            id,
+            &normalize_text(description),
+            &mut *transaction,
+        )
+        .instrument(edit_span("description"))
+        .await?;
+    }
+
+    if let Some(slug) = &new_collection.slug {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET slug = $1
+            WHERE (id = $2)
+            ",
+            slug.as_ref(),
+            id as database::models::ids::CollectionId,
+        )
+        .execute(&mut *transaction)
+        .instrument(edit_span("slug"))
+        .await?;
+    }
+
+    if let Some(body) = &new_collection.body {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET body = $1, body_html = $2
+            WHERE (id = $3)
+            ",
+            body,
+            render_markdown(body),
+            id as database::models::ids::CollectionId,
+        )
+        .execute(&mut *transaction)
+        .instrument(edit_span("body"))
+        .await?;
+    }
+
+    if let Some(categories) = &new_collection.categories {
+        database::models::Collection::set_categories(id, categories, &mut *transaction)
+            .instrument(edit_span("categories"))
+            .await?;
+    }
+
+    let mut listing = None;
+    if let Some(status) = &new_collection.status {
+        if let Some(user) = &user_option {
+            if *status != collection_item.status {
+                listing = ListingChange::between(collection_item.status, *status);
+
+                collection_item::CollectionHistoryEntry {
+                    collection_id: id,
+                    actor_id: user.id.into(),
+                    change: collection_item::CollectionChange::StatusChange {
+                        old_status: collection_item.status,
+                        new_status: *status,
+                    },
+                    created: Utc::now(),
+                }
+                .insert(&mut *transaction)
+                .await?;
+            }
+
+            database::models::Collection::set_status(id, *status, &mut *transaction)
+                .instrument(edit_span("status"))
+                .await?;
+        }
+    }
+
+    let mut membership_change = None;
+    if new_collection.new_projects.is_some() {
+        membership_change = Some((plan.added_projects.clone(), plan.removed_projects.clone()));
+
+        if project_mode == CollectionProjectMode::Replace {
+            database::models::Collection::clear_projects(collection_item.id, &mut *transaction)
+                .instrument(edit_span("clear_projects"))
+                .await?;
+        }
+
+        // Merged projects are added after the existing ones, which are left as they are
+        database::models::Collection::add_projects(
+            collection_item.id,
+            &plan.new_projects,
+            &mut *transaction,
+        )
+        .instrument(edit_span("add_projects"))
+        .await?;
+
+        database::models::Collection::notify_followers(
+            collection_item.id,
+            &plan.added_projects,
+            user_option.as_ref().map(|x| x.id.into()),
+            &mut *transaction,
+        )
+        .await?;
+    }
+
+    if let Some(ordered_ids) = &plan.ordered_projects {
+        let ordered_ids = ordered_ids.iter().map(|x| x.0).collect::<Vec<i64>>();
+
+        // Unordered projects are moved behind the ordered ones
+        sqlx::query!(
+            "
+            UPDATE collections_mods
+            SET ordinal = ordinal + $2
+            WHERE collection_id = $1 AND NOT (mod_id = ANY($3))
+            ",
+            collection_item.id as database::models::ids::CollectionId,
+            ordered_ids.len() as i32,
+            &ordered_ids,
+        )
+        .execute(&mut *transaction)
+        .instrument(edit_span("order_projects"))
+        .await?;
+
+        for (ordinal, project_id) in ordered_ids.iter().enumerate() {
+            sqlx::query!(
+                "
+                UPDATE collections_mods
+                SET ordinal = $3
+                WHERE collection_id = $1 AND mod_id = $2
+                ",
+                collection_item.id as database::models::ids::CollectionId,
+                project_id,
+                ordinal as i32,
+            )
+            .execute(&mut *transaction)
+            .instrument(edit_span("order_projects"))
+            .await?;
+        }
+    }
+
+    if new_collection.child_collections.is_some() {
+        database::models::Collection::set_children(
+            collection_item.id,
+            &plan.child_collections,
+            &mut *transaction,
+        )
+        .instrument(edit_span("child_collections"))
+        .await?;
+    }
+
+    if new_collection.featured_project.is_some() {
+        sqlx::query!(
+            "
+            UPDATE collections
+            SET featured_project = $1
+            WHERE (id = $2)
+            ",
+            plan.featured_project.map(|x| x.0),
+            id as database::models::ids::CollectionId,
+        )
+        .execute(&mut *transaction)
+        .instrument(edit_span("featured_project"))
+        .await?;
+    } else if new_collection.new_projects.is_some() {
+        database::models::Collection::clear_removed_featured_project(
+            collection_item.id,
+            &mut *transaction,
+        )
+        .await?;
+    }
+
+    if new_collection.new_projects.is_some() || new_collection.ordered_projects.is_some() {
+        database::models::Collection::update_colors(collection_item.id, &mut *transaction).await?;
+    }
+
+    if new_collection.title.is_some()
+        || new_collection.description.is_some()
+        || new_collection.slug.is_some()
+        || new_collection.body.is_some()
+        || new_collection.status.is_some()
+        || new_collection.categories.is_some()
+        || new_collection.new_projects.is_some()
+        || new_collection.ordered_projects.is_some()
+        || new_collection.featured_project.is_some()
+        || new_collection.child_collections.is_some()
+    {
+        database::models::Collection::touch(collection_item.id, &mut *transaction)
+            .instrument(edit_span("touch"))
+            .await?;
+    }
+
+    // Events are recorded in the edit's transaction, so they are delivered if and only if the
+    // edit is committed
+    let (added, removed) = membership_change.unwrap_or_default();
+    CollectionMutation {
+        collection_id: collection_item.id,
+        owner_id: collection_item.user_id,
+        editor_id: user_option.as_ref().map(|x| x.id.into()),
+        listing,
+        added,
+        removed,
+    }
+    .enqueue(&mut *transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CollectionProjects {
+    #[validate(length(min = 1, max = 64))]
+    pub projects: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionProjectsResult {
+    pub project_count: i64,
+}
+
+async fn count_collection_projects(
+    collection_id: database::models::ids::CollectionId,
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+) -> Result<i64, ApiError> {
+    let count = sqlx::query!(
+        "
+        SELECT COUNT(mod_id) count FROM collections_mods
+        WHERE collection_id = $1
+        ",
+        collection_id as database::models::ids::CollectionId,
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .count
+    .unwrap_or(0);
+
+    Ok(count)
+}
+
+pub async fn collection_projects_add(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionProjects>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    body.validate()
+        .map_err(|err| ApiError::Validation(validation_errors_to_string(err, None)))?;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    check_collection_permissions(
+        collection_edit_permissions(&collection_item, &user, &pool).await?,
+        CollectionPermissions::ADD_PROJECTS,
+    )?;
+
+    let project_ids =
+        resolve_collection_projects(&body.projects, &Some(user.clone()), &pool, &redis).await?;
+
+    let added_count = project_ids
+        .iter()
+        .filter(|x| !collection_item.projects.contains(x))
+        .count();
+    check_collection_projects_limit(&user, collection_item.user_id, 0, added_count, &pool).await?;
+
+    let mut transaction = pool.begin().await?;
+
+    // New projects are appended after the current members, in the order they were given
+    let added_projects = database::models::Collection::add_projects(
+        collection_item.id,
+        &project_ids,
+        &mut transaction,
+    )
+    .await?;
+
+    database::models::Collection::notify_followers(
+        collection_item.id,
+        &added_projects,
+        Some(user.id.into()),
+        &mut transaction,
+    )
+    .await?;
+
+    database::models::Collection::update_colors(collection_item.id, &mut transaction).await?;
+    database::models::Collection::touch(collection_item.id, &mut transaction).await?;
+    CollectionMutation {
+        collection_id: collection_item.id,
+        owner_id: collection_item.user_id,
+        editor_id: Some(user.id.into()),
+        listing: None,
+        added: added_projects,
+        removed: Vec::new(),
+    }
+    .enqueue(&mut transaction)
+    .await?;
+    let project_count = count_collection_projects(collection_item.id, &mut transaction).await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(CollectionProjectsResult { project_count }))
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct CollectionProjectsFromSearch {
+    pub query: Option<String>,
+    /// Facets in the format of the project search, eg: `[["categories:fabric"]]`
+    pub facets: Option<String>,
+    /// The project search index to order by, defaulting to `relevance`
+    pub index: Option<String>,
+    /// The most search results to add, defaulting to 20
+    #[validate(range(min = 1, max = 64))]
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionProjectsFromSearchResult {
+    pub added: u32,
+    /// Results which were already in the collection or can't be collected by the user
+    pub skipped: u32,
+    pub project_count: i64,
+}
+
+fn search_to_api_error(err: SearchError) -> ApiError {
+    match err {
+        SearchError::MeiliSearch(err) => ApiError::Search(err),
+        err => ApiError::InvalidInput(err.to_string()),
+    }
+}
+
+// Adds the results of a project search, in the order they were found
+#[allow(clippy::too_many_arguments)]
+pub async fn collection_projects_add_from_search(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionProjectsFromSearch>,
+    redis: web::Data<deadpool_redis::Pool>,
+    search_config: web::Data<SearchConfig>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    body.validate()
+        .map_err(|err| ApiError::Validation(validation_errors_to_string(err, None)))?;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    check_collection_permissions(
+        collection_edit_permissions(&collection_item, &user, &pool).await?,
+        CollectionPermissions::ADD_PROJECTS,
+    )?;
+
+    let search = SearchRequest {
+        query: body.query.clone(),
+        offset: None,
+        index: body.index.clone(),
+        limit: Some(body.limit.unwrap_or(20).to_string()),
+        new_filters: None,
+        facets: body.facets.clone(),
+        filters: None,
+        version: None,
+    };
+    let results = search_for_project(&search, &search_config)
+        .await
+        .map_err(search_to_api_error)?;
+    let hit_count = results.hits.len();
+    let result_ids = results
+        .hits
+        .into_iter()
+        .map(|x| x.project_id)
+        .collect::<Vec<_>>();
+
+    // The search index may lag behind, so visibility is checked against the database
+    let user_option = Some(user.clone());
+    let mut projects = database::models::Project::get_many(&result_ids, &**pool, &redis).await?;
+    let result_order = result_ids
+        .iter()
+        .filter_map(|x| parse_base62(x).ok())
+        .map(|x| database::models::ProjectId(x as i64))
+        .collect::<Vec<_>>();
+    collection_item::sort_by_ids(&mut projects, &result_order, |x| x.inner.id);
+
+    let mut project_ids = Vec::new();
+    for project in projects {
+        if collection_item.projects.contains(&project.inner.id)
+            || project_ids.contains(&project.inner.id)
+        {
+            continue;
+        }
+
+        if is_authorized(&project.inner, &user_option, &pool).await? {
+            project_ids.push(project.inner.id);
+        }
+    }
+
+    check_collection_projects_limit(&user, collection_item.user_id, 0, project_ids.len(), &pool)
+        .await?;
+
+    let mut transaction = pool.begin().await?;
+
+    let added_projects = database::models::Collection::add_projects(
+        collection_item.id,
+        &project_ids,
+        &mut transaction,
+    )
+    .await?;
+    let added = added_projects.len() as u32;
+
+    database::models::Collection::notify_followers(
+        collection_item.id,
+        &added_projects,
+        Some(user.id.into()),
+        &mut transaction,
+    )
+    .await?;
+
+    database::models::Collection::update_colors(collection_item.id, &mut transaction).await?;
+    database::models::Collection::touch(collection_item.id, &mut transaction).await?;
+    CollectionMutation {
+        collection_id: collection_item.id,
+        owner_id: collection_item.user_id,
+        editor_id: Some(user.id.into()),
+        listing: None,
+        added: added_projects,
+        removed: Vec::new(),
+    }
+    .enqueue(&mut transaction)
+    .await?;
+    let project_count = count_collection_projects(collection_item.id, &mut transaction).await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(CollectionProjectsFromSearchResult {
+        added,
+        skipped: hit_count as u32 - added,
+        project_count,
+    }))
+}
+
+pub async fn collection_projects_remove(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionProjects>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    body.validate()
+        .map_err(|err| ApiError::Validation(validation_errors_to_string(err, None)))?;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    check_collection_permissions(
+        collection_edit_permissions(&collection_item, &user, &pool).await?,
+        CollectionPermissions::REMOVE_PROJECTS,
+    )?;
+
+    // Projects that no longer exist cannot be members anymore, so they are skipped
+    let project_ids = resolve_existing_project_ids(&body.projects, &pool, &redis)
+        .await?
+        .into_iter()
+        .flatten()
+        .map(|x| x.0)
+        .collect::<Vec<i64>>();
+
+    let mut transaction = pool.begin().await?;
+
+    let removed = sqlx::query!(
+        "
+        DELETE FROM collections_mods
+        WHERE collection_id = $1 AND mod_id = ANY($2)
+        RETURNING mod_id
+        ",
+        collection_item.id as database::models::ids::CollectionId,
+        &project_ids,
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|x| database::models::ids::ProjectId(x.mod_id))
+    .collect();
+
+    database::models::Collection::clear_removed_featured_project(
+        collection_item.id,
+        &mut transaction,
+    )
+    .await?;
+    database::models::Collection::update_colors(collection_item.id, &mut transaction).await?;
+    database::models::Collection::touch(collection_item.id, &mut transaction).await?;
+    CollectionMutation {
+        collection_id: collection_item.id,
+        owner_id: collection_item.user_id,
+        editor_id: Some(user.id.into()),
+        listing: None,
+        added: Vec::new(),
+        removed,
+    }
+    .enqueue(&mut transaction)
+    .await?;
+    let project_count = count_collection_projects(collection_item.id, &mut transaction).await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(CollectionProjectsResult { project_count }))
+}
+
+pub async fn collection_projects_clear(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    check_collection_permissions(
+        collection_edit_permissions(&collection_item, &user, &pool).await?,
+        CollectionPermissions::REMOVE_PROJECTS,
+    )?;
+
+    let mut transaction = pool.begin().await?;
+
+    let removed =
+        database::models::Collection::clear_projects(collection_item.id, &mut transaction).await?;
+    database::models::Collection::clear_removed_featured_project(
+        collection_item.id,
+        &mut transaction,
+    )
+    .await?;
+    database::models::Collection::touch(collection_item.id, &mut transaction).await?;
+    CollectionMutation {
+        collection_id: collection_item.id,
+        owner_id: collection_item.user_id,
+        editor_id: Some(user.id.into()),
+        listing: None,
+        added: Vec::new(),
+        removed,
+    }
+    .enqueue(&mut transaction)
+    .await?;
+    let project_count = count_collection_projects(collection_item.id, &mut transaction).await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(CollectionProjectsResult { project_count }))
+}
+
+#[derive(Deserialize)]
+pub struct CollectionProjectMove {
+    /// The collection to move the project to
+    pub to: String,
+    pub project: String,
+}
+
+pub async fn collection_project_move(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionProjectMove>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let from_id = resolve_collection_id(&string, &pool).await?;
+    let to_id = resolve_collection_id(&body.to, &pool).await?;
+
+    if from_id == to_id {
+        return Err(ApiError::InvalidInput(
+            "A project cannot be moved to the collection it is already in!".to_string(),
+        ));
+    }
+
+    let from = database::models::Collection::get(from_id, &**pool, &redis).await?;
+    let to = database::models::Collection::get(to_id, &**pool, &redis).await?;
+    let (from, to) = if let (Some(from), Some(to)) = (from, to) {
+        (from, to)
+    } else {
+        return Err(ApiError::NotFound);
+    };
+
+    check_collection_permissions(
+        collection_edit_permissions(&from, &user, &pool).await?,
+        CollectionPermissions::REMOVE_PROJECTS,
+    )?;
+    check_collection_permissions(
+        collection_edit_permissions(&to, &user, &pool).await?,
+        CollectionPermissions::ADD_PROJECTS,
+    )?;
+
+    let project = database::models::Project::get(&body.project, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput(format!(
+                "The specified project {} does not exist!",
+                body.project
+            ))
+        })?;
+    check_project_collectable(&project, &Some(user.clone()), &pool).await?;
+
+    // Moving between the collections of one owner doesn't change how many projects they have
+    if from.user_id != to.user_id && !to.projects.contains(&project.inner.id) {
+        check_collection_projects_limit(&user, to.user_id, 0, 1, &pool).await?;
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    // The membership is checked by the delete itself, so a concurrent removal can't make the
+    // project vanish from both collections
+    let removed = sqlx::query!(
+        "
+        DELETE FROM collections_mods
+        WHERE collection_id = $1 AND mod_id = $2
+        ",
+        from.id as database::models::ids::CollectionId,
+        project.inner.id as database::models::ids::ProjectId,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if removed == 0 {
+        return Err(ApiError::InvalidInput(format!(
+            "The specified project {} is not in this collection!",
+            body.project
+        )));
+    }
+
+    let added_projects =
+        database::models::Collection::add_projects(to.id, &[project.inner.id], &mut transaction)
+            .await?;
+
+    database::models::Collection::notify_followers(
+        to.id,
+        &added_projects,
+        Some(user.id.into()),
+        &mut transaction,
+    )
+    .await?;
+
+    database::models::Collection::clear_removed_featured_project(from.id, &mut transaction).await?;
+    for id in [from.id, to.id] {
+        database::models::Collection::update_colors(id, &mut transaction).await?;
+        database::models::Collection::touch(id, &mut transaction).await?;
+    }
+
+    CollectionMutation {
+        collection_id: from.id,
+        owner_id: from.user_id,
+        editor_id: Some(user.id.into()),
+        listing: None,
+        added: Vec::new(),
+        removed: vec![project.inner.id],
+    }
+    .enqueue(&mut transaction)
+    .await?;
+    CollectionMutation {
+        collection_id: to.id,
+        owner_id: to.user_id,
+        editor_id: Some(user.id.into()),
+        listing: None,
+        added: added_projects,
+        removed: Vec::new(),
+    }
+    .enqueue(&mut transaction)
+    .await?;
+
+    database::models::Collection::clear_cache_many(&[from.id, to.id], &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct CollectionDeleteQuery {
+    /// Set to `snapshot` to respond with the collection as it was when deleted
+    #[serde(rename = "return")]
+    pub return_: Option<String>,
+}
+
+pub async fn collection_delete(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionDeleteQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let return_snapshot = match query.return_.as_deref() {
+        None => false,
+        Some("snapshot") => true,
+        Some(value) => {
+            return Err(ApiError::InvalidInput(format!(
+                "Unknown return value {value}, expected snapshot"
+            )))
+        }
+    };
+
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_DELETE]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            // Deleting an already deleted collection succeeds, so retried requests are harmless
+            if let (Some((owner_id, _)), Some(user)) = (
+                database::models::Collection::get_deleted(id, &**pool).await?,
+                &user_option,
+            ) {
+                if user.role.is_mod() || user.id == owner_id.into() {
+                    return Ok(HttpResponse::NoContent().body(""));
+                }
+            }
+
+            return Err(ApiError::InvalidInput(
+                "The specified collection does not exist!".to_string(),
+            ));
+        };
+    if !user_option
+        .as_ref()
+        .map(|x| is_authorized_collection_edit(&collection, x))
+        .unwrap_or(false)
+    {
+        return Err(edit_permission_error());
+    }
+    let mut transaction = pool.begin().await?;
+
+    let result =
+        database::models::Collection::remove(collection.id, &mut transaction, &redis).await?;
+    if result.is_some() {
+        CollectionMutation {
+            collection_id: collection.id,
+            owner_id: collection.user_id,
+            editor_id: user_option.as_ref().map(|x| x.id.into()),
+            listing: ListingChange::between(collection.status, CollectionStatus::Unlisted),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+        .enqueue(&mut transaction)
+        .await?;
+    }
+    database::models::Collection::clear_cache(collection.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    if result.is_none() {
+        Err(ApiError::NotFound)
+    } else if return_snapshot {
+        Ok(HttpResponse::Ok().json(Collection::from(collection)))
+    } else {
+        Ok(HttpResponse::NoContent().body(""))
+    }
+}
+
+pub async fn collection_restore(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_DELETE]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+
+    let (owner_id, deleted_at) =
+        if let Some(deleted) = database::models::Collection::get_deleted(id, &**pool).await? {
+            deleted
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    if user.id != owner_id.into() {
+        return Err(ApiError::NotFound);
+    }
+
+    if deleted_at < Utc::now() - Duration::days(collection_item::DELETED_RETENTION_DAYS) {
+        return Err(ApiError::InvalidInput(format!(
+            "Collections can only be restored within {} days of being deleted!",
+            collection_item::DELETED_RETENTION_DAYS
+        )));
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    let result = database::models::Collection::restore(id, &mut transaction, &redis).await?;
+    if let Some(status) = result {
+        CollectionMutation {
+            collection_id: id,
+            owner_id,
+            editor_id: Some(user.id.into()),
+            listing: ListingChange::between(CollectionStatus::Unlisted, status),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+        .enqueue(&mut transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+
+    if result.is_some() {
+        Ok(HttpResponse::NoContent().body(""))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CollectionTransfer {
+    /// Id or username of the user to transfer the collection to
+    pub user_id: String,
+}
+
+pub async fn collection_transfer(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionTransfer>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    let user = if let Some(user) = user_option
+        .as_ref()
+        .filter(|x| x.id == collection_item.user_id.into())
+    {
+        user
+    } else if is_authorized_collection(&collection_item, &user_option).await? {
+        return Err(ApiError::CustomAuthentication(
+            "Only the owner of a collection can transfer it!".to_string(),
+        ));
+    } else {
+        return Err(ApiError::NotFound);
+    };
+
+    let target = database::models::User::get(&body.user_id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput(format!(
+                "The specified user {} does not exist!",
+                body.user_id
+            ))
+        })?;
+
+    if target.id == collection_item.user_id {
+        return Err(ApiError::InvalidInput(
+            "You already own this collection!".to_string(),
+        ));
+    }
+
+    if target.is_banned(&**pool).await? {
+        return Err(ApiError::InvalidInput(format!(
+            "The user {} is banned and can't own collections!",
+            target.username
+        )));
+    }
+
+    // The projects count towards the new owner's limit like any they collect themselves
+    if !user.role.is_mod() {
+        let limit = collection_item::collection_projects_limit();
+        let current =
+            database::models::User::get_collected_projects_count(target.id, &**pool).await?;
+
+        if current + collection_item.projects.len() as i64 > limit {
+            return Err(ApiError::InvalidInput(format!(
+                "{} can have at most {limit} projects across all of their collections, and they currently have {current}!",
+                target.username
+            )));
+        }
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    // Shares were granted by the previous owner, so the new owner starts without any
+    sqlx::query!(
+        "
+        DELETE FROM collection_shares
+        WHERE collection_id = $1
+        ",
+        collection_item.id as database::models::ids::CollectionId,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "
+        UPDATE collections
+        SET user_id = $1
+        WHERE (id = $2)
+        ",
+        target.id as database::models::ids::UserId,
+        collection_item.id as database::models::ids::CollectionId,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    collection_item::CollectionHistoryEntry {
+        collection_id: collection_item.id,
+        actor_id: user.id.into(),
+        change: collection_item::CollectionChange::Transfer {
+            old_owner_id: collection_item.user_id,
+            new_owner_id: target.id,
+        },
+        created: Utc::now(),
+    }
+    .insert(&mut transaction)
+    .await?;
+
+    NotificationBuilder {
+        body: NotificationBody::CollectionTransfer {
+            collection_id: collection_item.id.into(),
+            old_owner_id: collection_item.user_id.into(),
+        },
+    }
+    .insert(target.id, &mut transaction)
+    .await?;
+
+    // A listed collection is unlisted for its old owner and listed again for the new one
+    database::models::Collection::touch(collection_item.id, &mut transaction).await?;
+    for (owner_id, before, after) in [
+        (
+            collection_item.user_id,
+            collection_item.status,
+            CollectionStatus::Unlisted,
+        ),
+        (
+            target.id,
+            CollectionStatus::Unlisted,
+            collection_item.status,
+        ),
+    ] {
+        CollectionMutation {
+            collection_id: collection_item.id,
+            owner_id,
+            editor_id: Some(user.id.into()),
+            listing: ListingChange::between(before, after),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+        .enqueue(&mut transaction)
+        .await?;
+    }
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CollectionModeration {
+    /// Either unlisted or rejected
+    pub status: CollectionStatus,
+    /// Why the collection was moderated, shown to its owner
+    #[validate(length(min = 3, max = 2048))]
+    pub reason: String,
+}
+
+pub async fn collection_moderate(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionModeration>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = check_is_moderator_from_headers(&req, &**pool, &redis, &session_queue).await?;
+
+    body.validate()
+        .map_err(|err| ApiError::Validation(validation_errors_to_string(err, None)))?;
+
+    if !matches!(
+        body.status,
+        CollectionStatus::Unlisted | CollectionStatus::Rejected
+    ) {
+        return Err(ApiError::InvalidInput(
+            "Collections can only be moderated to unlisted or rejected!".to_string(),
+        ));
+    }
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    let reason = body.reason.trim().to_string();
+    let mut transaction = pool.begin().await?;
+
+    // The owner permissions and `can_be_requested` don't apply, moderators can set either status
+    database::models::Collection::set_status(collection_item.id, body.status, &mut transaction)
+        .await?;
+
+    collection_item::CollectionHistoryEntry {
+        collection_id: collection_item.id,
+        actor_id: user.id.into(),
+        change: collection_item::CollectionChange::Moderation {
+            old_status: collection_item.status,
+            new_status: body.status,
+            reason: reason.clone(),
+        },
+        created: Utc::now(),
+    }
+    .insert(&mut transaction)
+    .await?;
+
+    NotificationBuilder {
+        body: NotificationBody::CollectionModerated {
+            collection_id: collection_item.id.into(),
+            new_status: body.status,
+            reason,
+        },
+    }
+    .insert(collection_item.user_id, &mut transaction)
+    .await?;
+
+    database::models::Collection::touch(collection_item.id, &mut transaction).await?;
+    CollectionMutation {
+        collection_id: collection_item.id,
+        owner_id: collection_item.user_id,
+        editor_id: Some(user.id.into()),
+        listing: ListingChange::between(collection_item.status, body.status),
+        added: Vec::new(),
+        removed: Vec::new(),
+    }
+    .enqueue(&mut transaction)
+    .await?;
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+// The title of a copy of a collection, shortened so the suffix fits within the title limits
+fn copy_title(title: &str) -> String {
+    use crate::util::validate::{NAME_MAX_CHARS, NAME_MAX_GRAPHEMES};
+    use unicode_segmentation::UnicodeSegmentation;
+
+    const SUFFIX: &str = " (copy)";
+
+    let mut shortened = String::new();
+    for (count, grapheme) in title.graphemes(true).enumerate() {
+        if count + SUFFIX.len() >= NAME_MAX_GRAPHEMES
+            || shortened.chars().count() + grapheme.chars().count() + SUFFIX.len() > NAME_MAX_CHARS
+        {
+            break;
+        }
+        shortened.push_str(grapheme);
+    }
+
+    format!("{}{SUFFIX}", shortened.trim_end())
+}
+
+pub async fn collection_duplicate(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    file_host: web::Data<Arc<dyn FileHost + Send + Sync>>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_CREATE]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let source =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    if !is_authorized_collection(&source, &Some(user.clone())).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    if !user.role.is_mod() {
+        if let Some(retry_after) = check_user_limit(
+            "collection_create",
+            user.id.into(),
+            parse_var("COLLECTION_CREATE_LIMIT").unwrap_or(30),
+            60 * 60,
+            &redis,
+        )
+        .await?
+        {
+            return Err(ApiError::RateLimited(retry_after));
+        }
+    }
+
+    // Projects the new owner can't add to a collection are left out of the copy
+    let user_option = Some(user.clone());
+    let mut projects = Vec::new();
+    for project in
+        database::models::Project::get_many_ids(&source.projects, &**pool, &redis).await?
+    {
+        if is_authorized(&project.inner, &user_option, &pool).await? {
+            projects.push(project.inner.id);
+        }
+    }
+    collection_item::sort_by_ids(&mut projects, &source.projects, |x| *x);
+
+    check_collection_projects_limit(&user, user.id.into(), 0, projects.len(), &pool).await?;
+
+    // Copies of collections which aren't public stay private to the new owner. Listed copies
+    // need enough projects, like collections which are edited to be listed.
+    let status = if source.status.is_hidden() {
+        CollectionStatus::Private
+    } else {
+        CollectionStatus::Listed
+    };
+    if status == CollectionStatus::Listed {
+        check_listed_project_count(projects.len() as i64)?;
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    let collection_id = generate_collection_id(&mut transaction).await?;
+
+    collection_item::CollectionBuilder {
+        collection_id,
+        user_id: user.id.into(),
+        title: copy_title(&source.title),
+        description: source.description.clone(),
+        // Slugs are unique, so copies start without one
+        slug: None,
+        status,
+        categories: source.categories.clone(),
+        projects,
+    }
+    .insert(&mut transaction)
+    .await?;
+    database::models::Collection::update_colors(collection_id, &mut transaction).await?;
+
+    let mut uploaded_icon = None;
+    if let Some(icon_url) = &source.icon_url {
+        let cdn_url = dotenvy::var("CDN_URL")?;
+
+        let img_data = icon_url.rsplit('.').next().and_then(|ext| {
+            crate::util::ext::get_image_content_type(ext).map(|content_type| (ext, content_type))
+        });
+
+        // Copies of collections whose icon is missing from the CDN start without one
+        let icon = match img_data {
+            Some(img_data) => fetch_icon(icon_url)
+                .await?
+                .map(|(_, bytes)| (img_data, bytes)),
+            None => None,
+        };
+
+        if let Some(((ext, content_type), bytes)) = icon {
+            let hash = sha1::Sha1::from(&bytes).hexdigest();
+
+            let upload_data = file_host
+                .upload_file(
+                    content_type,
+                    &format!(
+                        "data/{}/{}.{}",
+                        CollectionId::from(collection_id),
+                        hash,
+                        ext
+                    ),
+                    bytes,
+                )
+                .await?;
+
+            let result = database::models::Collection::set_icon(
+                collection_id,
+                &format!("{}/{}", cdn_url, upload_data.file_name),
+                source.color,
+                upload_data.content_length as i64,
+                source.icon_filename.as_deref(),
+                &mut transaction,
+            )
+            .await;
+
+            // Nothing references the upload until the copy is committed
+            if let Err(err) = result {
+                delete_or_defer(
+                    &upload_data.file_id,
+                    &upload_data.file_name,
+                    &***file_host,
+                    &pool,
+                )
+                .await?;
+                return Err(err.into());
+            }
+
+            uploaded_icon = Some((upload_data.file_id, upload_data.file_name));
+        }
+    }
+
+    if let Err(err) = transaction.commit().await {
+        if let Some((file_id, file_name)) = uploaded_icon {
+            delete_or_defer(&file_id, &file_name, &***file_host, &pool).await?;
+        }
+        return Err(err.into());
+    }
+
+    let collection = database::models::Collection::get(collection_id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The duplicated collection could not be found!".to_string())
+        })?;
+
+    Ok(HttpResponse::Ok().json(Collection::from(collection)))
+}
+
+pub async fn collection_follow(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection = database::models::Collection::get(id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+
+    let user_id: database::models::ids::UserId = user.id.into();
+
+    if !is_authorized_collection(&collection, &Some(user)).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    // The follow is inserted first, so concurrent follows can't count the same follower twice
+    let mut transaction = pool.begin().await?;
+
+    let inserted = sqlx::query!(
+        "
+        INSERT INTO collection_follows (follower_id, collection_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        ",
+        user_id as database::models::ids::UserId,
+        collection.id as database::models::ids::CollectionId
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Err(ApiError::InvalidInput(
+            "You are already following this collection!".to_string(),
+        ));
+    }
+
+    sqlx::query!(
+        "
+        UPDATE collections
+        SET follows = follows + 1
+        WHERE id = $1
+        ",
+        collection.id as database::models::ids::CollectionId,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    database::models::Collection::clear_cache(collection.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+/// The most collections a user may pin
+const MAX_PINNED_COLLECTIONS: i64 = 20;
+
+// Pins are a private bookmark list, so unlike follows they don't change the collection
+pub async fn collection_pin(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection = database::models::Collection::get(id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+
+    let user_id: database::models::ids::UserId = user.id.into();
+
+    if !is_authorized_collection(&collection, &Some(user)).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    let pins = sqlx::query!(
+        "
+        SELECT COUNT(*) count, BOOL_OR(cp.collection_id = $2) pinned, MAX(cp.ordinal) max_ordinal
+        FROM collection_pins cp
+        WHERE cp.user_id = $1
+        ",
+        user_id as database::models::ids::UserId,
+        collection.id as database::models::ids::CollectionId
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    if pins.pinned.unwrap_or(false) {
+        return Err(ApiError::InvalidInput(
+            "You have already pinned this collection!".to_string(),
+        ));
+    }
+
+    if pins.count.unwrap_or(0) >= MAX_PINNED_COLLECTIONS {
+        return Err(ApiError::InvalidInput(format!(
+            "You can only pin up to {MAX_PINNED_COLLECTIONS} collections!"
+        )));
+    }
+
+    sqlx::query!(
+        "
+        INSERT INTO collection_pins (user_id, collection_id, ordinal)
+        VALUES ($1, $2, $3)
+        ",
+        user_id as database::models::ids::UserId,
+        collection.id as database::models::ids::CollectionId,
+        pins.max_ordinal.map(|x| x + 1).unwrap_or(0),
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+pub async fn collection_unpin(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection = database::models::Collection::get(id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+
+    let user_id: database::models::ids::UserId = user.id.into();
+
+    let result = sqlx::query!(
+        "
+        DELETE FROM collection_pins
+        WHERE user_id = $1 AND collection_id = $2
+        ",
+        user_id as database::models::ids::UserId,
+        collection.id as database::models::ids::CollectionId
+    )
+    .execute(&**pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::InvalidInput(
+            "You have not pinned this collection!".to_string(),
+        ));
+    }
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+pub async fn collection_unfollow(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection = database::models::Collection::get(id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+
+    let user_id: database::models::ids::UserId = user.id.into();
+
+    // Only the request which deleted the follow decrements the count
+    let mut transaction = pool.begin().await?;
+
+    let deleted = sqlx::query!(
+        "
+        DELETE FROM collection_follows
+        WHERE follower_id = $1 AND collection_id = $2
+        ",
+        user_id as database::models::ids::UserId,
+        collection.id as database::models::ids::CollectionId
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    if deleted == 0 {
+        return Err(ApiError::InvalidInput(
+            "You are not following this collection!".to_string(),
+        ));
+    }
+
+    sqlx::query!(
+        "
+        UPDATE collections
+        SET follows = follows - 1
+        WHERE id = $1
+        ",
+        collection.id as database::models::ids::CollectionId,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    database::models::Collection::clear_cache(collection.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+pub async fn collection_history(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    check_is_moderator_from_headers(&req, &**pool, &redis, &session_queue).await?;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection = database::models::Collection::get(id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput("The specified collection does not exist!".to_string())
+        })?;
+
+    let history: Vec<CollectionHistoryEntry> =
+        collection_item::CollectionHistoryEntry::get_for_collection(collection.id, &**pool)
+            .await?
+            .into_iter()
+            .map(CollectionHistoryEntry::from)
+            .collect();
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsListQuery {
+    pub offset: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+/// A page of collections. The response also has `X-Total-Count` and `Link` headers with the
+/// same information, for clients which paginate through headers.
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsListPage {
+    pub collections: Vec<Collection>,
+    pub total_count: i64,
+    /// Relative URL of the next page, if there is one
+    pub next: Option<String>,
+    /// Relative URL of the previous page, if there is one
+    pub prev: Option<String>,
+}
+
+const COLLECTIONS_LIST_MAX_LIMIT: u32 = 100;
+
+// Adds the `X-Total-Count` header and an RFC 5988 `Link` header with the `next` and `prev` pages
+// to a paginated response, so generic clients can paginate without reading the body. The links
+// keep the other query parameters of the request.
+fn insert_pagination_headers(
+    response: &mut actix_web::HttpResponseBuilder,
+    req: &HttpRequest,
+    offset: u32,
+    limit: u32,
+    total_count: i64,
+) {
+    let page_url = |offset: u32| {
+        let mut query = req
+            .query_string()
+            .split('&')
+            .filter(|x| {
+                let key = x.split('=').next().unwrap_or_default();
+                !x.is_empty() && key != "offset" && key != "limit"
+            })
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        query.push(format!("offset={offset}"));
+        query.push(format!("limit={limit}"));
+
+        format!("{}?{}", req.path(), query.join("&"))
+    };
+
+    let mut links = Vec::new();
+    if (offset as i64 + limit as i64) < total_count {
+        links.push(format!("<{}>; rel=\"next\"", page_url(offset + limit)));
+    }
+    if offset > 0 {
+        links.push(format!(
+            "<{}>; rel=\"prev\"",
+            page_url(offset.saturating_sub(limit))
+        ));
+    }
+
+    response.insert_header(("X-Total-Count", total_count.to_string()));
+    if !links.is_empty() {
+        response.insert_header((header::LINK, links.join(", ")));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectCollectionMembership {
+    pub id: CollectionId,
+    pub title: String,
+    pub status: CollectionStatus,
+    /// Whether the project is in this collection
+    pub contains_project: bool,
+}
+
+// Lists the authenticated user's own collections, marking those which contain the project
+pub async fn project_collections_membership(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let project = database::models::Project::get(&string, &**pool, &redis).await?;
+    let user_id = user.id.into();
+    let user_option = Some(user);
+
+    let Some(project) = project else {
+        return Err(ApiError::NotFound);
+    };
+    if !is_authorized(&project.inner, &user_option, &pool).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    let memberships =
+        database::models::Collection::get_user_memberships(user_id, project.inner.id, &**pool)
+            .await?
+            .into_iter()
+            .map(
+                |(id, title, status, contains_project)| ProjectCollectionMembership {
+                    id: id.into(),
+                    title,
+                    status,
+                    contains_project,
+                },
+            )
+            .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(memberships))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectInCollectionsPage {
+    #[serde(flatten)]
+    pub page: CollectionSearchPage,
+    /// The number of unlisted and private collections containing the project, only included for
+    /// members of its team and moderators
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden_count: Option<i64>,
+}
+
+// Lists the listed collections containing a project, for discovering related curation. Hidden
+// collections are only counted, and only for the project's team.
+pub async fn project_in_collections(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionsListQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let string = info.into_inner().0;
+    let Some(project) = database::models::Project::get(&string, &**pool, &redis).await? else {
+        return Err(ApiError::NotFound);
+    };
+    if !is_authorized(&project.inner, &user_option, &pool).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(COLLECTIONS_LIST_MAX_LIMIT)
+        .clamp(1, COLLECTIONS_LIST_MAX_LIMIT);
+
+    let (collection_ids, total_hits) = database::models::Collection::get_listed_containing_project(
+        project.inner.id,
+        offset as i64,
+        limit as i64,
+        &**pool,
+    )
+    .await?;
+
+    let mut collections =
+        database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+    collection_item::sort_by_ids(&mut collections, &collection_ids, |x| x.id);
+
+    let hits = collections
+        .into_iter()
+        .map(|collection| {
+            let project_count = collection.projects.len() as u32;
+            let collection = Collection::from(collection);
+
+            CollectionSearchResult {
+                follower_count: collection.follows,
+                project_count,
+                collection,
+            }
+        })
+        .collect();
+
+    let mut hidden_count = None;
+    if let Some(user) = &user_option {
+        let is_team_member = user.role.is_mod()
+            || database::models::TeamMember::get_from_user_id(
+                project.inner.team_id,
+                user.id.into(),
+                &**pool,
+            )
+            .await?
+            .is_some();
+
+        if is_team_member {
+            hidden_count = Some(
+                database::models::Collection::count_hidden_containing_project(
+                    project.inner.id,
+                    &**pool,
+                )
+                .await?,
+            );
+        }
+    }
+
+    let mut response = HttpResponse::Ok();
+    insert_pagination_headers(&mut response, &req, offset, limit, total_hits);
+
+    Ok(response.json(ProjectInCollectionsPage {
+        page: CollectionSearchPage {
+            hits,
+            offset,
+            limit,
+            total_hits,
+        },
+        hidden_count,
+    }))
+}
+
+// Collections which were hidden from the user since they were pinned are left out, but stay
+// pinned in case they become visible again
+pub async fn user_pinned_collections(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await?
+    .1;
+
+    let collection_ids =
+        database::models::User::get_pinned_collections(user.id.into(), &**pool).await?;
+    let collections_data =
+        database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+    let mut collections =
+        filter_authorized_collections(collections_data, &Some(user), &pool).await?;
+
+    collection_item::sort_by_ids(&mut collections, &collection_ids, |x| x.id.into());
+
+    Ok(HttpResponse::Ok().json(collections))
+}
+
+pub async fn user_collections_list(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionsListQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let id_option = database::models::User::get(&info.into_inner().0, &**pool, &redis).await?;
+
+    if let Some(id) = id_option.map(|x| x.id) {
+        let user_id: crate::models::users::UserId = id.into();
+
+        let can_view_private = user
+            .as_ref()
+            .map(|y| y.role.is_mod() || y.id == user_id)
+            .unwrap_or(false);
+
+        let statuses = CollectionStatus::iterator()
+            .filter(|x| can_view_private || !x.is_hidden())
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+
+        let offset = query.offset.unwrap_or(0);
+        let limit = query
+            .limit
+            .unwrap_or(COLLECTIONS_LIST_MAX_LIMIT)
+            .clamp(1, COLLECTIONS_LIST_MAX_LIMIT);
+
+        let (collection_ids, total_count) = database::models::User::get_collections(
+            id,
+            &statuses,
+            offset as i64,
+            limit as i64,
+            &**pool,
+        )
+        .await?;
+
+        let collections_data =
+            database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+        let mut collections = filter_authorized_collections(collections_data, &user, &pool).await?;
+
+        collection_item::sort_by_ids(&mut collections, &collection_ids, |x| x.id.into());
+
+        let next = if (offset as i64 + limit as i64) < total_count {
+            Some(format!(
+                "user/{}/collections?offset={}&limit={}",
+                user_id,
+                offset + limit,
+                limit
+            ))
+        } else {
+            None
+        };
+        let prev = if offset > 0 {
+            Some(format!(
+                "user/{}/collections?offset={}&limit={}",
+                user_id,
+                offset.saturating_sub(limit),
+                limit
+            ))
+        } else {
+            None
+        };
+
+        let mut response = HttpResponse::Ok();
+        insert_pagination_headers(&mut response, &req, offset, limit, total_count);
+
+        Ok(response.json(CollectionsListPage {
+            collections,
+            total_count,
+            next,
+            prev,
+        }))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+pub async fn user_collections_stats(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let id_option = database::models::User::get(&info.into_inner().0, &**pool, &redis).await?;
+
+    if let Some(id) = id_option.map(|x| x.id) {
+        let user_id: crate::models::users::UserId = id.into();
+
+        let can_view_private = user
+            .as_ref()
+            .map(|y| y.role.is_mod() || y.id == user_id)
+            .unwrap_or(false);
+
+        let statuses = CollectionStatus::iterator()
+            .filter(|x| can_view_private || !x.is_hidden())
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+
+        let stats = database::models::User::get_collection_stats(id, &statuses, &**pool).await?;
+
+        Ok(HttpResponse::Ok().json(stats))
+    } else {
+        Err(ApiError::NotFound)
+    }
+}
+
+// Whether a version supports the loader and game version, for those which are given
+fn version_supports(
+    version: &database::models::version_item::QueryVersion,
+    loader: Option<&String>,
+    game_version: Option<&String>,
+) -> bool {
+    loader.is_none_or(|x| version.loaders.contains(x))
+        && game_version.is_none_or(|x| version.game_versions.contains(x))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionCompatibilityQuery {
+    pub game_version: Option<String>,
+    pub loader: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectCompatibility {
+    /// The project has a version supporting the loader and game version
+    Compatible,
+    /// None of the project's versions support the loader and game version
+    Incompatible,
+    /// The project has no versions, or isn't visible to the user
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionProjectCompatibility {
+    pub project_id: ProjectId,
+    pub compatibility: ProjectCompatibility,
+    /// The latest compatible version of the project, if it is compatible
+    pub version_id: Option<crate::models::ids::VersionId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionCompatibility {
+    pub compatible: u32,
+    pub incompatible: u32,
+    pub unknown: u32,
+    /// Each member project, in collection order
+    pub projects: Vec<CollectionProjectCompatibility>,
+}
+
+// Reports which member projects of a collection have a version for a loader and game version
+pub async fn collection_compatibility(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionCompatibilityQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let collection =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    if !is_authorized_collection(&collection, &user_option).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    let projects =
+        database::models::Project::get_many_ids(&collection.projects, &**pool, &redis).await?;
+    let projects = filter_authorized_projects(projects, &user_option, &pool).await?;
+
+    let version_ids = projects
+        .iter()
+        .flat_map(|x| x.versions.iter().map(|y| (*y).into()))
+        .collect::<Vec<database::models::ids::VersionId>>();
+    let versions = database::models::Version::get_many(&version_ids, &**pool, &redis).await?;
+    let compatible_ids = versions
+        .iter()
+        .filter(|x| version_supports(x, query.loader.as_ref(), query.game_version.as_ref()))
+        .map(|x| crate::models::ids::VersionId::from(x.inner.id))
+        .collect::<Vec<_>>();
+    let versions = filter_authorized_versions(versions, &user_option, &pool).await?;
+
+    let mut compatibility = CollectionCompatibility {
+        compatible: 0,
+        incompatible: 0,
+        unknown: 0,
+        projects: Vec::new(),
+    };
+    for project_id in collection.projects.into_iter().map(ProjectId::from) {
+        let project_versions = versions
+            .iter()
+            .filter(|x| x.project_id == project_id)
+            .collect::<Vec<_>>();
+        let latest_compatible = project_versions
+            .iter()
+            .filter(|x| compatible_ids.contains(&x.id))
+            .max_by_key(|x| x.date_published);
+
+        let project_compatibility =
+            if !projects.iter().any(|x| x.id == project_id) || project_versions.is_empty() {
+                compatibility.unknown += 1;
+                ProjectCompatibility::Unknown
+            } else if latest_compatible.is_some() {
+                compatibility.compatible += 1;
+                ProjectCompatibility::Compatible
+            } else {
+                compatibility.incompatible += 1;
+                ProjectCompatibility::Incompatible
+            };
+
+        compatibility.projects.push(CollectionProjectCompatibility {
+            project_id,
+            compatibility: project_compatibility,
+            version_id: latest_compatible.map(|x| x.id),
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(compatibility))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::ResponseError;
+
+    async fn error_body(err: ApiError) -> serde_json::Value {
+        let body = actix_web::body::to_bytes(err.error_response().into_body())
+            .await
+            .unwrap();
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn unauthorized_edit_uses_error_envelope() {
+        let err = edit_permission_error();
+        assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+
+        let body = error_body(err).await;
+        assert_eq!(body["error"], "unauthorized");
+        assert_eq!(
+            body["description"],
+            "Authentication Error: You don't have permission to edit this collection!"
+        );
+        assert_eq!(body.as_object().unwrap().len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn missing_collection_uses_error_envelope() {
+        let err = ApiError::NotFound;
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+
+        let body = error_body(err).await;
+        assert_eq!(body["error"], "not_found");
+        assert_eq!(body.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn parse_collection_ids_valid() {
+        let ids = parse_collection_ids(r#"["1", "a"]"#).unwrap();
+
+        assert_eq!(
+            ids,
+            vec![
+                database::models::CollectionId(1),
+                database::models::CollectionId(36)
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn parse_collection_ids_garbage() {
+        let err = parse_collection_ids("garbage").unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+
+        let body = error_body(err).await;
+        assert_eq!(body["error"], "invalid_input");
+        assert!(body["description"].as_str().unwrap().contains("garbage"));
+    }
+
+    #[actix_rt::test]
+    async fn parse_collection_ids_bad_token() {
+        let err = parse_collection_ids(r#"["AAAA", "not-base62"]"#).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+
+        let body = error_body(err).await;
+        assert_eq!(body["error"], "invalid_input");
+        assert!(body["description"].as_str().unwrap().contains("not-base62"));
+    }
+
+    #[test]
+    fn edit_collection_empty_projects() {
+        let edit: EditCollection = serde_json::from_str(r#"{"new_projects": []}"#).unwrap();
+
+        assert!(edit.validate().is_ok());
+        assert_eq!(edit.new_projects, Some(Vec::new()));
+    }
+
+    #[test]
+    fn dedup_project_ids_repeated() {
+        let (ids, duplicates) = dedup_project_ids(vec![
+            ProjectId(3),
+            ProjectId(1),
+            ProjectId(3),
+            ProjectId(2),
+            ProjectId(1),
+            ProjectId(3),
+        ]);
+
+        assert_eq!(ids, vec![ProjectId(3), ProjectId(1), ProjectId(2)]);
+        assert_eq!(duplicates, 3);
+    }
+
+    #[test]
+    fn expected_version_from_if_match() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(parse_expected_version(&headers).unwrap(), None);
+
+        headers.insert(header::IF_MATCH, header::HeaderValue::from_static("\"3\""));
+        assert_eq!(parse_expected_version(&headers).unwrap(), Some(3));
+
+        headers.insert(header::IF_MATCH, header::HeaderValue::from_static("*"));
+        assert_eq!(parse_expected_version(&headers).unwrap(), None);
+
+        headers.insert(header::IF_MATCH, header::HeaderValue::from_static("abc"));
+        assert!(parse_expected_version(&headers).is_err());
+    }
+
+    #[test]
+    fn collection_fields_match_serialization() {
+        let collection = Collection {
+            id: CollectionId(1),
+            user: crate::models::users::UserId(1),
+            title: String::new(),
+            description: String::new(),
+            slug: Some(String::new()),
+            body: String::new(),
+            icon_url: Some(String::new()),
+            color: Some(0),
+            colors: Vec::new(),
+            status: CollectionStatus::Listed,
+            categories: Vec::new(),
+            created: Utc::now(),
+            updated: Utc::now(),
+            follows: 0,
+            views: Some(0),
+            featured_project: Some(ProjectId(1)),
+            version: 1,
+            projects: Vec::new(),
+            child_collections: Vec::new(),
+            project_count: Some(0),
+            expanded_projects: Some(Vec::new()),
+            hidden_project_count: Some(0),
+            project_groups: Some(Vec::new()),
+            editable: Some(true),
+            owner: Some(CollectionOwner {
+                id: crate::models::users::UserId(1),
+                username: String::new(),
+                avatar_url: None,
+            }),
+        };
+        let value = serde_json::to_value(&collection).unwrap();
+        let mut keys = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut fields = COLLECTION_FIELDS
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        keys.sort();
+        fields.sort();
+
+        assert_eq!(keys, fields);
+        assert!(parse_collection_fields("id, title,icon_url").is_ok());
+        assert!(parse_collection_fields("id,owners").is_err());
+    }
+
+    #[test]
+    fn collection_timestamps_keep_fractional_seconds() {
+        let created = DateTime::parse_from_rfc3339("2023-08-28T12:00:00.123Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let updated = created + Duration::milliseconds(1);
+
+        let collection = Collection {
+            id: CollectionId(1),
+            user: crate::models::users::UserId(1),
+            title: String::new(),
+            description: String::new(),
+            slug: None,
+            body: String::new(),
+            icon_url: None,
+            color: None,
+            colors: Vec::new(),
+            status: CollectionStatus::Listed,
+            categories: Vec::new(),
+            created,
+            updated,
+            follows: 0,
+            views: None,
+            featured_project: None,
+            version: 1,
+            projects: Vec::new(),
+            child_collections: Vec::new(),
+            project_count: None,
+            expanded_projects: None,
+            hidden_project_count: None,
+            project_groups: None,
+            editable: None,
+            owner: None,
+        };
+        let value = serde_json::to_value(&collection).unwrap();
+
+        assert_eq!(value["created"], "2023-08-28T12:00:00.123Z");
+        assert_eq!(value["updated"], "2023-08-28T12:00:00.124Z");
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a database, run with `cargo test -- --ignored`"]
+    async fn edits_advance_the_updated_timestamp(pool: PgPool) {
+        let user_id = database::models::UserId(1);
+        let collection_id = database::models::CollectionId(1);
+
+        sqlx::query("INSERT INTO users (id, username) VALUES ($1, 'collector')")
+            .bind(user_id.0)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let mut transaction = pool.begin().await.unwrap();
+        collection_item::CollectionBuilder {
+            collection_id,
+            user_id,
+            title: "Magic mods".to_string(),
+            description: "All my favourite magic mods".to_string(),
+            slug: None,
+            status: CollectionStatus::Unlisted,
+            categories: Vec::new(),
+            projects: Vec::new(),
+        }
+        .insert(&mut transaction)
+        .await
+        .unwrap();
+        transaction.commit().await.unwrap();
+
+        // The edit goes through the same planning and writes as `collection_edit`
+        let (collection_item, _) =
+            database::models::Collection::get_without_projects(collection_id, &pool)
+                .await
+                .unwrap()
+                .unwrap();
+        let new_collection: EditCollection =
+            serde_json::from_value(serde_json::json!({ "title": "Magic mods!" })).unwrap();
+        let user_option = Some(crate::models::users::User {
+            id: user_id.into(),
+            username: "collector".to_string(),
+            name: None,
+            avatar_url: None,
+            bio: None,
+            created: Utc::now(),
+            role: crate::models::users::Role::Developer,
+            badges: crate::models::users::Badges::NONE,
+            payout_data: None,
+            auth_providers: None,
+            email: None,
+            email_verified: None,
+            has_password: None,
+            has_totp: None,
+            github_id: None,
+        });
+        // Title edits don't look anything up in the cache, so the pool is never connected to
+        let redis = deadpool_redis::Config::from_url("redis://localhost")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+
+        let plan = plan_collection_edit(
+            &collection_item,
+            &new_collection,
+            CollectionProjectMode::Merge,
+            &user_option,
+            &web::Data::new(pool.clone()),
+            &redis,
+        )
+        .await
+        .unwrap();
+
+        let edit_span = |step: &'static str| tracing::debug_span!("collection_edit", step);
+        let mut transaction = pool.begin().await.unwrap();
+        write_collection_edit(
+            &collection_item,
+            &new_collection,
+            CollectionProjectMode::Merge,
+            &plan,
+            &user_option,
+            &edit_span,
+            &mut transaction,
+        )
+        .await
+        .unwrap();
+        transaction.commit().await.unwrap();
+
+        let (created, updated): (DateTime<Utc>, DateTime<Utc>) =
+            sqlx::query_as("SELECT created, updated FROM collections WHERE id = $1")
+                .bind(collection_id.0)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+
+        assert!(updated > created);
+    }
+
+    #[test]
+    fn copy_titles_fit_the_title_limits() {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        assert_eq!(copy_title("Magic mods"), "Magic mods (copy)");
+
+        // Each flag is one grapheme made of two characters, which must not be split
+        let title = "\u{1F1E9}\u{1F1EA}".repeat(64);
+        let copy = copy_title(&title);
+        assert!(copy.ends_with(" (copy)"));
+        assert!(copy.graphemes(true).count() <= crate::util::validate::NAME_MAX_GRAPHEMES);
+        assert!(validate_collection_title(&copy).is_ok());
+        assert!(copy
+            .trim_end_matches(" (copy)")
+            .graphemes(true)
+            .all(|x| x == "\u{1F1E9}\u{1F1EA}"));
+    }
+
+    #[test]
+    fn short_descriptions_get_a_warning() {
+        assert!(description_warning("Mods").is_some());
+        assert!(description_warning("   Magic mods      ").is_some());
+        assert!(description_warning("All my favourite magic mods").is_none());
+    }
+
+    #[test]
+    fn description_bounds_match_between_create_and_edit() {
+        for (length, valid) in [
+            (COLLECTION_DESCRIPTION_MIN_LENGTH - 1, false),
+            (COLLECTION_DESCRIPTION_MIN_LENGTH, true),
+            (COLLECTION_DESCRIPTION_MAX_LENGTH, true),
+            (COLLECTION_DESCRIPTION_MAX_LENGTH + 1, false),
+        ] {
+            let description = "a".repeat(length);
+
+            let create = CollectionCreateData {
+                title: "Collection".to_string(),
+                description: description.clone(),
+                slug: None,
+                projects: Vec::new(),
+                categories: Vec::new(),
+            };
+            let edit: EditCollection =
+                serde_json::from_value(serde_json::json!({ "description": description })).unwrap();
+
+            assert_eq!(create.validate().is_ok(), valid, "create at {length}");
+            assert_eq!(edit.validate().is_ok(), valid, "edit at {length}");
+        }
+    }
+
+    #[test]
+    fn imports_are_split_at_the_creation_limit() {
+        for (length, kept) in [
+            (
+                COLLECTION_CREATE_MAX_PROJECTS,
+                COLLECTION_CREATE_MAX_PROJECTS,
+            ),
+            (
+                COLLECTION_CREATE_MAX_PROJECTS + 1,
+                COLLECTION_CREATE_MAX_PROJECTS,
+            ),
+        ] {
+            let projects = (0..length).map(|x| x.to_string()).collect::<Vec<_>>();
+
+            let (resolved, over_limit) = split_import_projects(projects.clone());
+            assert_eq!(resolved, projects[..kept], "kept at {length}");
+            assert_eq!(over_limit, projects[kept..], "skipped at {length}");
+
+            let create = CollectionCreateData {
+                title: "Collection".to_string(),
+                description: "Description".to_string(),
+                slug: None,
+                projects: resolved,
+                categories: Vec::new(),
+            };
+            assert!(create.validate().is_ok(), "create at {}", length);
+        }
+    }
+
+    #[test]
+    fn description_rejects_surrounding_whitespace() {
+        let description = format!("{}  ", "a".repeat(COLLECTION_DESCRIPTION_MAX_LENGTH - 2));
+
+        assert!(validate_collection_description(&description).is_err());
+        assert!(validate_collection_description("  abc  ").is_err());
+    }
+
+    #[test]
+    fn slugs_are_lowercase_url_safe_and_not_reserved() {
+        for (slug, valid) in [
+            ("my-modpack_2", true),
+            ("My-Modpack", false),
+            ("my modpack", false),
+            ("ab", false),
+            ("search", false),
+        ] {
+            let edit: EditCollection =
+                serde_json::from_value(serde_json::json!({ "slug": slug })).unwrap();
+
+            assert_eq!(edit.validate().is_ok(), valid, "{slug}");
+        }
+
+        let edit: EditCollection =
+            serde_json::from_value(serde_json::json!({ "slug": null })).unwrap();
+        assert!(edit.validate().is_ok());
+        assert_eq!(edit.slug, Some(None));
+    }
+
+    #[test]
+    fn project_modes_merge_or_replace_new_projects() {
+        use crate::database::models::ids::ProjectId;
+
+        let existing = [ProjectId(1), ProjectId(2)];
+        let new_projects = vec![ProjectId(3), ProjectId(1)];
+
+        assert_eq!(
+            CollectionProjectMode::Merge.apply(&existing, new_projects.clone()),
+            vec![ProjectId(1), ProjectId(2), ProjectId(3)]
+        );
+        assert_eq!(
+            CollectionProjectMode::Replace.apply(&existing, new_projects),
+            vec![ProjectId(3), ProjectId(1)]
+        );
+        assert!(CollectionProjectMode::Replace
+            .apply(&existing, Vec::new())
+            .is_empty());
+
+        let query: EditCollectionQuery = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(query.project_mode, CollectionProjectMode::Merge);
+    }
+
+    #[test]
+    fn edits_require_permissions_for_changed_fields() {
+        let edit: EditCollection =
+            serde_json::from_value(serde_json::json!({ "new_projects": [] })).unwrap();
+        assert_eq!(
+            edit.required_permissions(CollectionProjectMode::Merge),
+            CollectionPermissions::ADD_PROJECTS
+        );
+        assert_eq!(
+            edit.required_permissions(CollectionProjectMode::Replace),
+            CollectionPermissions::ADD_PROJECTS | CollectionPermissions::REMOVE_PROJECTS
+        );
+
+        let edit: EditCollection =
+            serde_json::from_value(serde_json::json!({ "title": "Collection" })).unwrap();
+        assert_eq!(
+            edit.required_permissions(CollectionProjectMode::Merge),
+            CollectionPermissions::EDIT_METADATA
+        );
+
+        let missing = check_collection_permissions(
+            CollectionPermissions::ADD_PROJECTS,
+            CollectionPermissions::ADD_PROJECTS | CollectionPermissions::REMOVE_PROJECTS,
+        );
+        assert!(
+            matches!(missing, Err(ApiError::Forbidden(message)) if message.contains("REMOVE_PROJECTS"))
+        );
+        assert!(check_collection_permissions(
+            CollectionPermissions::empty(),
+            CollectionPermissions::empty()
+        )
+        .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn oversized_edit_bodies_are_rejected() {
+        let app = actix_web::test::init_service(
+            actix_web::App::new().service(
+                web::resource("/")
+                    .app_data(collection_edit_json_config())
+                    .route(web::patch().to(|_: web::Json<EditCollection>| async {
+                        HttpResponse::NoContent().finish()
+                    })),
+            ),
+        )
+        .await;
+
+        let oversized = serde_json::json!({ "new_projects": vec!["a".repeat(64); 10000] });
+        let request = actix_web::test::TestRequest::patch()
+            .uri("/")
+            .set_json(&oversized)
+            .to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let largest = serde_json::json!({
+            "body": "\u{1F600}".repeat(65536),
+            "new_projects": vec!["a".repeat(64); 64],
+            "ordered_projects": vec!["a".repeat(64); 64],
+        });
+        let request = actix_web::test::TestRequest::patch()
+            .uri("/")
+            .set_json(&largest)
+            .to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn project_mentions_are_parsed_once_each() {
+        assert_eq!(
+            parse_project_mentions("See also @sodium, @Lithium and @sodium.\n@iris"),
+            vec!["sodium", "Lithium", "iris"]
+        );
+        assert!(parse_project_mentions("mail me at me@example.com or @ab").is_empty());
+    }
+
+    #[test]
+    fn pagination_headers_link_neighbouring_pages() {
+        let req = actix_web::test::TestRequest::get()
+            .uri("/v3/collections/search?query=tech&offset=20&limit=10")
+            .to_http_request();
+        let mut response = HttpResponse::Ok();
+        insert_pagination_headers(&mut response, &req, 20, 10, 45);
+        let response = response.finish();
+
+        assert_eq!(response.headers().get("X-Total-Count").unwrap(), "45");
+        assert_eq!(
+            response.headers().get(header::LINK).unwrap(),
+            "</v3/collections/search?query=tech&offset=30&limit=10>; rel=\"next\", \
+             </v3/collections/search?query=tech&offset=10&limit=10>; rel=\"prev\""
+        );
+
+        let mut response = HttpResponse::Ok();
+        insert_pagination_headers(&mut response, &req, 0, 10, 5);
+        assert!(response.finish().headers().get(header::LINK).is_none());
+    }
+
+    #[test]
+    fn title_rejects_zero_width_joiners() {
+        let title = "\u{200D}\u{200D}Collection\u{200D}";
+
+        let create = CollectionCreateData {
+            title: title.to_string(),
+            description: "A collection".to_string(),
+            slug: None,
+            projects: Vec::new(),
+            categories: Vec::new(),
+        };
+        let edit: EditCollection =
+            serde_json::from_value(serde_json::json!({ "title": title })).unwrap();
+
+        assert!(create.validate().is_err());
+        assert!(edit.validate().is_err());
+    }
+
+    #[test]
+    fn parse_collection_id_bad_path() {
+        let err = parse_collection_id("$$$").unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn resolve_collection_id_bad_path() {
+        // Neither an id nor a slug, so it is rejected before the pool ever connects
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/labrinth")
+            .unwrap();
+
+        let err = resolve_collection_id("$$$", &pool).await.unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::auth::checks::{filter_authorized_collections, is_authorized_collection};
+use crate::auth::get_user_from_headers;
+use crate::database;
+use crate::database::models::collection_item;
+use crate::models::collections::{Collection, CollectionProject, CollectionSort, CollectionStatus};
+use crate::models::pats::Scopes;
+use crate::models::projects::ProjectStatus;
+use crate::queue::session::AuthQueue;
+use crate::routes::ApiError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{
+    check_collection_categories, insert_pagination_headers, resolve_collection_id,
+    COLLECTIONS_LIST_MAX_LIMIT,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsRecentQuery {
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecentCollection {
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub project_count: u32,
+    pub owner_username: String,
+}
+
+const DEFAULT_RECENT_COLLECTIONS_LIMIT: u32 = 20;
+
+// Every caller sees the same feed of listed collections, so it is not personalized by auth
+pub async fn collections_recent(
+    web::Query(query): web::Query<CollectionsRecentQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RECENT_COLLECTIONS_LIMIT)
+        .clamp(1, COLLECTIONS_LIST_MAX_LIMIT);
+
+    let collection_ids =
+        database::models::Collection::get_recent(limit as i64, &**pool, &redis).await?;
+    let collections_data =
+        database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+
+    let mut owner_ids = collections_data
+        .iter()
+        .map(|x| x.user_id)
+        .collect::<Vec<_>>();
+    owner_ids.sort_by_key(|x| x.0);
+    owner_ids.dedup();
+    let owners = database::models::User::get_many_ids(&owner_ids, &**pool, &redis).await?;
+
+    let mut collections = collections_data
+        .into_iter()
+        // The cached ids may briefly include collections which have since been hidden
+        .filter(|x| x.status == CollectionStatus::Listed)
+        .filter_map(|collection| {
+            let owner = owners.iter().find(|x| x.id == collection.user_id)?;
+
+            Some(RecentCollection {
+                project_count: collection.projects.len() as u32,
+                owner_username: owner.username.clone(),
+                collection: collection.into(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    collection_item::sort_by_ids(&mut collections, &collection_ids, |x| {
+        x.collection.id.into()
+    });
+
+    Ok(HttpResponse::Ok().json(collections))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionsTrendingQuery {
+    /// The number of days of follows to rank by, defaulting to 7
+    pub days: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TrendingCollection {
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub project_count: u32,
+    pub owner_username: String,
+    /// Follows gained within the requested window
+    pub recent_follows: u32,
+}
+
+const DEFAULT_TRENDING_DAYS: u32 = 7;
+const MAX_TRENDING_DAYS: u32 = 30;
+
+// Ranks by recent follows rather than all-time totals, so rising collections surface. Views
+// are only counted in total, so they only break ties.
+pub async fn collections_trending(
+    web::Query(query): web::Query<CollectionsTrendingQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+) -> Result<HttpResponse, ApiError> {
+    let days = query
+        .days
+        .unwrap_or(DEFAULT_TRENDING_DAYS)
+        .clamp(1, MAX_TRENDING_DAYS);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RECENT_COLLECTIONS_LIMIT)
+        .clamp(1, COLLECTIONS_LIST_MAX_LIMIT);
+
+    let trending =
+        database::models::Collection::get_trending(days as i32, limit as i64, &**pool, &redis)
+            .await?;
+    let collection_ids = trending.iter().map(|x| x.0).collect::<Vec<_>>();
+    let collections_data =
+        database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+
+    let mut owner_ids = collections_data
+        .iter()
+        .map(|x| x.user_id)
+        .collect::<Vec<_>>();
+    owner_ids.sort_by_key(|x| x.0);
+    owner_ids.dedup();
+    let owners = database::models::User::get_many_ids(&owner_ids, &**pool, &redis).await?;
+
+    let mut collections = collections_data
+        .into_iter()
+        // The cached ranking may briefly include collections which have since been hidden
+        .filter(|x| x.status == CollectionStatus::Listed)
+        .filter_map(|collection| {
+            let owner = owners.iter().find(|x| x.id == collection.user_id)?;
+            let recent_follows = trending.iter().find(|x| x.0 == collection.id)?.1;
+
+            Some(TrendingCollection {
+                project_count: collection.projects.len() as u32,
+                owner_username: owner.username.clone(),
+                recent_follows: recent_follows as u32,
+                collection: collection.into(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    collection_item::sort_by_ids(&mut collections, &collection_ids, |x| {
+        x.collection.id.into()
+    });
+
+    Ok(HttpResponse::Ok().json(collections))
+}
+
+pub async fn collection_categories_list(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let categories = database::models::Collection::list_categories(&**pool).await?;
+
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionSearchQuery {
+    /// Substring to match against collection titles
+    pub query: Option<String>,
+    pub status: Option<CollectionStatus>,
+    /// Id or username of the collection owner
+    pub user_id: Option<String>,
+    /// Only match collections tagged with this category
+    pub category: Option<String>,
+    /// One of `created`, `updated`, `follows` or `projects`, defaulting to `updated`.
+    /// Sorting by `follows` requires collection follows to be enabled.
+    pub sort: Option<String>,
+    pub offset: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionSearchResult {
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub follower_count: u32,
+    pub project_count: u32,
+}
+
+/// A page of collection search results, with `X-Total-Count` and `Link` headers like
+/// `CollectionsListPage`
+#[derive(Serialize, Deserialize)]
+pub struct CollectionSearchPage {
+    pub hits: Vec<CollectionSearchResult>,
+    pub offset: u32,
+    pub limit: u32,
+    pub total_hits: i64,
+}
+
+pub async fn collections_search(
+    req: HttpRequest,
+    web::Query(query): web::Query<CollectionSearchQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let owner_id = if let Some(user_id) = &query.user_id {
+        if let Some(owner) = database::models::User::get(user_id, &**pool, &redis).await? {
+            Some(owner.id)
+        } else {
+            return Err(ApiError::NotFound);
+        }
+    } else {
+        None
+    };
+
+    // Only moderators and the owner searching their own collections see anything but listed ones
+    let can_view_hidden = user
+        .as_ref()
+        .map(|x| {
+            x.role.is_mod()
+                || owner_id
+                    .map(|y| crate::models::users::UserId::from(y) == x.id)
+                    .unwrap_or(false)
+        })
+        .unwrap_or(false);
+
+    let statuses = CollectionStatus::iterator()
+        .filter(|x| can_view_hidden || *x == CollectionStatus::Listed)
+        .filter(|x| query.status.map(|y| y == *x).unwrap_or(true))
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>();
+
+    if let Some(category) = &query.category {
+        check_collection_categories(std::slice::from_ref(category), &pool).await?;
+    }
+
+    let sort = match &query.sort {
+        Some(sort) => CollectionSort::from_str(sort).ok_or_else(|| {
+            ApiError::InvalidInput(format!(
+                "Unknown sort '{sort}', expected one of created, updated, follows or projects"
+            ))
+        })?,
+        None => CollectionSort::default(),
+    };
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(COLLECTIONS_LIST_MAX_LIMIT)
+        .clamp(1, COLLECTIONS_LIST_MAX_LIMIT);
+
+    let (collection_ids, total_hits) = database::models::Collection::search(
+        query.query.as_deref().filter(|x| !x.is_empty()),
+        &statuses,
+        owner_id,
+        query.category.as_deref(),
+        sort,
+        offset as i64,
+        limit as i64,
+        &**pool,
+    )
+    .await?;
+
+    let collections_data =
+        database::models::Collection::get_many(&collection_ids, &**pool, &redis).await?;
+    let mut collections = filter_authorized_collections(collections_data, &user, &pool).await?;
+
+    collection_item::sort_by_ids(&mut collections, &collection_ids, |x| x.id.into());
+
+    let hits = collections
+        .into_iter()
+        .map(|collection| CollectionSearchResult {
+            follower_count: collection.follows,
+            project_count: collection.projects.len() as u32,
+            collection,
+        })
+        .collect();
+
+    let mut response = HttpResponse::Ok();
+    insert_pagination_headers(&mut response, &req, offset, limit, total_hits);
+
+    Ok(response.json(CollectionSearchPage {
+        hits,
+        offset,
+        limit,
+        total_hits,
+    }))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionProjectsSearchQuery {
+    /// Substring to match against member project titles and slugs
+    pub query: String,
+    pub offset: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionProjectsSearchPage {
+    pub hits: Vec<CollectionProject>,
+    pub offset: u32,
+    pub limit: u32,
+    pub total_hits: i64,
+}
+
+pub async fn collection_projects_search(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    web::Query(query): web::Query<CollectionProjectsSearchQuery>,
+    pool: web::Data<PgPool>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_data = database::models::Collection::get(id, &**pool, &redis).await?;
+    let user_option = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_READ]),
+    )
+    .await
+    .map(|x| x.1)
+    .ok();
+
+    let Some(collection) = collection_data else {
+        return Err(ApiError::NotFound);
+    };
+
+    if !is_authorized_collection(&collection, &user_option).await? {
+        return Err(ApiError::NotFound);
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(COLLECTIONS_LIST_MAX_LIMIT)
+        .clamp(1, COLLECTIONS_LIST_MAX_LIMIT);
+
+    // Visibility is checked by the search, the same way as filter_authorized_projects does, so
+    // hidden projects aren't counted or matched. Moderators can see all of them.
+    let visible_statuses = (!user_option.as_ref().is_some_and(|x| x.role.is_mod())).then(|| {
+        ProjectStatus::iterator()
+            .filter(|x| !x.is_hidden())
+            .map(|x| x.as_str().to_string())
+            .collect::<Vec<_>>()
+    });
+
+    let (project_ids, total_hits) = database::models::Collection::search_projects(
+        collection.id,
+        &query.query,
+        visible_statuses.as_deref(),
+        user_option.as_ref().map(|x| x.id.into()),
+        offset as i64,
+        limit as i64,
+        &**pool,
+    )
+    .await?;
+
+    let mut projects = database::models::Project::get_many_ids(&project_ids, &**pool, &redis)
+        .await?
+        .into_iter()
+        .map(crate::models::projects::Project::from)
+        .collect::<Vec<_>>();
+
+    collection_item::sort_by_ids(&mut projects, &project_ids, |x| x.id.into());
+
+    Ok(HttpResponse::Ok().json(CollectionProjectsSearchPage {
+        hits: projects.into_iter().map(CollectionProject::from).collect(),
+        offset,
+        limit,
+        total_hits,
+    }))
+}
@@ -0,0 +1,157 @@
+use crate::auth::checks::is_authorized_collection_edit;
+use crate::auth::get_user_from_headers;
+use crate::database;
+use crate::models::collections::CollectionPermissions;
+use crate::models::pats::Scopes;
+use crate::queue::session::AuthQueue;
+use crate::routes::ApiError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use super::{edit_permission_error, resolve_collection_id};
+
+#[derive(Deserialize)]
+pub struct CollectionShare {
+    /// Id or username of the user to share the collection with
+    pub user_id: String,
+    /// What the user may change about the collection. Sharing with a user again replaces their
+    /// permissions, and without any they can only view it.
+    #[serde(default = "CollectionPermissions::empty")]
+    pub permissions: CollectionPermissions,
+}
+
+pub async fn collection_share(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionShare>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    if !is_authorized_collection_edit(&collection_item, &user) {
+        return Err(edit_permission_error());
+    }
+
+    let target = database::models::User::get(&body.user_id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput(format!(
+                "The specified user {} does not exist!",
+                body.user_id
+            ))
+        })?;
+
+    if target.id == collection_item.user_id {
+        return Err(ApiError::InvalidInput(
+            "A collection cannot be shared with its owner!".to_string(),
+        ));
+    }
+
+    if CollectionPermissions::from_bits(body.permissions.bits()).is_none() {
+        return Err(ApiError::InvalidInput(format!(
+            "Unknown collection permissions {}",
+            body.permissions.bits()
+        )));
+    }
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        "
+        INSERT INTO collection_shares (collection_id, user_id, permissions)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (collection_id, user_id) DO UPDATE SET permissions = EXCLUDED.permissions
+        ",
+        collection_item.id as database::models::ids::CollectionId,
+        target.id as database::models::ids::UserId,
+        body.permissions.bits() as i64,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
+
+pub async fn collection_unshare(
+    req: HttpRequest,
+    info: web::Path<(String,)>,
+    pool: web::Data<PgPool>,
+    body: web::Json<CollectionShare>,
+    redis: web::Data<deadpool_redis::Pool>,
+    session_queue: web::Data<AuthQueue>,
+) -> Result<HttpResponse, ApiError> {
+    let user = get_user_from_headers(
+        &req,
+        &**pool,
+        &redis,
+        &session_queue,
+        Some(&[Scopes::COLLECTION_WRITE]),
+    )
+    .await?
+    .1;
+
+    let string = info.into_inner().0;
+    let id = resolve_collection_id(&string, &pool).await?;
+    let collection_item =
+        if let Some(collection) = database::models::Collection::get(id, &**pool, &redis).await? {
+            collection
+        } else {
+            return Err(ApiError::NotFound);
+        };
+
+    if !is_authorized_collection_edit(&collection_item, &user) {
+        return Err(edit_permission_error());
+    }
+
+    let target = database::models::User::get(&body.user_id, &**pool, &redis)
+        .await?
+        .ok_or_else(|| {
+            ApiError::InvalidInput(format!(
+                "The specified user {} does not exist!",
+                body.user_id
+            ))
+        })?;
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        "
+        DELETE FROM collection_shares
+        WHERE collection_id = $1 AND user_id = $2
+        ",
+        collection_item.id as database::models::ids::CollectionId,
+        target.id as database::models::ids::UserId,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    database::models::Collection::clear_cache(collection_item.id, &redis).await?;
+
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().body(""))
+}
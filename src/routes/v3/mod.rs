@@ -3,10 +3,16 @@ use crate::util::cors::default_cors;
 use actix_web::{web, HttpResponse};
 use serde_json::json;
 
+pub mod collections;
+
 pub fn config(cfg: &mut web::ServiceConfig) {
+    // Registered ahead of the v3 scope, which would otherwise apply the default CORS to them
+    cfg.configure(collections::read_config);
     cfg.service(
         web::scope("v3")
+            .wrap_fn(crate::util::metrics::record_request)
             .wrap(default_cors())
+            .configure(collections::config)
             .route("", web::get().to(hello_world)),
     );
 }
@@ -1,3 +1,4 @@
+use crate::util::env::parse_strings_from_var;
 use actix_cors::Cors;
 
 pub fn default_cors() -> Cors {
@@ -5,6 +6,26 @@ pub fn default_cors() -> Cors {
         .allow_any_origin()
         .allow_any_header()
         .allow_any_method()
+        // Pagination headers of collection listings
+        .expose_headers(["X-Total-Count", "Link"])
         .max_age(3600)
         .send_wildcard()
 }
+
+/// CORS for the public collection reads, which are embedded on other sites. The origins allowed
+/// are read from `COLLECTIONS_ALLOWED_ORIGINS`, with `*` allowing any, and any origin is allowed
+/// when it is unset.
+pub fn collections_read_cors() -> Cors {
+    let allowed_origins = parse_strings_from_var("COLLECTIONS_ALLOWED_ORIGINS");
+
+    Cors::default()
+        .allowed_origin_fn(move |origin, _req_head| match &allowed_origins {
+            Some(allowed_origins) => allowed_origins
+                .iter()
+                .any(|x| x == "*" || x.as_bytes() == origin.as_bytes()),
+            None => true,
+        })
+        .allowed_methods(vec!["GET"])
+        .allow_any_header()
+        .max_age(3600)
+}
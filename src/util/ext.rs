@@ -5,6 +5,7 @@ pub fn get_image_content_type(extension: &str) -> Option<&'static str> {
         "jpeg" | "jpg" => Some("image/jpeg"),
         "png" => Some("image/png"),
         "webp" => Some("image/webp"),
+        "avif" => Some("image/avif"),
         _ => None,
     }
 }
@@ -16,6 +17,7 @@ pub fn get_image_ext(content_type: &str) -> Option<&'static str> {
         "image/jpeg" => Some("jpg"),
         "image/png" => Some("png"),
         "image/webp" => Some("webp"),
+        "image/avif" => Some("avif"),
         _ => None,
     }
 }
@@ -1,6 +1,8 @@
+use bytes::Bytes;
 use color_thief::ColorFormat;
 use image::imageops::FilterType;
-use image::{EncodableLayout, ImageError};
+use image::{EncodableLayout, ImageError, ImageFormat};
+use std::io::Cursor;
 
 use crate::database;
 use crate::database::models::image_item;
@@ -19,6 +21,89 @@ pub fn get_color_from_img(data: &[u8]) -> Result<Option<u32>, ImageError> {
     Ok(color)
 }
 
+/// Icons larger than this in either dimension are rejected
+pub const MAX_ICON_DIMENSION: u32 = 2048;
+/// Icons larger than this in either dimension are downscaled before storage
+pub const ICON_RESIZE_DIMENSION: u32 = 512;
+
+// Validates the dimensions of an uploaded icon and downscales it if needed.
+// The dimensions are read from the image header so oversized images are never fully decoded.
+pub fn process_icon(data: Bytes, ext: &str) -> Result<Bytes, ApiError> {
+    let format = ImageFormat::from_extension(ext)
+        .ok_or_else(|| ApiError::InvalidInput(format!("Invalid format for icon: {ext}")))?;
+
+    if format == ImageFormat::Avif && !cfg!(feature = "avif") {
+        return Err(ApiError::InvalidInput(
+            "AVIF icons are not supported by this server".to_string(),
+        ));
+    }
+
+    // The extension decides the stored content type, so it has to match what the data actually is
+    let actual_format = image::guess_format(&data)
+        .map_err(|_| ApiError::InvalidInput("Icon is not a recognized image".to_string()))?;
+    if actual_format != format {
+        return Err(ApiError::InvalidInput(format!(
+            "Icon was uploaded as {ext}, but is a {actual_format:?} image"
+        )));
+    }
+
+    let (width, height) =
+        image::io::Reader::with_format(Cursor::new(&data), format).into_dimensions()?;
+
+    if width > MAX_ICON_DIMENSION || height > MAX_ICON_DIMENSION {
+        return Err(ApiError::InvalidInput(format!(
+            "Icons must be at most {MAX_ICON_DIMENSION}x{MAX_ICON_DIMENSION} pixels, got {width}x{height}"
+        )));
+    }
+
+    if width <= ICON_RESIZE_DIMENSION && height <= ICON_RESIZE_DIMENSION {
+        return Ok(data);
+    }
+
+    let image = image::load_from_memory_with_format(&data, format)?.resize(
+        ICON_RESIZE_DIMENSION,
+        ICON_RESIZE_DIMENSION,
+        FilterType::Lanczos3,
+    );
+
+    let mut output = Cursor::new(Vec::new());
+    image.write_to(&mut output, format)?;
+
+    Ok(Bytes::from(output.into_inner()))
+}
+
+/// The width and height of each icon in a composite
+pub const COMPOSITE_TILE_DIMENSION: u32 = 128;
+
+// Arranges up to four icons in a 2x2 grid, filling in order from the top left.
+// Icons which cannot be decoded leave their tile transparent.
+pub fn composite_icons(icons: &[Bytes]) -> Result<Vec<u8>, ImageError> {
+    let mut composite =
+        image::RgbaImage::new(COMPOSITE_TILE_DIMENSION * 2, COMPOSITE_TILE_DIMENSION * 2);
+
+    for (index, icon) in icons.iter().take(4).enumerate() {
+        let Ok(tile) = image::load_from_memory(icon) else {
+            continue;
+        };
+        let tile = tile
+            .resize_to_fill(
+                COMPOSITE_TILE_DIMENSION,
+                COMPOSITE_TILE_DIMENSION,
+                FilterType::Lanczos3,
+            )
+            .to_rgba8();
+
+        let x = (index as u32 % 2) * COMPOSITE_TILE_DIMENSION;
+        let y = (index as u32 / 2) * COMPOSITE_TILE_DIMENSION;
+        image::imageops::overlay(&mut composite, &tile, x as i64, y as i64);
+    }
+
+    let mut output = Cursor::new(Vec::new());
+    composite.write_to(&mut output, ImageFormat::Png)?;
+
+    Ok(output.into_inner())
+}
+
 // check changes to associated images
 // if they no longer exist in the String list, delete them
 // Eg: if description is modified and no longer contains a link to an iamge
@@ -47,3 +132,73 @@ pub async fn delete_unused_images(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    // A 1x1 lossless WebP image
+    const WEBP_1X1: &str = "UklGRhoAAABXRUJQVlA4TA0AAAAvAAAAEAcQERGIiP4HAA==";
+
+    fn webp_bytes() -> Bytes {
+        Bytes::from(
+            base64::engine::general_purpose::STANDARD
+                .decode(WEBP_1X1)
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn process_icon_accepts_webp() {
+        let bytes = webp_bytes();
+        let processed = process_icon(bytes.clone(), "webp").unwrap();
+
+        assert_eq!(processed, bytes);
+    }
+
+    #[test]
+    fn get_color_from_webp() {
+        let processed = process_icon(webp_bytes(), "webp").unwrap();
+
+        assert!(get_color_from_img(&processed).is_ok());
+    }
+
+    #[test]
+    fn composite_icons_fills_grid() {
+        let composite = composite_icons(&[webp_bytes(), Bytes::new(), webp_bytes()]).unwrap();
+        let image = image::load_from_memory(&composite).unwrap();
+
+        assert_eq!(image.width(), COMPOSITE_TILE_DIMENSION * 2);
+        assert_eq!(image.height(), COMPOSITE_TILE_DIMENSION * 2);
+    }
+
+    #[test]
+    fn process_icon_rejects_mismatched_extension() {
+        for ext in ["png", "jpg", "gif"] {
+            assert!(matches!(
+                process_icon(webp_bytes(), ext),
+                Err(ApiError::InvalidInput(message)) if message.contains("WebP")
+            ));
+        }
+
+        let mut png = Cursor::new(Vec::new());
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        let png = Bytes::from(png.into_inner());
+        assert!(process_icon(png.clone(), "jpg").is_err());
+        assert!(process_icon(png, "png").is_ok());
+
+        assert!(process_icon(Bytes::from_static(b"not an image"), "webp").is_err());
+    }
+
+    #[cfg(not(feature = "avif"))]
+    #[test]
+    fn process_icon_rejects_avif_without_feature() {
+        assert!(matches!(
+            process_icon(Bytes::new(), "avif"),
+            Err(ApiError::InvalidInput(..))
+        ));
+    }
+}
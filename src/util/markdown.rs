@@ -0,0 +1,40 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// Renders user supplied markdown to HTML which is safe to embed in a page.
+/// Raw HTML in the markdown is passed through the sanitizer along with the rendered output,
+/// so scripts, event handlers and other unsafe markup never reach the client.
+pub fn render_markdown(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+
+    ammonia::Builder::default()
+        .link_rel(Some("noopener noreferrer ugc"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_markdown() {
+        assert!(render_markdown("**bold** text").contains("<p><strong>bold</strong> text</p>"));
+    }
+
+    #[test]
+    fn strips_scripts_and_event_handlers() {
+        let html = render_markdown(
+            "<script>alert(1)</script>\n\n<img src=\"x.png\" onerror=\"alert(1)\">\n\n[link](javascript:alert(1))",
+        );
+
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript:"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Prometheus metrics of the collection endpoints and their background tasks, exported at
+//! `/metrics`
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::future::Future;
+
+lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::new();
+    /// Handled requests, labelled by route and response status code
+    pub static ref COLLECTION_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new(
+            "labrinth_collection_requests_total",
+            "Requests handled by the collection routes"
+        ),
+        &["route", "status"],
+    ));
+    /// Time spent handling requests, labelled by route
+    pub static ref COLLECTION_REQUEST_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "labrinth_collection_request_duration_seconds",
+            "Time spent handling requests of the collection routes"
+        ),
+        &["route"],
+    ));
+    /// Time spent reading collections from Redis or Postgres, labelled by the backend
+    pub static ref COLLECTION_BACKEND_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "labrinth_collection_backend_duration_seconds",
+            "Time spent reading collections from their backing stores"
+        ),
+        &["backend"],
+    ));
+    /// CDN objects which are no longer used but couldn't be deleted yet
+    pub static ref PENDING_CDN_DELETIONS: IntGauge = register(IntGauge::new(
+        "labrinth_pending_cdn_deletions",
+        "CDN objects waiting to be deleted after a failed deletion"
+    ));
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(
+    collector: Result<T, prometheus::Error>,
+) -> T {
+    let collector = collector.expect("Invalid metric definition");
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("Metric registered twice");
+
+    collector
+}
+
+// Routes are labelled by their method and pattern, such as `PATCH /v3/collection/{id}`, so ids
+// don't create a label per collection. Unmatched requests share a single label.
+fn route_label(req: &actix_web::HttpRequest) -> String {
+    format!(
+        "{} {}",
+        req.method(),
+        req.match_pattern()
+            .unwrap_or_else(|| "unmatched".to_string())
+    )
+}
+
+/// Records a request to a collection route once its response is ready
+pub fn record_request<S, B>(
+    req: ServiceRequest,
+    service: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let start = std::time::Instant::now();
+    let response = service.call(req);
+
+    async move {
+        let response = response.await?;
+
+        let route = route_label(response.request());
+        COLLECTION_REQUEST_DURATION
+            .with_label_values(&[&route])
+            .observe(start.elapsed().as_secs_f64());
+        COLLECTION_REQUESTS
+            .with_label_values(&[&route, response.status().as_str()])
+            .inc();
+
+        Ok(response)
+    }
+}
+
+/// Encodes all metrics in the Prometheus text format
+pub fn gather() -> Result<String, prometheus::Error> {
+    // Metrics are registered when first used, so make sure unused ones are exported as well
+    lazy_static::initialize(&COLLECTION_REQUESTS);
+    lazy_static::initialize(&COLLECTION_REQUEST_DURATION);
+    lazy_static::initialize(&COLLECTION_BACKEND_DURATION);
+    lazy_static::initialize(&PENDING_CDN_DELETIONS);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
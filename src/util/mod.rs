@@ -4,6 +4,9 @@ pub mod env;
 pub mod ext;
 pub mod guards;
 pub mod img;
+pub mod markdown;
+pub mod metrics;
+pub mod remote;
 pub mod routes;
 pub mod validate;
 pub mod webhook;
@@ -0,0 +1,206 @@
+use crate::routes::ApiError;
+use bytes::{Bytes, BytesMut};
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use url::{Host, Url};
+
+const MAX_REDIRECTS: usize = 3;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A file fetched from a remote URL
+pub struct RemoteFile {
+    pub bytes: Bytes,
+    /// The media type the server sent, without parameters
+    pub content_type: Option<String>,
+    /// The URL the file was fetched from after following redirects
+    pub url: Url,
+}
+
+/// Whether an address is reachable on the public internet. Fetching anything else on behalf of
+/// a user could reach services on the internal network.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // "This network" 0.0.0.0/8 and shared address space 100.64.0.0/10
+                || octets[0] == 0
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+
+            let first = ip.segments()[0];
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7 and link local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+// Resolves the host of an https URL, failing unless every address it resolves to is public
+async fn resolve_public_addr(url: &Url) -> Result<SocketAddr, ApiError> {
+    if url.scheme() != "https" {
+        return Err(ApiError::InvalidInput(
+            "Only https URLs can be fetched".to_string(),
+        ));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        Some(Host::Domain(domain)) => {
+            let domain = domain.to_string();
+
+            actix_web::web::block(move || {
+                (domain.as_str(), port)
+                    .to_socket_addrs()
+                    .map(|x| x.collect::<Vec<_>>())
+            })
+            .await
+            .ok()
+            .and_then(|x| x.ok())
+            .ok_or_else(|| {
+                ApiError::InvalidInput(format!("The host of {url} could not be resolved"))
+            })?
+        }
+        None => {
+            return Err(ApiError::InvalidInput(format!("{url} has no host")));
+        }
+    };
+
+    match addrs.first() {
+        Some(addr) if addrs.iter().all(|x| is_public_ip(x.ip())) => Ok(*addr),
+        _ => Err(ApiError::InvalidInput(format!(
+            "{url} does not point to a public address"
+        ))),
+    }
+}
+
+/// Fetches a file from a public https URL. Redirects are followed up to a limit as long as they
+/// stay on public addresses, and the body is limited to `max_size` bytes.
+pub async fn fetch_public_file(
+    url: &str,
+    max_size: usize,
+    size_err_msg: &'static str,
+) -> Result<RemoteFile, ApiError> {
+    let mut url =
+        Url::parse(url).map_err(|_| ApiError::InvalidInput(format!("Invalid URL: {url}")))?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let addr = resolve_public_addr(&url).await?;
+        let fetch_error =
+            |err: reqwest::Error| ApiError::InvalidInput(format!("Unable to fetch {url}: {err}"));
+
+        // The client only connects to the address which was checked, so the host can't be
+        // resolved again to an internal address
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(FETCH_TIMEOUT)
+            .resolve(url.host_str().unwrap_or_default(), addr)
+            .build()
+            .map_err(fetch_error)?;
+
+        let mut response = client.get(url.clone()).send().await.map_err(fetch_error)?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|x| x.to_str().ok())
+                .ok_or_else(|| {
+                    ApiError::InvalidInput(format!("{url} redirects without a location"))
+                })?;
+            url = url.join(location).map_err(|_| {
+                ApiError::InvalidInput(format!("{url} redirects to an invalid URL"))
+            })?;
+
+            continue;
+        }
+
+        if !response.status().is_success() {
+            return Err(ApiError::InvalidInput(format!(
+                "Fetching {url} failed with status {}",
+                response.status()
+            )));
+        }
+
+        if response
+            .content_length()
+            .is_some_and(|x| x > max_size as u64)
+        {
+            return Err(ApiError::InvalidInput(size_err_msg.to_string()));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.split(';').next())
+            .map(|x| x.trim().to_lowercase());
+
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = response.chunk().await.map_err(fetch_error)? {
+            if bytes.len() + chunk.len() > max_size {
+                return Err(ApiError::InvalidInput(size_err_msg.to_string()));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        return Ok(RemoteFile {
+            bytes: bytes.freeze(),
+            content_type,
+            url,
+        });
+    }
+
+    Err(ApiError::InvalidInput(format!(
+        "Fetching {url} took more than {MAX_REDIRECTS} redirects"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
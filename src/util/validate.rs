@@ -5,6 +5,7 @@ use validator::{ValidationErrors, ValidationErrorsKind};
 
 lazy_static! {
     pub static ref RE_URL_SAFE: Regex = Regex::new(r#"^[a-zA-Z0-9!@$()`.+,_"-]*$"#).unwrap();
+    pub static ref RE_LOWERCASE_SLUG: Regex = Regex::new(r"^[a-z0-9_-]*$").unwrap();
 }
 
 //TODO: In order to ensure readability, only the first error is printed, this may need to be expanded on in the future!
@@ -91,16 +92,58 @@ pub fn validate_url(value: &str) -> Result<(), validator::ValidationError> {
     Ok(())
 }
 
+/// The most grapheme clusters a name may have, so the limit matches what users see
+pub const NAME_MAX_GRAPHEMES: usize = 64;
+/// The most characters a name may have, which keeps it within the database columns
+pub const NAME_MAX_CHARS: usize = 255;
+
 pub fn validate_name(value: &str) -> Result<(), validator::ValidationError> {
+    use unicode_segmentation::UnicodeSegmentation;
+
     if value.trim().is_empty() {
         return Err(validator::ValidationError::new(
             "Name cannot contain only whitespace.",
         ));
     }
 
+    if value.graphemes(true).count() > NAME_MAX_GRAPHEMES || value.chars().count() > NAME_MAX_CHARS
+    {
+        return Err(validator::ValidationError::new("length"));
+    }
+
+    Ok(())
+}
+
+// Characters which render as nothing, and so must not pad user-facing text
+fn is_invisible(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Rejects text with C0 or C1 control characters, or with whitespace or zero-width characters
+/// at either end. Such text breaks the rendering of single-line fields like titles.
+pub fn validate_display_text(value: &str) -> Result<(), validator::ValidationError> {
+    if value.chars().any(char::is_control) {
+        return Err(validator::ValidationError::new(
+            "Text cannot contain control characters.",
+        ));
+    }
+
+    if value.starts_with(is_invisible) || value.ends_with(is_invisible) {
+        return Err(validator::ValidationError::new(
+            "Text cannot start or end with whitespace.",
+        ));
+    }
+
     Ok(())
 }
 
+/// Normalizes user-facing text to NFC before it is stored
+pub fn normalize_text(value: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    value.nfc().collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -116,4 +159,49 @@ mod tests {
         let result = validate_name("  ");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn validate_name_counts_flag_emoji_as_one() {
+        // Each flag is two regional indicator characters
+        let flags = "\u{1F1FA}\u{1F1F8}".repeat(NAME_MAX_GRAPHEMES);
+        assert!(validate_name(&flags).is_ok());
+        assert!(validate_name(&format!("{flags}\u{1F1EB}\u{1F1F7}")).is_err());
+    }
+
+    #[test]
+    fn validate_name_counts_combining_diacritics_with_their_base() {
+        let accented = "e\u{0301}".repeat(NAME_MAX_GRAPHEMES);
+        assert!(validate_name(&accented).is_ok());
+        assert!(validate_name(&format!("{accented}e")).is_err());
+
+        // Stacked marks stay one grapheme each, but may not overrun the column
+        let stacked = format!("e{}", "\u{0301}".repeat(NAME_MAX_CHARS));
+        assert!(validate_name(&stacked).is_err());
+    }
+
+    #[test]
+    fn validate_display_text_rejects_zero_width_padding() {
+        assert!(validate_display_text("\u{200D}\u{200D}My Collection\u{200D}").is_err());
+        assert!(validate_display_text("\u{200D}\u{200D}\u{200D}").is_err());
+    }
+
+    #[test]
+    fn validate_display_text_allows_joined_emoji() {
+        assert!(
+            validate_display_text("Family \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} mods")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn validate_display_text_rejects_control_characters() {
+        assert!(validate_display_text("My\u{0007}Collection").is_err());
+        assert!(validate_display_text("My\u{0085}Collection").is_err());
+        assert!(validate_display_text(" My Collection").is_err());
+    }
+
+    #[test]
+    fn normalize_text_composes() {
+        assert_eq!(normalize_text("Cafe\u{0301}"), "Caf\u{00E9}");
+    }
 }